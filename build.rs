@@ -115,8 +115,10 @@ fn generate_manpages(out_dir: PathBuf) -> std::io::Result<()> {
     generate_command_manpage(crunchy_cli_core::Cli::command(), &out_dir, "")?;
     generate_command_manpage(crunchy_cli_core::Archive::command(), &out_dir, "archive")?;
     generate_command_manpage(crunchy_cli_core::Download::command(), &out_dir, "download")?;
+    generate_command_manpage(crunchy_cli_core::Info::command(), &out_dir, "info")?;
     generate_command_manpage(crunchy_cli_core::Login::command(), &out_dir, "login")?;
     generate_command_manpage(crunchy_cli_core::Search::command(), &out_dir, "search")?;
+    generate_command_manpage(crunchy_cli_core::Mux::command(), &out_dir, "mux")?;
 
     Ok(())
 }
@@ -0,0 +1,122 @@
+use crate::utils::clap::clap_parse_resolution;
+use anyhow::{bail, Result};
+use crunchyroll_rs::media::Resolution;
+use crunchyroll_rs::Locale;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// A single `[[job]]` entry of a batch file. Every field besides `url` overrides the respective
+/// cli argument for this job only.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct BatchJob {
+    pub(crate) url: String,
+    pub(crate) resolution: Option<Resolution>,
+    pub(crate) audio: Option<Vec<Locale>>,
+    pub(crate) subtitle: Option<Vec<Locale>>,
+    pub(crate) output: Option<String>,
+    pub(crate) directory: Option<PathBuf>,
+}
+
+/// Read a batch file. The file is in toml format and contains a `[[job]]` table for every url to
+/// archive, e.g.
+/// ```toml
+/// [[job]]
+/// url = "https://www.crunchyroll.com/series/GY8VEQ95Y/darling-in-the-franxx"
+/// resolution = "720p"
+/// audio = ["ja-JP", "en-US"]
+/// directory = "darling"
+/// ```
+pub(crate) fn read_batch_file<P: AsRef<Path>>(path: P) -> Result<Vec<BatchJob>> {
+    let path = path.as_ref();
+    let table = match fs::read_to_string(path)?.parse::<Table>() {
+        Ok(table) => table,
+        Err(e) => bail!("Batch file '{}' is invalid: {}", path.to_string_lossy(), e),
+    };
+
+    for key in table.keys() {
+        if key != "job" {
+            bail!(
+                "Invalid key '{}' in batch file '{}', only '[[job]]' tables are allowed",
+                key,
+                path.to_string_lossy()
+            )
+        }
+    }
+    let Some(entries) = table.get("job") else {
+        bail!(
+            "Batch file '{}' doesn't contain any '[[job]]' table",
+            path.to_string_lossy()
+        )
+    };
+    let Some(entries) = entries.as_array() else {
+        bail!(
+            "'job' in batch file '{}' must be a '[[job]]' table array",
+            path.to_string_lossy()
+        )
+    };
+
+    let mut jobs = vec![];
+    for (i, entry) in entries.iter().enumerate() {
+        let error_prefix = format!("Job {} in batch file '{}'", i + 1, path.to_string_lossy());
+        let Some(entry) = entry.as_table() else {
+            bail!("{} is not a table", error_prefix)
+        };
+
+        let mut job = BatchJob::default();
+        for (key, value) in entry {
+            match key.as_str() {
+                "url" => job.url = value_to_string(&error_prefix, key, value)?,
+                "resolution" => {
+                    let resolution = value_to_string(&error_prefix, key, value)?;
+                    match clap_parse_resolution(&resolution) {
+                        Ok(resolution) => job.resolution = Some(resolution),
+                        Err(e) => bail!("{} has an invalid resolution: {}", error_prefix, e),
+                    }
+                }
+                "audio" => job.audio = Some(value_to_locales(&error_prefix, key, value)?),
+                "subtitle" => job.subtitle = Some(value_to_locales(&error_prefix, key, value)?),
+                "output" => job.output = Some(value_to_string(&error_prefix, key, value)?),
+                "directory" => {
+                    job.directory = Some(PathBuf::from(value_to_string(&error_prefix, key, value)?))
+                }
+                _ => bail!(
+                    "{} has the unknown key '{}'. Valid keys are 'url', 'resolution', 'audio', 'subtitle', 'output' and 'directory'",
+                    error_prefix,
+                    key
+                ),
+            }
+        }
+        if job.url.is_empty() {
+            bail!("{} has no 'url'", error_prefix)
+        }
+        jobs.push(job)
+    }
+
+    Ok(jobs)
+}
+
+fn value_to_string(error_prefix: &str, key: &str, value: &Value) -> Result<String> {
+    match value.as_str() {
+        Some(value) => Ok(value.to_string()),
+        None => bail!("{}: '{}' must be a string", error_prefix, key),
+    }
+}
+
+/// Locales can either be given as single string or as array of strings.
+fn value_to_locales(error_prefix: &str, key: &str, value: &Value) -> Result<Vec<Locale>> {
+    if let Some(locale) = value.as_str() {
+        return Ok(vec![Locale::from(locale.to_string())]);
+    }
+    let Some(locales) = value.as_array() else {
+        bail!(
+            "{}: '{}' must be a string or an array of strings",
+            error_prefix,
+            key
+        )
+    };
+    locales
+        .iter()
+        .map(|l| value_to_string(error_prefix, key, l).map(Locale::from))
+        .collect()
+}
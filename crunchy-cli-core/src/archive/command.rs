@@ -1,28 +1,52 @@
+use crate::archive::batch::read_batch_file;
+use crate::utils::cache::MetadataCache;
+use crate::utils::concat::PartCollector;
 use crate::utils::context::Context;
 use crate::utils::download::{
-    DownloadBuilder, DownloadFormat, DownloadFormatMetadata, MergeBehavior,
+    DownloadBuilder, DownloadFormat, DownloadFormatMetadata, DownloadStats, MergeBehavior,
+    SegmentError,
 };
 use crate::utils::ffmpeg::FFmpegPreset;
 use crate::utils::filter::{Filter, FilterMediaScope};
-use crate::utils::format::{Format, SingleFormat};
-use crate::utils::locale::{all_locale_in_locales, resolve_locales, LanguageTagging};
+use crate::utils::fmt::format_size;
+use crate::utils::format::{
+    check_path_template, report_skipped, Format, SingleFormat, UnavailableError,
+    PLEX_OUTPUT_TEMPLATE,
+};
+use crate::utils::locale::{all_locale_in_locales, resolve_locales, LanguageTagging, LocaleMap};
 use crate::utils::log::progress;
-use crate::utils::os::{free_file, has_ffmpeg, is_special_file};
-use crate::utils::parse::parse_url;
-use crate::utils::video::stream_data_from_stream;
+use crate::utils::manifest::dump_manifest;
+use crate::utils::os::{
+    ffmpeg_path, font_directory, free_file, has_ffmpeg, is_existing_file, is_special_file,
+    should_overwrite,
+};
+use crate::utils::parse::{episodes_to_url_filter, parse_url, split_url_output, UrlFilter};
+use crate::utils::sidecar::{
+    absolute_path, write_description, write_metadata_json, write_nfo, write_series_description,
+    Playlist, StreamMetadata,
+};
+use crate::utils::video::{stream_data_from_stream, BEST_RESOLUTION};
 use crate::Execute;
 use anyhow::bail;
 use anyhow::Result;
-use chrono::Duration;
+use chrono::{Duration, TimeDelta};
+use crunchyroll_rs::list::WatchlistOptions;
 use crunchyroll_rs::media::{Resolution, Subtitle};
-use crunchyroll_rs::Locale;
-use log::{debug, warn};
+use crunchyroll_rs::{Locale, MediaCollection};
+use indicatif::MultiProgress;
+use log::{debug, info, warn};
 use regex::Regex;
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::future::Future;
 use std::iter::zip;
+use std::mem;
 use std::ops::Sub;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+use tokio::task::JoinSet;
 
 #[derive(Clone, Debug, clap::Parser)]
 #[clap(about = "Archive a video")]
@@ -56,6 +80,7 @@ pub struct Archive {
       {height}                   → Height of the video\n  \
       {season_number}            → Number of the season\n  \
       {episode_number}           → Number of the episode\n  \
+      {episode_number_padded}    → Like '{episode_number}' but only integer numbers are zero padded\n  \
       {relative_episode_number}  → Number of the episode relative to its season\n  \
       {sequence_number}          → Like '{episode_number}' but without possible non-number characters\n  \
       {relative_sequence_number} → Like '{relative_episode_number}' but with support for episode 0's and .5's\n  \
@@ -72,21 +97,90 @@ pub struct Archive {
     If not set, the '-o'/'--output' flag will be used as name template")]
     #[arg(long)]
     pub(crate) output_specials: Option<String>,
+    #[arg(help = "Name of the folder the output file is stored in")]
+    #[arg(long_help = "Name of the folder the output file is stored in. \
+    The folder is put in front of the '-o'/'--output' (and '--output-specials') template, so the same pattern as in '-o'/'--output' can be used (e.g. '{series_name}/Season {season_number}'). \
+    Useful to split a series into multiple season folders like media servers expect it")]
+    #[arg(long)]
+    pub(crate) season_folder: Option<String>,
+    #[arg(
+        help = "Name of the output file, without any folder. Overwrites the '-o'/'--output' template"
+    )]
+    #[arg(long_help = "Name of the output file, without any folder. \
+    If set, it is used instead of the '-o'/'--output' template and combined with '--season-folder' as folder. \
+    Supports the same pattern as '-o'/'--output'")]
+    #[arg(long)]
+    pub(crate) episode_file: Option<String>,
 
     #[arg(help = "Sanitize the output file for use with all operating systems. \
     This option only affects template options and not static characters.")]
     #[arg(long, default_value_t = false)]
     pub(crate) universal_output: bool,
+    #[arg(help = "Use the folder and file structure Plex expects for series")]
+    #[arg(
+        long_help = "Use the folder and file structure Plex expects for series: '{series_name}/Season {season_number}/{series_name} - S{season_number}E{episode_number} - {title}.mkv'. \
+    Missing folders are created. \
+    Implies '--universal-output', so that the names are safe on all filesystems"
+    )]
+    #[arg(long, default_value_t = false)]
+    #[arg(conflicts_with_all = ["output", "season_folder", "episode_file"])]
+    pub(crate) plex: bool,
+    #[arg(
+        help = "Use the episode number exactly as Crunchyroll provides it for '{episode_number}'"
+    )]
+    #[arg(
+        long_help = "Use the episode number exactly as Crunchyroll provides it for '{episode_number}' instead of zero padding it. \
+    Crunchyroll episode numbers are not always numeric (e.g. '5.5' or 'SP1'), but are padded like numbers to at least two characters otherwise. \
+    Use '{episode_number_padded}' if integer episode numbers should still be padded"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) episode_naming_from_metadata: bool,
 
     #[arg(help = "Video resolution")]
     #[arg(long_help = "The video resolution. \
     Can either be specified via the pixels (e.g. 1920x1080), the abbreviation for pixels (e.g. 1080p) or 'common-use' words (e.g. best). \
     Specifying the exact pixels is not recommended, use one of the other options instead. \
     Crunchyroll let you choose the quality with pixel abbreviation on their clients, so you might be already familiar with the available options. \
-    The available common-use words are 'best' (choose the best resolution available) and 'worst' (worst resolution available)")]
+    The available common-use words are 'best' (choose the best resolution available) and 'worst' (worst resolution available). \
+    If the specified resolution is not available, the nearest lower resolution is used")]
     #[arg(short, long, default_value = "best")]
     #[arg(value_parser = crate::utils::clap::clap_parse_resolution)]
     pub(crate) resolution: Resolution,
+    #[arg(help = "Minimal bitrate of the video stream. Must be in format of <number>[k|m]")]
+    #[arg(
+        long_help = "Minimal bitrate of the video stream. Must be in format of <number>[k|m] (e.g. 2000k or 2m). \
+    Video streams with a lower bitrate are never chosen, even if `--resolution` falls back to a lower resolution. \
+    If no video stream meets the bitrate, the archive fails"
+    )]
+    #[arg(long, value_parser = crate::utils::clap::clap_parse_bitrate)]
+    pub(crate) min_bitrate: Option<u64>,
+    #[arg(
+        help = "Behavior if an audio locale is not available in the chosen resolution. Valid behaviors are 'strict' and 'per-track'"
+    )]
+    #[arg(
+        long_help = "Behavior if an audio locale (besides the first one) is not available in the chosen resolution (or with the chosen minimal bitrate). \
+    'strict' fails the archive (default). \
+    'per-track' uses the best available resolution of this audio locale instead, the video of the first audio locale stays untouched"
+    )]
+    #[arg(long, default_value = "strict", value_parser = ["strict", "per-track"])]
+    pub(crate) quality_fallback: String,
+    #[arg(help = "Maximal estimated file size of a video. Must be in format of <number>[k|m|g]")]
+    #[arg(
+        long_help = "Maximal estimated file size of a video. Must be in format of <number>[k|m|g] (e.g. 700m or 1.5g). \
+    The size is estimated from the bandwidth of the chosen streams before anything is downloaded. \
+    What happens with videos which exceed the size is set via `--max-filesize-policy`. \
+    Combine it with `--resolution` to choose a lower resolution for long videos"
+    )]
+    #[arg(long, value_parser = crate::utils::clap::clap_parse_filesize)]
+    pub(crate) max_filesize: Option<u64>,
+    #[arg(
+        help = "Behavior if a video exceeds `--max-filesize`. Valid behaviors are 'skip' and 'error'"
+    )]
+    #[arg(long_help = "Behavior if a video exceeds `--max-filesize`. \
+    'skip' skips the video and continues with the next one (default). \
+    'error' fails the archive")]
+    #[arg(long, default_value = "skip", value_parser = ["skip", "error"])]
+    pub(crate) max_filesize_policy: String,
 
     #[arg(
         help = "Sets the behavior of the stream merging. Valid behaviors are 'auto', 'sync', 'audio' and 'video'"
@@ -114,6 +208,26 @@ pub struct Archive {
     )]
     #[arg(long, default_value_t = 4)]
     pub(crate) merge_sync_precision: u32,
+    #[arg(help = "Drop audio tracks whose language is already present in the output")]
+    #[arg(
+        long_help = "Drop audio tracks whose language is already present in the output, e.g. if multiple merged videos have japanese audio. \
+    Only the audio with the highest bitrate is kept. \
+    Every video keeps at least one audio track, as it's required to sync the videos"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) dedupe_audio: bool,
+    #[arg(
+        help = "Only use the videos of these versions (audio languages) as separate video tracks. Can be used multiple times"
+    )]
+    #[arg(
+        long_help = "Only use the videos of these versions as separate video tracks, if `--merge` is 'video' or 'auto'. \
+    Crunchyroll provides one version per audio language, which may differ in their video (e.g. because of cut scenes). \
+    The videos are ordered like the given versions, the audios and subtitles of all other versions are added to the first video, like with `--merge audio`. \
+    The tracks of every video are labeled with the name of its version instead of its number. \
+    Every version must also be given via `-a`/`--audio`"
+    )]
+    #[arg(long, value_delimiter = ',')]
+    pub(crate) versions: Vec<Locale>,
 
     #[arg(
         help = "Specified which language tagging the audio and subtitle tracks and language specific format options should have. \
@@ -126,6 +240,22 @@ pub struct Archive {
     #[arg(long)]
     #[arg(value_parser = LanguageTagging::parse)]
     pub(crate) language_tagging: Option<LanguageTagging>,
+    #[arg(help = "File which maps locales to the language tags written into the video file")]
+    #[arg(
+        long_help = "File which maps locales to the language tags written into the video file, e.g. to use ISO 639-2 codes which some media servers expect. \
+    Every line has the format <locale>=<tag> (e.g. 'es-419=spa'). \
+    Lines before any section apply to audio and subtitle tracks, lines after an '[audio]' or '[subtitle]' line only to the respective tracks. \
+    Empty lines and lines starting with '#' are ignored. \
+    Takes precedence over `--language-tagging` for the mapped locales"
+    )]
+    #[arg(long)]
+    pub(crate) locale_map: Option<PathBuf>,
+    #[arg(help = "Only use the exact given audio and subtitle locales")]
+    #[arg(long_help = "Only use the exact given audio and subtitle locales. \
+    Without this flag, language codes like 'es' are implicitly resolved to a locale of the same language (e.g. 'es-ES') when `--language-tagging` is used. \
+    With this flag, the archive fails instead if the given locale isn't an exact locale")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) strict_locale_match: bool,
 
     #[arg(help = format!("Presets for converting the video to a specific coding format. \
     Available presets: \n  {}", FFmpegPreset::available_matches_human_readable().join("\n  ")))]
@@ -141,19 +271,69 @@ pub struct Archive {
     #[arg(
         long_help = "The number of threads used by ffmpeg to generate the output file. \
     Does not work with every codec/preset and is skipped entirely when specifying custom ffmpeg output arguments instead of a preset for `--ffmpeg-preset`. \
-    By default, ffmpeg chooses the thread count which works best for the output codec"
+    By default, all cpu threads are used if the video is re-encoded. If the streams are only copied, ffmpeg chooses the thread count itself"
     )]
     #[arg(long)]
     pub(crate) ffmpeg_threads: Option<usize>,
+    #[arg(help = "Normalize the loudness of the audio")]
+    #[arg(
+        long_help = "Normalize the loudness of the audio via ffmpeg's 'loudnorm' filter. \
+    This requires the audio to be re-encoded (as aac with 192k, unless the ffmpeg preset sets another audio codec), which takes longer and might slightly reduce the audio quality. \
+    Without it, the audio is copied untouched"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) normalize_audio: bool,
+    #[arg(help = "Pixel format of the video if it is re-encoded (e.g. yuv420p10le)")]
+    #[arg(
+        long_help = "Pixel format of the video if it is re-encoded (e.g. 'yuv420p' or 'yuv420p10le'). \
+    Only has an effect if the video is re-encoded, e.g. because of `--ffmpeg-preset` or hardsubs. \
+    If custom ffmpeg output arguments which already contain '-pix_fmt' are given via `--ffmpeg-preset`, they take precedence. \
+    Hardware encoders (e.g. nvidia or amd presets) only support a limited set of pixel formats. \
+    By default, the pixel format is chosen by the encoder"
+    )]
+    #[arg(long)]
+    pub(crate) pix_fmt: Option<String>,
 
     #[arg(
         help = "Set which subtitle language should be set as default / auto shown when starting a video"
     )]
     #[arg(long)]
     pub(crate) default_subtitle: Option<Locale>,
+    #[arg(help = "Burn the subtitle of the given language into the video")]
+    #[arg(
+        long_help = "Burn the subtitle of the given language into the video, so that it's shown on players which don't support (or don't auto select) soft subtitles. \
+    The subtitle is still embedded as soft subtitle, like all other subtitles. \
+    Burning subtitles into the video requires a re-encode, which may take a while"
+    )]
+    #[arg(long)]
+    pub(crate) hardsub: Option<Locale>,
+    #[arg(
+        help = "Set which audio language should be set as default / auto selected when starting a video"
+    )]
+    #[arg(
+        long_help = "Set which audio language should be set as default / auto selected when starting a video. \
+    Without it, players usually pick the first audio track"
+    )]
+    #[arg(long)]
+    pub(crate) default_audio: Option<Locale>,
+    #[arg(help = "Order the subtitle tracks like the audio tracks")]
+    #[arg(long_help = "Order the subtitle tracks like the audio tracks. \
+    Subtitles which have the same locale as an audio track are placed in the same order as the audio tracks, so that e.g. the second audio and second subtitle track have the same language where possible. \
+    All other subtitles are ordered as specified by `-s` / `--subtitle` after them")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) sort_subtitles_by_audio: bool,
     #[arg(help = "Include fonts in the downloaded file")]
     #[arg(long)]
     pub(crate) include_fonts: bool,
+    #[arg(help = "Directory in which fonts are looked up before downloading them")]
+    #[arg(
+        long_help = "Directory in which fonts are looked up before downloading them. \
+    A font is found if the directory contains a file with the same filename as Crunchyroll uses for it, or a file which is named like the font itself (e.g. 'Arial.ttf'). \
+    This also allows fonts which are not provided by Crunchyroll. \
+    Can also be set via the `CRUNCHY_CLI_FONT_DIR` env variable. Only works if `--include-fonts` is set"
+    )]
+    #[arg(long)]
+    pub(crate) font_dir: Option<PathBuf>,
     #[arg(
         help = "Includes chapters (e.g. intro, credits, ...). Only works if `--merge` is set to 'audio'"
     )]
@@ -165,6 +345,162 @@ pub struct Archive {
     )]
     #[arg(long, default_value_t = false)]
     pub(crate) include_chapters: bool,
+    #[arg(
+        help = "Only include the given chapter types. Implies `--include-chapters`. Valid types are: recap, intro, credits, preview"
+    )]
+    #[arg(long, value_delimiter = ',', value_parser = ["recap", "intro", "credits", "preview"])]
+    pub(crate) chapters: Vec<String>,
+    #[arg(help = "Do not fill the gaps between chapters with 'Episode' chapters")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) no_gap_chapters: bool,
+    #[arg(help = "Do not include chapters. This is the default")]
+    #[arg(long_help = "Do not include chapters. \
+    This is the default, the flag exists to explicitly disable chapters (e.g. in a shell alias). \
+    Conflicts with `--include-chapters` and `--chapters`")]
+    #[arg(long, default_value_t = false)]
+    #[arg(conflicts_with_all = ["include_chapters", "chapters"])]
+    pub(crate) no_chapters: bool,
+    #[arg(help = "Embed the episode / movie thumbnail as cover art")]
+    #[arg(long_help = "Embed the episode / movie thumbnail as cover art. \
+    mkv files get it as `cover.jpg` attachment, mp4 / mov files as attached picture. \
+    Other containers (and mp4 / mov files with hardsubs) can't carry it, so it's skipped in this case")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) thumbnail: bool,
+
+    #[arg(help = "Write a .nfo metadata file next to every downloaded video")]
+    #[arg(
+        long_help = "Write a .nfo metadata file next to every downloaded video. \
+    The file has the same name as the video and contains the title, season and episode number, air date, description and series title. \
+    Media servers like Jellyfin or Kodi use these files to identify the video without having to scrape any metadata. \
+    Has no effect if the output is stdout or a special file"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) write_nfo: bool,
+    #[arg(help = "Write a .txt file with the description next to every downloaded video")]
+    #[arg(
+        long_help = "Write a .txt file with the description next to every downloaded video. \
+    The file has the same name as the video and contains the title, series name, season and episode number, air date and description as plain text. \
+    Independent of `--write-nfo`. \
+    Has no effect if the output is stdout or a special file"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) write_description: bool,
+    #[arg(help = "Write a series.txt file with the series description")]
+    #[arg(
+        long_help = "Write a series.txt file with the series title, launch year and description. \
+    It's written once per series url into the directory of the first downloaded video of the series. \
+    Has no effect if the url isn't a series url or the output is stdout or a special file"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) write_series_description: bool,
+    #[arg(help = "Write a .json file with all resolved metadata next to every downloaded video")]
+    #[arg(
+        long_help = "Write a .json file with all resolved metadata next to every downloaded video. \
+    The file has the same name as the video and contains the ids, titles, season and episode number, audio and subtitle locales, resolution, duration and skip events (e.g. intro or credits) of the video. \
+    Has no effect if the output is stdout or a special file"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) metadata_json: bool,
+    #[arg(help = "Append every downloaded video to the given .m3u / .m3u8 playlist")]
+    #[arg(
+        long_help = "Append every downloaded video to the given .m3u / .m3u8 playlist, together with its duration and title. \
+    Videos are appended as soon as they're downloaded, so the playlist is in the order in which the downloads complete. \
+    If the playlist doesn't exist yet, it gets created. \
+    Videos which are output to stdout or a special file are not added"
+    )]
+    #[arg(long)]
+    pub(crate) playlist: Option<PathBuf>,
+    #[arg(help = "Write the video paths of `--playlist` relative to the playlist")]
+    #[arg(
+        long_help = "Write the video paths of `--playlist` relative to the directory of the playlist instead of absolute. \
+    Useful if the playlist and videos are moved together to another location"
+    )]
+    #[arg(long, requires = "playlist", default_value_t = false)]
+    pub(crate) playlist_relative: bool,
+    #[arg(
+        help = "Additionally write subtitles as separate files next to the video, named by the given template"
+    )]
+    #[arg(
+        long_help = "Additionally write subtitles as separate files next to the video. \
+    The given template is inserted between the video name and the subtitle extension, e.g. '{iso639_2}.{forced}' results in 'video.eng.forced.ass'. \
+    The following tokens are available:\n  \
+      {locale}   → Subtitle language (respects '--language-tagging')\n  \
+      {iso639_2} → Three letter ISO 639-2 code of the subtitle language\n  \
+      {cc}       → 'cc' if the subtitle is a closed caption, empty otherwise\n  \
+      {forced}   → 'forced' if the subtitle has the forced flag (closed captions), empty otherwise\n\
+    Has no effect if the output is stdout or a special file"
+    )]
+    #[arg(long)]
+    pub(crate) subtitle_filename_template: Option<String>,
+    #[arg(
+        help = "Additionally write subtitles as separate files next to the video in the given format. Valid formats are 'ass', 'vtt' and 'srt'"
+    )]
+    #[arg(
+        long_help = "Additionally write subtitles as separate files next to the video in the given format, regardless if they're also embedded into the video. \
+    Valid formats are 'ass', 'vtt' (e.g. for web players) and 'srt'. \
+    The files are named after the video with '--subtitle-filename-template' inserted before the extension, which defaults to '{locale}.{cc}' (e.g. 'video.en-US.cc.vtt') if not given. \
+    Has no effect if the output is stdout or a special file"
+    )]
+    #[arg(long, value_parser = ["ass", "vtt", "srt"])]
+    pub(crate) write_subs: Option<String>,
+    #[arg(
+        help = "Shift all subtitle timings by the given milliseconds. Can be set per language with <LANG>=<MS>"
+    )]
+    #[arg(
+        long_help = "Shift all subtitle timings by the given milliseconds (negative values shift them to the front). \
+    To only shift subtitles of a specific language, use <LANG>=<MS> (e.g. 'en-US=-500'). \
+    Can be used multiple times, a language specific offset takes precedence over a global one. \
+    Timings which would be negative after shifting are set to 0"
+    )]
+    #[arg(long, allow_hyphen_values = true, value_parser = crate::utils::clap::clap_parse_subtitle_offset)]
+    pub(crate) subtitle_offset: Vec<(Option<Locale>, TimeDelta)>,
+    #[arg(help = "Override the subtitle styles with the styles of the given .ass file")]
+    #[arg(
+        long_help = "Override the styles of every subtitle with the styles in the `[V4+ Styles]` section of the given .ass file (e.g. created with Aegisub). \
+    A style of a subtitle is replaced if the file contains a style with the same name (e.g. 'Default'), all other styles are left untouched. \
+    Useful to enforce a consistent font, size or outline"
+    )]
+    #[arg(long)]
+    pub(crate) subtitle_style: Option<PathBuf>,
+    #[arg(help = "Keep the partially written output file if ffmpeg fails")]
+    #[arg(long_help = "Keep the partially written output file if ffmpeg fails. \
+    By default, it is deleted so that it isn't mistaken as a complete download. Useful for debugging")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) keep_partial: bool,
+    #[arg(help = "Copy the temporary files of every download to the given directory")]
+    #[arg(
+        long_help = "Copy the temporary files of every download (raw video, audio and subtitle streams, chapters, ...) to the given directory. \
+    They're copied after ffmpeg has run, regardless if it was successful or not, and are prefixed with the output file name. Useful for debugging"
+    )]
+    #[arg(long)]
+    pub(crate) keep_temp: Option<PathBuf>,
+    #[arg(help = "Abort if a video isn't available instead of skipping it")]
+    #[arg(
+        long_help = "Abort if a video isn't available (e.g. because it's region locked) instead of skipping it. \
+    By default, unavailable videos are skipped and listed at the end"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) fail_fast: bool,
+    #[arg(help = "Add a global metadata tag to the output file. Must be provided as 'KEY=VALUE'")]
+    #[arg(
+        long_help = "Add a global metadata tag to the output file. Must be provided as 'KEY=VALUE' (e.g. 'COMMENT=my comment'). \
+    Can be used multiple times to set multiple tags. \
+    mkv files accept any key and store them as matroska tags, other containers (like mp4) only support a specific set of keys (e.g. 'title', 'date', 'comment') and drop all others"
+    )]
+    #[arg(long = "metadata", value_parser = crate::utils::clap::clap_parse_metadata)]
+    pub(crate) container_metadata: Vec<(String, String)>,
+
+    #[arg(
+        help = "Verify that all streams are present in the output file and that it isn't truncated"
+    )]
+    #[arg(
+        long_help = "Verify that all streams are present in the output file and that it isn't truncated. \
+    After the output file is generated, ffprobe is used to check if every video, audio and subtitle stream is present and not empty, and if the length of the file matches the length of the video. \
+    Fails and deletes the output file if a stream is missing or empty or the file is truncated. \
+    Has no effect if the output is stdout or a special file"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) verify_output: bool,
 
     #[arg(help = "Omit closed caption subtitles in the downloaded file")]
     #[arg(long, default_value_t = false)]
@@ -183,27 +519,297 @@ pub struct Archive {
     #[arg(long, default_values_t = SkipExistingMethod::default())]
     #[arg(value_parser = SkipExistingMethod::parse)]
     pub(crate) skip_existing_method: Vec<SkipExistingMethod>,
-    #[arg(help = "Skip special episodes")]
+    #[arg(
+        help = "Only works in combination with `--skip-existing`. Only skip already existing files if their size is at least 80% of the estimated file size"
+    )]
+    #[arg(long_help = "Only works in combination with `--skip-existing`. \
+    Only skip already existing files if their size matches the estimated file size of the episode. \
+    This is helpful to re-download files which are incomplete because of an aborted download. \
+    Because the estimation is based on the stream bandwidth, files are considered as complete if they're at least 80% of the estimated size")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) skip_existing_size: bool,
+    #[arg(
+        help = "Only works in combination with `--skip-existing`. Cache the output files of archived episodes for the given number of hours"
+    )]
+    #[arg(long_help = "Only works in combination with `--skip-existing`. \
+    Cache the output file of every archived (or already existing) episode for the given number of hours. \
+    If an episode is cached and its output file still exists on a later run, it's skipped before its streams are resolved, which saves api requests. \
+    Episodes are only skipped this way if the output template and the audio and subtitle languages are the same as when they were cached. \
+    The cache is not used if `--skip-existing-method` or `--skip-existing-size` is set, since they need the stream information")]
+    #[arg(long, value_name = "HOURS")]
+    pub(crate) metadata_cache: Option<u32>,
+    #[arg(help = "Clear the metadata cache before archiving")]
     #[arg(long, default_value_t = false)]
+    pub(crate) no_cache: bool,
+    #[arg(
+        help = "What to do if the output file already exists. Valid policies are 'always', 'never' and 'prompt'"
+    )]
+    #[arg(long_help = "What to do if the output file already exists. \
+    'always' overwrites the file, 'never' skips the video and 'prompt' asks interactively (if not run in a terminal, it behaves like 'never'). \
+    If not set, the video is written to a new file with a number appended to its name. \
+    Files which are skipped by `--skip-existing` are never overwritten. \
+    Has no effect if the output is stdout or a special file")]
+    #[arg(long, value_parser = ["always", "never", "prompt"])]
+    pub(crate) overwrite: Option<String>,
+    #[arg(help = "Skip special episodes")]
+    #[arg(long, visible_alias = "no-specials", default_value_t = false)]
     pub(crate) skip_specials: bool,
+    #[arg(help = "Only archive the given episodes of series urls, e.g. '5-12,14,S1'")]
+    #[arg(
+        long_help = "Only archive the given episodes of series urls, e.g. '5-12,14,S1'. \
+    Valid are episode numbers (e.g. '5'), episode ranges (e.g. '5-12', '5-' or '-12') and seasons / season episodes (e.g. 'S1' or 'S2E3-S2E5'), separated by ','. \
+    It works the same as a filter at the end of the url (e.g. '[E5-E12,E14,S1]') and is applied to every url without such a filter"
+    )]
+    #[arg(short, long)]
+    pub(crate) episodes: Option<String>,
+    #[arg(
+        help = "Additionally download the extras (featured music videos like openings and endings) of series"
+    )]
+    #[arg(
+        long_help = "Additionally download the extras (featured music videos like openings and endings) of series. \
+    Only has an effect if a series url is given. Every extra is downloaded as its own video, named after its title"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) include_extras: bool,
 
     #[arg(help = "Skip any interactive input")]
     #[arg(short, long, default_value_t = false)]
     pub(crate) yes: bool,
 
-    #[arg(help = "The number of threads used to download")]
-    #[arg(short, long, default_value_t = num_cpus::get())]
+    #[arg(help = "Print the download progress as json instead of drawing progress bars")]
+    #[arg(
+        long_help = "Print the download progress as json instead of drawing progress bars. \
+    Every progress update is written as a single json object per line to stderr, e.g. {\"stage\":\"download\",\"message\":\"Downloading video #1\",\"current\":123,\"total\":456,\"bytes_per_sec\":789}. \
+    Useful if the output is parsed by another program"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) progress_json: bool,
+    #[arg(help = "Read the ffmpeg progress from a regular file instead of a named pipe")]
+    #[arg(
+        long_help = "Read the ffmpeg progress from a regular file instead of a named pipe. \
+    On some ffmpeg builds or platforms, writing the progress to a named pipe doesn't work. \
+    This is detected if no progress is received within 10 seconds and the progress file is used automatically then, this flag skips the 10 seconds of waiting. \
+    The progress file is polled, so only use it if the progress doesn't work otherwise"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) no_progress_pipe: bool,
+
+    #[arg(
+        help = "Write the raw stream manifest and segment urls of every video to the given directory"
+    )]
+    #[arg(
+        long_help = "Write the raw stream manifest and segment urls of every video to the given directory. \
+    Useful to debug stream specific issues. \
+    Access tokens in the urls are redacted, use '--no-redact' to keep them"
+    )]
+    #[arg(long)]
+    pub(crate) dump_manifest: Option<PathBuf>,
+    #[arg(help = "Do not redact access tokens when using '--dump-manifest'")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) no_redact: bool,
+
+    #[arg(
+        help = "The number of threads used to download. Use 'auto' to adapt it to the connection"
+    )]
+    #[arg(long_help = "The number of threads used to download. \
+    Use 'auto' to start with 2 threads and adapt the count (max. 16) to the measured segment latency and retry rate: \
+    the thread count is increased as long as the latency stays close to the best one seen, decreased if it rises and halved if segments had to be retried")]
+    #[arg(short, long, default_value_t = num_cpus::get(), value_parser = crate::utils::clap::clap_parse_threads)]
     pub(crate) threads: usize,
+    #[arg(help = "The number of episodes which are downloaded at the same time")]
+    #[arg(
+        long_help = "The number of episodes which are downloaded at the same time. \
+    All parallel downloads share the same rate limit (`--speed-limit`) and the free disk space check takes the estimated size of all running downloads into account. \
+    Note that every download uses `--threads` threads, so you might want to lower it when using this flag"
+    )]
+    #[arg(long, default_value_t = 1)]
+    pub(crate) parallel_downloads: usize,
+
+    #[arg(
+        help = "Size of the buffer which is used to write downloaded segments to disk. Must be in format of <number>[B|KB|MB]"
+    )]
+    #[arg(
+        long_help = "Size of the buffer which is used to write downloaded segments to disk. Must be in format of <number>[B|KB|MB] (e.g. 512KB or 8MB). \
+    When downloading to slow disks (e.g. USB drives or network shares), writing the segments can slow down the whole download. \
+    A bigger write buffer keeps the segments in memory and writes them in bigger chunks to the disk. \
+    By default, segments are written directly without a buffer"
+    )]
+    #[arg(long, value_parser = crate::utils::clap::clap_parse_size)]
+    pub(crate) write_buffer_size: Option<usize>,
+    #[arg(
+        help = "Write segments which are downloaded out of order to temporary files instead of buffering them in memory"
+    )]
+    #[arg(
+        long_help = "Write segments which are downloaded out of order to temporary files instead of buffering them in memory. \
+    Segments are downloaded in parallel but must be written in order, so if one download thread stalls, all segments downloaded after it are kept in memory until it finishes, which can take up a lot of memory with high bitrate videos. \
+    With this flag, the memory usage stays low at the cost of additional disk writes"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) concurrent_fragment_writes: bool,
+    #[arg(
+        help = "Order in which the download threads fetch the segments of a stream. Valid orders are 'interleaved' and 'sequential'"
+    )]
+    #[arg(
+        long_help = "Order in which the download threads fetch the segments of a stream. \
+    With 'interleaved', every thread downloads the next segment which isn't downloaded yet, so the segments are downloaded (and written) roughly in order. \
+    With 'sequential', the segments are split into one contiguous block per thread, which some CDNs serve faster. \
+    Because the segments must still be written in order, 'sequential' keeps most of the downloaded segments buffered until the first block is finished, combine it with `--concurrent-fragment-writes` to buffer them on disk instead of in memory"
+    )]
+    #[arg(long, default_value = "interleaved", value_parser = ["interleaved", "sequential"])]
+    pub(crate) segment_order: String,
+
+    #[arg(help = "Number of retries if downloading a video segment fails")]
+    #[arg(long_help = "Number of retries if downloading a video segment fails. \
+    If the download of a segment still fails after all retries, the whole download is aborted. \
+    0 disables retries")]
+    #[arg(long, default_value_t = 5)]
+    pub(crate) download_retries: u32,
+    #[arg(help = "Delay in milliseconds before retrying a failed segment download")]
+    #[arg(
+        long_help = "Delay in milliseconds before retrying a failed segment download. \
+    The delay is doubled after every retry (e.g. 500, 1000, 2000, ... with a value of 500). \
+    Useful for rate limited accounts. By default, failed segments are retried immediately"
+    )]
+    #[arg(long, default_value_t = 0)]
+    pub(crate) retry_backoff: u64,
+    #[arg(
+        help = "Timeout in seconds for downloading a single video segment. 0 disables the timeout"
+    )]
+    #[arg(
+        long_help = "Timeout in seconds for downloading a single video segment. \
+    A segment which takes longer is counted as failed and retried, so every timeout uses up one of the `--download-retries`. \
+    Increase it on slow connections where large segments (e.g. of 1080p videos) legitimately take longer. \
+    0 disables the timeout"
+    )]
+    #[arg(long, default_value_t = 60)]
+    pub(crate) segment_timeout: u64,
+    #[arg(help = "Number of retries of a whole episode if it fails because of a temporary error")]
+    #[arg(
+        long_help = "Number of retries of a whole episode if it fails because of a temporary error (e.g. a rate limit or server error of Crunchyroll). \
+    Before every retry, the cli waits 1 minute, doubled after every retry up to 20 minutes, which should be enough for most rate limits to reset. \
+    Errors which are not temporary (like authentication errors) are never retried. \
+    If `--parallel-downloads` is greater than 1, only fetching the episode is retried, not the download itself. \
+    0 disables retries"
+    )]
+    #[arg(long, default_value_t = 0)]
+    pub(crate) episode_retries: u32,
+
+    #[arg(help = "Archive everything on the watchlist of your account")]
+    #[arg(
+        long_help = "Archive everything on the watchlist of your account, additionally to the given urls. \
+    Every watchlist entry is handled like a series / movie url without any filter. \
+    Combine it with `--skip-existing` to only download new episodes since the last run. \
+    Requires a login with credentials"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) watchlist: bool,
+    #[arg(help = "Only archive watchlist entries which aren't fully watched yet")]
+    #[arg(long, default_value_t = false, requires = "watchlist")]
+    pub(crate) watchlist_unwatched_only: bool,
+
+    #[arg(help = "Read urls with per url options from a batch file")]
+    #[arg(
+        long_help = "Read urls with per url options from a batch file, additionally to the given urls. \
+    The file is in toml format and has a '[[job]]' table for every url. \
+    Besides the required 'url', every job can have the keys 'resolution', 'audio', 'subtitle' (each like the respective argument) and 'output' (like `-o`/`--output`) to overwrite the arguments given on the command line, \
+    and 'directory' to put the output of this job into this directory. \
+    All jobs are validated before anything is downloaded"
+    )]
+    #[arg(long)]
+    pub(crate) batch: Option<PathBuf>,
+    #[arg(skip)]
+    batch_jobs: Vec<Archive>,
 
     #[arg(help = "Crunchyroll series url(s)")]
-    #[arg(required = true)]
+    #[arg(long_help = "Crunchyroll series url(s). \
+    A url can be suffixed with '=<output>' (e.g. 'https://www.crunchyroll.com/series/GY8VEQ95Y/darling-in-the-franxx=darling/{title}.mkv') to use another output template than '-o'/'--output' for this url. \
+    This output template is used as it is, '--season-folder' and '--episode-file' are not applied to it")]
+    #[arg(required_unless_present_any = ["watchlist", "batch"])]
     pub(crate) urls: Vec<String>,
+    #[arg(skip)]
+    url_outputs: Vec<Option<String>>,
+    #[arg(skip)]
+    pub(crate) dry_run: bool,
 }
 
 impl Execute for Archive {
     fn pre_check(&mut self) -> Result<()> {
+        // every batch job is a copy of the cli arguments with its own overrides, so it runs through
+        // the same checks as the arguments itself
+        if let Some(batch) = self.batch.take() {
+            for (i, batch_job) in read_batch_file(&batch)?.into_iter().enumerate() {
+                let mut job = self.clone();
+                job.watchlist = false;
+                job.urls = vec![batch_job.url];
+                if let Some(resolution) = batch_job.resolution {
+                    job.resolution = resolution
+                }
+                if let Some(audio) = batch_job.audio {
+                    job.audio = audio
+                }
+                if let Some(subtitle) = batch_job.subtitle {
+                    job.subtitle = subtitle
+                }
+                if let Some(output) = batch_job.output {
+                    job.output = output
+                }
+                if let Err(e) = job.pre_check() {
+                    bail!(
+                        "Job {} in batch file '{}' is invalid: {}",
+                        i + 1,
+                        batch.to_string_lossy(),
+                        e
+                    )
+                }
+                if let Some(directory) = batch_job.directory {
+                    if job.output != "-" && !is_special_file(&job.output) {
+                        job.output = directory.join(&job.output).to_string_lossy().to_string()
+                    }
+                }
+                self.batch_jobs.push(job)
+            }
+        }
+
+        if self.plex {
+            self.output = PLEX_OUTPUT_TEMPLATE.to_string();
+            self.universal_output = true
+        }
+        if let Some(episode_file) = &self.episode_file {
+            if Path::new(episode_file)
+                .parent()
+                .is_some_and(|p| !p.as_os_str().is_empty())
+            {
+                bail!("`--episode-file` must not contain a folder, use `--season-folder` instead")
+            }
+            self.output.clone_from(episode_file)
+        }
+        if let Some(season_folder) = &self.season_folder {
+            if self.output != "-" && !is_special_file(&self.output) {
+                self.output = Path::new(season_folder)
+                    .join(&self.output)
+                    .to_string_lossy()
+                    .to_string()
+            }
+            if let Some(output_specials) = &mut self.output_specials {
+                if output_specials.as_str() != "-" && !is_special_file(output_specials.as_str()) {
+                    *output_specials = Path::new(season_folder)
+                        .join(output_specials.as_str())
+                        .to_string_lossy()
+                        .to_string()
+                }
+            }
+        }
+
+        check_path_template(&self.output)?;
+        if let Some(output_specials) = &self.output_specials {
+            check_path_template(output_specials)?
+        }
+
         if !has_ffmpeg() {
-            bail!("FFmpeg is needed to run this command")
+            bail!(
+                "FFmpeg is needed to run this command but '{}' could not be executed. Install it or set its path via `--ffmpeg-path` / the `CRUNCHY_CLI_FFMPEG` env variable",
+                ffmpeg_path().to_string_lossy()
+            )
         } else if PathBuf::from(&self.output)
             .extension()
             .unwrap_or_default()
@@ -226,6 +832,47 @@ impl Execute for Archive {
             }
         }
 
+        if self.parallel_downloads == 0 {
+            bail!("`--parallel-downloads` must be at least 1")
+        } else if self.parallel_downloads > 1
+            && (self.output == "-"
+                || is_special_file(&self.output)
+                || self
+                    .output_specials
+                    .as_ref()
+                    .is_some_and(|so| so == "-" || is_special_file(so)))
+        {
+            bail!("`--parallel-downloads` cannot be used when writing to stdout or a special file")
+        }
+
+        if let Some(subtitle_style) = &self.subtitle_style {
+            if !subtitle_style.is_file() {
+                bail!(
+                    "Subtitle style file '{}' does not exist",
+                    subtitle_style.to_string_lossy()
+                )
+            }
+        }
+
+        if self.font_dir.is_none() {
+            self.font_dir = font_directory()
+        }
+        if let Some(font_dir) = &self.font_dir {
+            if !font_dir.is_dir() {
+                bail!(
+                    "Font directory '{}' does not exist",
+                    font_dir.to_string_lossy()
+                )
+            } else if !self.include_fonts {
+                warn!("`--font-dir` has no effect without `--include-fonts`")
+            }
+        }
+
+        if self.no_chapters {
+            self.include_chapters = false
+        } else if !self.chapters.is_empty() {
+            self.include_chapters = true
+        }
         if self.include_chapters
             && !matches!(self.merge, MergeBehavior::Sync)
             && !matches!(self.merge, MergeBehavior::Audio)
@@ -236,13 +883,70 @@ impl Execute for Archive {
         if !self.skip_existing_method.is_empty() && !self.skip_existing {
             warn!("`--skip-existing-method` has no effect if `--skip-existing` is not set")
         }
+        if self.skip_existing_size && !self.skip_existing {
+            warn!("`--skip-existing-size` has no effect if `--skip-existing` is not set")
+        }
+        if self.metadata_cache.is_some() && !self.skip_existing {
+            warn!("`--metadata-cache` has no effect if `--skip-existing` is not set")
+        }
+
+        self.url_outputs.clear();
+        for url in self.urls.iter_mut() {
+            let (split_url, url_output) = split_url_output(url.as_str());
+            if let Some(url_output) = &url_output {
+                check_path_template(url_output)?;
+                if PathBuf::from(url_output)
+                    .extension()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    != "mkv"
+                    && !is_special_file(url_output)
+                    && url_output != "-"
+                {
+                    bail!(
+                        "File extension for the output of url {} is not '.mkv'. Currently only matroska / '.mkv' files are supported",
+                        split_url
+                    )
+                }
+            }
+            *url = split_url;
+            self.url_outputs.push(url_output)
+        }
+        if let Some(episodes) = &self.episodes {
+            let filter = episodes_to_url_filter(episodes)?;
+            for url in self.urls.iter_mut() {
+                // urls which have their own filter are left as they are
+                if !url.ends_with(']') {
+                    url.push_str(&format!("[{}]", filter))
+                }
+            }
+        }
 
         self.audio = all_locale_in_locales(self.audio.clone());
         self.subtitle = all_locale_in_locales(self.subtitle.clone());
 
+        if !self.versions.is_empty() {
+            if !matches!(self.merge, MergeBehavior::Video | MergeBehavior::Auto) {
+                bail!("`--versions` can only be used if `--merge` is set to 'video' or 'auto'")
+            }
+            if let Some(version) = self.versions.iter().find(|v| !self.audio.contains(v)) {
+                bail!(
+                    "Version {} must also be given via `-a` / `--audio`",
+                    version
+                )
+            }
+        }
+
+        if let Some(hardsub) = &self.hardsub {
+            if !self.subtitle.contains(hardsub) {
+                bail!("`--hardsub` must be one of the subtitles given via `-s` / `--subtitle`")
+            }
+            warn!("Subtitles are burned into the video. Adding subtitles may take a while")
+        }
+
         if let Some(language_tagging) = &self.language_tagging {
-            self.audio = resolve_locales(&self.audio);
-            self.subtitle = resolve_locales(&self.subtitle);
+            self.audio = resolve_locales(&self.audio, self.strict_locale_match)?;
+            self.subtitle = resolve_locales(&self.subtitle, self.strict_locale_match)?;
             self.output_audio_locales = language_tagging.convert_locales(&self.audio);
             self.output_subtitle_locales = language_tagging.convert_locales(&self.subtitle);
         } else {
@@ -260,13 +964,85 @@ impl Execute for Archive {
                 .collect();
         }
 
+        if let Some(locale_map) = &self.locale_map {
+            let locale_map = LocaleMap::read(locale_map)?;
+            for (locale, output) in zip(&self.audio, &mut self.output_audio_locales) {
+                if let Some(tag) = locale_map.audio.get(locale) {
+                    output.clone_from(tag)
+                }
+            }
+            for (locale, output) in zip(&self.subtitle, &mut self.output_subtitle_locales) {
+                if let Some(tag) = locale_map.subtitle.get(locale) {
+                    output.clone_from(tag)
+                }
+            }
+        }
+
         Ok(())
     }
 
-    async fn execute(self, ctx: Context) -> Result<()> {
+    async fn execute(mut self, ctx: Context) -> Result<()> {
         if !ctx.crunchy.premium().await {
             warn!("You may not be able to download all requested videos when logging in anonymously or using a non-premium account")
         }
+        let download_stats = DownloadStats::start();
+        let mut skipped = vec![];
+
+        if self.no_cache {
+            MetadataCache::clear()?
+        }
+        let mut metadata_cache = self
+            .metadata_cache
+            .filter(|_| self.skip_existing && !self.dry_run)
+            .map(|ttl| MetadataCache::load(TimeDelta::hours(ttl as i64)))
+            .transpose()?;
+
+        let batch_jobs = mem::take(&mut self.batch_jobs);
+        if !self.urls.is_empty() || self.watchlist {
+            self.archive(&ctx, &download_stats, &mut skipped, &mut metadata_cache)
+                .await?
+        }
+        for (i, job) in batch_jobs.into_iter().enumerate() {
+            info!("Running batch job {}", i + 1);
+            job.archive(&ctx, &download_stats, &mut skipped, &mut metadata_cache)
+                .await?
+        }
+
+        download_stats.log_summary();
+        report_skipped(&skipped)
+    }
+}
+
+impl Archive {
+    /// Key of an episode in the metadata cache. Besides the episode id it contains the output
+    /// templates and locale selection, so that an episode is archived again if any of them changes.
+    fn metadata_cache_key(&self, episode_id: &str, output: &str) -> String {
+        let locales = |locales: &[Locale]| {
+            locales
+                .iter()
+                .map(|l| l.to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+        format!(
+            "{}|{}|{}|{}|{}",
+            episode_id,
+            output,
+            self.output_specials.as_deref().unwrap_or_default(),
+            locales(&self.audio),
+            locales(&self.subtitle)
+        )
+    }
+
+    async fn archive(
+        self,
+        ctx: &Context,
+        download_stats: &DownloadStats,
+        skipped: &mut Vec<(String, String)>,
+        metadata_cache: &mut Option<MetadataCache>,
+    ) -> Result<()> {
+        // the stream information is required to check the existing file with those flags
+        let use_metadata_cache = self.skip_existing_method.is_empty() && !self.skip_existing_size;
 
         let mut parsed_urls = vec![];
 
@@ -280,8 +1056,43 @@ impl Execute for Archive {
                 Err(e) => bail!("url {} could not be parsed: {}", url, e),
             };
         }
+        if self.watchlist {
+            let progress_handler = progress!("Fetching watchlist");
+            let entries = match ctx.crunchy.watchlist(WatchlistOptions::default()).await {
+                Ok(entries) => entries,
+                Err(e) => bail!("Watchlist could not be fetched: {}", e),
+            };
+            let mut count = 0;
+            for entry in entries {
+                if self.watchlist_unwatched_only && entry.fully_watched {
+                    continue;
+                }
+                parsed_urls.push((entry.panel, UrlFilter::default()));
+                count += 1
+            }
+            progress_handler.stop(format!("Fetched {} watchlist entries", count));
+        }
+
+        let playlist = match self.playlist.as_ref().filter(|_| !self.dry_run) {
+            Some(playlist) => Some(Playlist::open(playlist.clone(), self.playlist_relative)?),
+            None => None,
+        };
 
         for (i, (media_collection, url_filter)) in parsed_urls.into_iter().enumerate() {
+            // the output which was specified via `<url>=<output>` takes precedence over `--output`
+            let output = self
+                .url_outputs
+                .get(i)
+                .cloned()
+                .flatten()
+                .unwrap_or_else(|| self.output.clone());
+
+            let mut series_description = match &media_collection {
+                MediaCollection::Series(series) => Some(series.clone()),
+                _ => None,
+            }
+            .filter(|_| self.write_series_description && !self.dry_run);
+
             let progress_handler = progress!("Fetching series details");
             let single_format_collection = Filter::new(
                 url_filter,
@@ -325,11 +1136,12 @@ impl Execute for Archive {
                     warn!("Skipping premium episodes in season {season}");
                     Ok(())
                 },
-                Format::has_relative_fmt(&self.output),
+                Format::has_relative_fmt(&output),
                 !self.yes,
                 self.skip_specials,
                 ctx.crunchy.premium().await,
             )
+            .include_extras(self.include_extras)
             .visit(media_collection)
             .await?;
 
@@ -344,12 +1156,18 @@ impl Execute for Archive {
             let download_builder =
                 DownloadBuilder::new(ctx.client.clone(), ctx.rate_limiter.clone())
                     .default_subtitle(self.default_subtitle.clone())
+                    .hardsub(self.hardsub.clone())
+                    .default_audio(self.default_audio.clone())
                     .download_fonts(self.include_fonts)
+                    .font_dir(self.font_dir.clone())
                     .ffmpeg_preset(self.ffmpeg_preset.clone().unwrap_or_default())
                     .ffmpeg_threads(self.ffmpeg_threads)
+                    .normalize_audio(self.normalize_audio)
+                    .pix_fmt(self.pix_fmt.clone())
                     .output_format(Some("matroska".to_string()))
                     .audio_sort(Some(self.audio.clone()))
                     .subtitle_sort(Some(self.subtitle.clone()))
+                    .subtitle_sort_by_audio(self.sort_subtitles_by_audio)
                     .no_closed_caption(self.no_closed_caption)
                     .merge_sync_tolerance(match self.merge {
                         MergeBehavior::Sync => Some(self.merge_sync_tolerance),
@@ -360,6 +1178,30 @@ impl Execute for Archive {
                         _ => None,
                     })
                     .threads(self.threads)
+                    .write_buffer_size(self.write_buffer_size)
+                    .concurrent_fragment_writes(self.concurrent_fragment_writes)
+                    .segment_order(self.segment_order.clone())
+                    .download_retries(self.download_retries)
+                    .download_stats(Some(download_stats.clone()))
+                    .retry_backoff(self.retry_backoff)
+                    .segment_timeout(self.segment_timeout)
+                    .progress_json(self.progress_json)
+                    .progress_pipe(!self.no_progress_pipe)
+                    .verify_output(self.verify_output)
+                    .subtitle_filename_template(self.subtitle_filename_template.clone())
+                    .subtitle_sidecar_format(self.write_subs.clone())
+                    .subtitle_offset(self.subtitle_offset.clone())
+                    .subtitle_style(self.subtitle_style.clone())
+                    .keep_partial(self.keep_partial)
+                    .keep_temp(self.keep_temp.clone())
+                    .chapter_types(self.chapters.clone())
+                    .gap_chapters(!self.no_gap_chapters)
+                    .container_metadata(self.container_metadata.clone())
+                    .dry_run(self.dry_run)
+                    .multi_progress((self.parallel_downloads > 1).then(MultiProgress::new))
+                    .concurrent_space(
+                        (self.parallel_downloads > 1).then(|| Arc::new(AtomicU64::new(0))),
+                    )
                     .audio_locale_output_map(
                         zip(self.audio.clone(), self.output_audio_locales.clone()).collect(),
                     )
@@ -367,88 +1209,194 @@ impl Execute for Archive {
                         zip(self.subtitle.clone(), self.output_subtitle_locales.clone()).collect(),
                     );
 
+            let mut part_collector = PartCollector::default();
+            let mut downloads = JoinSet::new();
+            // the cache is only updated after all downloads of the url finished successfully
+            let mut cache_entries = vec![];
             for single_formats in single_format_collection.into_iter() {
-                let (download_formats, mut format) = get_format(&self, &single_formats).await?;
+                if part_collector.skip_part(single_formats[0].part) {
+                    debug!(
+                        "Skipping part {} of {} as its first part was skipped",
+                        single_formats[0].part.unwrap().0,
+                        single_formats[0].title
+                    );
+                    continue;
+                }
+                if let Some(cached_path) = metadata_cache
+                    .as_ref()
+                    .filter(|_| use_metadata_cache && single_formats[0].part.is_none())
+                    .and_then(|c| {
+                        c.get(&self.metadata_cache_key(&single_formats[0].episode_id, &output))
+                    })
+                {
+                    if is_existing_file(cached_path, None) {
+                        debug!(
+                            "Skipping already existing file '{}' (cached)",
+                            cached_path.to_string_lossy()
+                        );
+                        continue;
+                    }
+                }
+
+                let (download_formats, mut format) =
+                    match retry_episode(self.episode_retries, &single_formats[0].title, || {
+                        get_format(&ctx, &self, &single_formats)
+                    })
+                    .await
+                    {
+                        Ok(format) => format,
+                        Err(e) if !self.fail_fast && e.is::<UnavailableError>() => {
+                            let name = format!(
+                                "{} ({})",
+                                single_formats[0].title, single_formats[0].episode_id
+                            );
+                            warn!("Skipping {}: {}", name, e);
+                            skipped.push((name, e.to_string()));
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                    };
 
+                let stream_metadata = self
+                    .metadata_json
+                    .then(|| StreamMetadata::new(&download_formats));
                 let mut downloader = download_builder.clone().build();
                 for download_format in download_formats {
                     downloader.add_format(download_format)
                 }
 
-                let formatted_path = if format.is_special() {
-                    format.format_path(
-                        self.output_specials
-                            .as_ref()
-                            .map_or((&self.output).into(), |so| so.into()),
-                        self.universal_output,
-                        self.language_tagging.as_ref(),
-                    )
+                if let Some(max_filesize) = self.max_filesize {
+                    let estimated_size = downloader.estimated_file_size();
+                    if estimated_size > max_filesize {
+                        let message = format!(
+                            "estimated file size ({}) exceeds the maximal file size ({})",
+                            format_size(estimated_size),
+                            format_size(max_filesize)
+                        );
+                        if self.max_filesize_policy == "error" {
+                            bail!(
+                                "{} ({}): {}",
+                                single_formats[0].title,
+                                single_formats[0].episode_id,
+                                message
+                            )
+                        }
+                        warn!(
+                            "Skipping {} ({}): {}",
+                            single_formats[0].title, single_formats[0].episode_id, message
+                        );
+                        continue;
+                    }
+                }
+
+                // all parts of a video which is split into multiple parts are stored in the output
+                // file of the first part
+                let path = if let Some(path) = part_collector.following_dst(single_formats[0].part)
+                {
+                    path
                 } else {
-                    format.format_path(
-                        (&self.output).into(),
-                        self.universal_output,
-                        self.language_tagging.as_ref(),
-                    )
-                };
-                let (mut path, changed) = free_file(formatted_path.clone());
-
-                if changed && self.skip_existing {
-                    let mut skip = true;
-
-                    if !self.skip_existing_method.is_empty() {
-                        if let Some((audio_locales, subtitle_locales)) =
-                            get_video_streams(&formatted_path)?
-                        {
-                            let method_audio = self
-                                .skip_existing_method
-                                .contains(&SkipExistingMethod::Audio);
-                            let method_subtitle = self
-                                .skip_existing_method
-                                .contains(&SkipExistingMethod::Subtitle);
-
-                            let audio_differ = if method_audio {
-                                format
-                                    .locales
-                                    .iter()
-                                    .any(|(a, _)| !audio_locales.contains(a))
-                            } else {
-                                false
-                            };
-                            let subtitle_differ = if method_subtitle {
-                                format
-                                    .locales
-                                    .clone()
-                                    .into_iter()
-                                    .flat_map(|(a, mut s)| {
-                                        // remove the closed caption if the flag is given to omit
-                                        // closed captions
-                                        if self.no_closed_caption && a != Locale::ja_JP {
-                                            s.retain(|l| l != &a)
-                                        }
-                                        s
-                                    })
-                                    .any(|l| !subtitle_locales.contains(&l))
-                            } else {
-                                false
-                            };
+                    let formatted_path = if format.is_special() {
+                        format.format_path(
+                            self.output_specials
+                                .as_ref()
+                                .map_or((&output).into(), |so| so.into()),
+                            self.universal_output,
+                            self.episode_naming_from_metadata,
+                            self.language_tagging.as_ref(),
+                        )
+                    } else {
+                        format.format_path(
+                            (&output).into(),
+                            self.universal_output,
+                            self.episode_naming_from_metadata,
+                            self.language_tagging.as_ref(),
+                        )
+                    };
+                    let (mut path, changed) = free_file(formatted_path.clone());
 
-                            if (method_audio && audio_differ)
-                                || (method_subtitle && subtitle_differ)
+                    if changed && self.skip_existing {
+                        let mut skip = true;
+
+                        if !is_existing_file(
+                            &formatted_path,
+                            self.skip_existing_size
+                                .then(|| downloader.min_existing_file_size()),
+                        ) {
+                            // the existing file is empty or smaller than expected, probably
+                            // because a previous download was aborted
+                            skip = false;
+                            path.clone_from(&formatted_path)
+                        } else if !self.skip_existing_method.is_empty() {
+                            if let Some((audio_locales, subtitle_locales)) =
+                                get_video_streams(&formatted_path)?
                             {
-                                skip = false;
-                                path.clone_from(&formatted_path)
+                                let method_audio = self
+                                    .skip_existing_method
+                                    .contains(&SkipExistingMethod::Audio);
+                                let method_subtitle = self
+                                    .skip_existing_method
+                                    .contains(&SkipExistingMethod::Subtitle);
+
+                                let audio_differ = if method_audio {
+                                    format
+                                        .locales
+                                        .iter()
+                                        .any(|(a, _)| !audio_locales.contains(a))
+                                } else {
+                                    false
+                                };
+                                let subtitle_differ = if method_subtitle {
+                                    format
+                                        .locales
+                                        .clone()
+                                        .into_iter()
+                                        .flat_map(|(a, mut s)| {
+                                            // remove the closed caption if the flag is
+                                            // given to omit closed captions
+                                            if self.no_closed_caption && a != Locale::ja_JP {
+                                                s.retain(|l| l != &a)
+                                            }
+                                            s
+                                        })
+                                        .any(|l| !subtitle_locales.contains(&l))
+                                } else {
+                                    false
+                                };
+
+                                if (method_audio && audio_differ)
+                                    || (method_subtitle && subtitle_differ)
+                                {
+                                    skip = false;
+                                    path.clone_from(&formatted_path)
+                                }
                             }
                         }
-                    }
 
-                    if skip {
-                        debug!(
-                            "Skipping already existing file '{}'",
-                            formatted_path.to_string_lossy()
-                        );
-                        continue;
+                        if skip {
+                            debug!(
+                                "Skipping already existing file '{}'",
+                                formatted_path.to_string_lossy()
+                            );
+                            if single_formats[0].part.is_none() {
+                                cache_entries.push((
+                                    self.metadata_cache_key(&single_formats[0].episode_id, &output),
+                                    absolute_path(&formatted_path)?,
+                                ))
+                            }
+                            continue;
+                        }
+                    } else if let (true, Some(overwrite)) = (changed, &self.overwrite) {
+                        if !should_overwrite(&formatted_path, overwrite) {
+                            info!(
+                                "Skipping already existing file '{}'",
+                                formatted_path.to_string_lossy()
+                            );
+                            continue;
+                        }
+                        path.clone_from(&formatted_path)
                     }
-                }
+                    path
+                };
 
                 format.locales.sort_by(|(a, _), (b, _)| {
                     self.audio
@@ -465,9 +1413,154 @@ impl Execute for Archive {
                     })
                 }
 
+                part_collector.start_part(single_formats[0].part);
                 format.visual_output(&path);
 
-                downloader.download(&path).await?
+                if single_formats[0].part.is_none()
+                    && path.to_string_lossy() != "-"
+                    && !is_special_file(&path)
+                {
+                    cache_entries.push((
+                        self.metadata_cache_key(&single_formats[0].episode_id, &output),
+                        absolute_path(&path)?,
+                    ))
+                }
+
+                if let Some(series) = series_description
+                    .take_if(|_| path.to_string_lossy() != "-" && !is_special_file(&path))
+                {
+                    let dir = path.parent().unwrap_or(Path::new("."));
+                    let series_path = write_series_description(&series, dir)?;
+                    debug!(
+                        "Wrote series description file '{}'",
+                        series_path.to_string_lossy()
+                    )
+                }
+
+                if let Some(part) = single_formats[0].part.filter(|_| {
+                    !self.dry_run && path.to_string_lossy() != "-" && !is_special_file(&path)
+                }) {
+                    // continue until all parts are downloaded and concatenated
+                    if !part_collector
+                        .download(downloader, &format.title, part, &path)
+                        .await?
+                    {
+                        continue;
+                    }
+                } else if self.parallel_downloads > 1 {
+                    // wait until a download slot is free
+                    if downloads.len() >= self.parallel_downloads {
+                        downloads.join_next().await.unwrap()??
+                    }
+
+                    let write_nfo_file = self.write_nfo && !self.dry_run;
+                    let write_description_file = self.write_description && !self.dry_run;
+                    let stream_metadata = stream_metadata.filter(|_| !self.dry_run);
+                    let is_movie = single_formats[0].is_movie();
+                    let playlist = playlist
+                        .clone()
+                        .filter(|_| path.to_string_lossy() != "-" && !is_special_file(&path));
+                    downloads.spawn(async move {
+                        downloader.download(&path).await?;
+                        if write_nfo_file {
+                            let nfo_path = write_nfo(&format, is_movie, &path)?;
+                            debug!("Wrote nfo file '{}'", nfo_path.to_string_lossy())
+                        }
+                        if write_description_file {
+                            let description_path = write_description(&format, is_movie, &path)?;
+                            debug!(
+                                "Wrote description file '{}'",
+                                description_path.to_string_lossy()
+                            )
+                        }
+                        if let Some(stream_metadata) = &stream_metadata {
+                            let json_path =
+                                write_metadata_json(&format, is_movie, stream_metadata, &path)?;
+                            debug!("Wrote metadata json file '{}'", json_path.to_string_lossy())
+                        }
+                        if let Some(playlist) = &playlist {
+                            playlist.append(&format.title, &path)?;
+                            debug!("Added '{}' to playlist", path.to_string_lossy())
+                        }
+                        Ok::<(), anyhow::Error>(())
+                    });
+                    continue;
+                } else {
+                    // the first attempt uses the already created downloader, every retry fetches
+                    // the episode again as the stream urls may be expired
+                    let mut downloader = Some(downloader);
+                    retry_episode(self.episode_retries, &format.title, || {
+                        let downloader = downloader.take();
+                        let (ctx, archive, single_formats, download_builder, path) =
+                            (&ctx, &self, &single_formats, &download_builder, &path);
+                        async move {
+                            let downloader = match downloader {
+                                Some(downloader) => downloader,
+                                None => {
+                                    let (download_formats, _) =
+                                        get_format(ctx, archive, single_formats).await?;
+                                    let mut downloader = download_builder.clone().build();
+                                    for download_format in download_formats {
+                                        downloader.add_format(download_format)
+                                    }
+                                    downloader
+                                }
+                            };
+                            downloader.download(path).await
+                        }
+                    })
+                    .await?;
+                }
+
+                if self.write_nfo
+                    && !self.dry_run
+                    && path.to_string_lossy() != "-"
+                    && !is_special_file(&path)
+                {
+                    let nfo_path = write_nfo(&format, single_formats[0].is_movie(), &path)?;
+                    debug!("Wrote nfo file '{}'", nfo_path.to_string_lossy())
+                }
+                if self.write_description
+                    && !self.dry_run
+                    && path.to_string_lossy() != "-"
+                    && !is_special_file(&path)
+                {
+                    let description_path =
+                        write_description(&format, single_formats[0].is_movie(), &path)?;
+                    debug!(
+                        "Wrote description file '{}'",
+                        description_path.to_string_lossy()
+                    )
+                }
+                if let Some(stream_metadata) = stream_metadata.as_ref().filter(|_| {
+                    !self.dry_run && path.to_string_lossy() != "-" && !is_special_file(&path)
+                }) {
+                    let json_path = write_metadata_json(
+                        &format,
+                        single_formats[0].is_movie(),
+                        stream_metadata,
+                        &path,
+                    )?;
+                    debug!("Wrote metadata json file '{}'", json_path.to_string_lossy())
+                }
+                if let Some(playlist) = playlist
+                    .as_ref()
+                    .filter(|_| path.to_string_lossy() != "-" && !is_special_file(&path))
+                {
+                    playlist.append(&format.title, &path)?;
+                    debug!("Added '{}' to playlist", path.to_string_lossy())
+                }
+            }
+            while let Some(result) = downloads.join_next().await {
+                result??
+            }
+            part_collector.finish()?;
+
+            if let Some(metadata_cache) = metadata_cache.as_mut() {
+                for (key, path) in cache_entries {
+                    metadata_cache.insert(key, path)
+                }
+                metadata_cache.save()?
             }
         }
 
@@ -506,17 +1599,37 @@ impl SkipExistingMethod {
 }
 
 async fn get_format(
+    ctx: &Context,
     archive: &Archive,
     single_formats: &Vec<SingleFormat>,
 ) -> Result<(Vec<DownloadFormat>, Format)> {
     let mut format_pairs = vec![];
     let mut single_format_to_format_pairs = vec![];
 
-    for single_format in single_formats {
+    for (i, single_format) in single_formats.iter().enumerate() {
         let stream = single_format.stream().await?;
-        let Some((video, audio, _)) =
-            stream_data_from_stream(&stream, &archive.resolution, None).await?
-        else {
+        let stream_data = match stream_data_from_stream(
+            &stream,
+            &archive.resolution,
+            archive.min_bitrate,
+            None,
+        )
+        .await?
+        {
+            None if i > 0 && archive.quality_fallback == "per-track" => {
+                warn!(
+                    "Resolution ({}) is not available for the {} audio of {} ({}), using the best available resolution instead",
+                    archive.resolution,
+                    single_format.audio,
+                    single_format.source_type(),
+                    single_format.title
+                );
+                stream_data_from_stream(&stream, &BEST_RESOLUTION, archive.min_bitrate, None)
+                    .await?
+            }
+            stream_data => stream_data,
+        };
+        let Some((video, audio, _)) = stream_data else {
             if single_format.is_episode() {
                 bail!(
                     "Resolution ({}) is not available for episode {} ({}) of {} season {}",
@@ -536,6 +1649,18 @@ async fn get_format(
             }
         };
 
+        if let Some(dump_dir) = archive.dump_manifest.as_ref().filter(|_| !archive.dry_run) {
+            dump_manifest(
+                ctx,
+                &stream,
+                &[&video, &audio],
+                &format!("{}_{}", single_format.episode_id, single_format.audio),
+                dump_dir,
+                !archive.no_redact,
+            )
+            .await?
+        }
+
         let subtitles: Vec<(Subtitle, bool)> = archive
             .subtitle
             .iter()
@@ -557,6 +1682,25 @@ async fn get_format(
                 subtitles
             })
             .collect();
+        // requested subtitles which don't exist for this stream are reported, but only if the
+        // subtitles were explicitly given and not 'all' (which is also the default)
+        if archive.subtitle != Locale::all() {
+            let missing: Vec<String> = archive
+                .subtitle
+                .iter()
+                .filter(|l| !stream.captions.contains_key(*l) && !stream.subtitles.contains_key(*l))
+                .map(|l| l.to_string())
+                .collect();
+            if !missing.is_empty() {
+                warn!(
+                    "{} subtitles are not available for the {} audio of {} ({}), only the available subtitles are downloaded",
+                    missing.join(", "),
+                    single_format.audio,
+                    single_format.source_type(),
+                    single_format.title
+                )
+            }
+        }
 
         format_pairs.push((single_format, video.clone(), audio, subtitles.clone()));
         single_format_to_format_pairs.push((single_format.clone(), video, subtitles));
@@ -573,7 +1717,15 @@ async fn get_format(
                     video: (video, single_format.audio.clone()),
                     audios: vec![(audio, single_format.audio.clone())],
                     subtitles,
-                    metadata: DownloadFormatMetadata { skip_events: None },
+                    metadata: DownloadFormatMetadata {
+                        skip_events: None,
+                        thumbnail: if archive.thumbnail {
+                            single_format.thumbnail()
+                        } else {
+                            None
+                        },
+                        video_label: Some(single_format.audio.to_human_readable()),
+                    },
                 })
             }
         }
@@ -598,6 +1750,12 @@ async fn get_format(
                 } else {
                     None
                 },
+                thumbnail: if archive.thumbnail {
+                    format_pairs.first().unwrap().0.thumbnail()
+                } else {
+                    None
+                },
+                video_label: None,
             },
         }),
         MergeBehavior::Auto | MergeBehavior::Sync => {
@@ -639,6 +1797,12 @@ async fn get_format(
                                     } else {
                                         None
                                     },
+                                    thumbnail: if archive.thumbnail {
+                                        single_format.thumbnail()
+                                    } else {
+                                        None
+                                    },
+                                    video_label: Some(single_format.audio.to_human_readable()),
                                 },
                             },
                         ));
@@ -652,18 +1816,148 @@ async fn get_format(
         }
     }
 
+    if !archive.versions.is_empty() {
+        select_versions(&mut download_formats, &archive.versions)
+    }
+    if archive.dedupe_audio {
+        dedup_audios(&mut download_formats)
+    }
+
     Ok((
         download_formats,
         Format::from_single_formats(single_format_to_format_pairs),
     ))
 }
 
+/// Calls `f` until it succeeds, fails with a non-temporary error or `retries` retries are used
+/// up. The delay between retries starts at 1 minute and is doubled after every retry, up to 20
+/// minutes.
+async fn retry_episode<T, F, Fut>(retries: u32, title: &str, mut f: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut retry = 0;
+    loop {
+        match f().await {
+            Ok(result) => return Ok(result),
+            Err(e) if retry < retries && is_temporary_error(&e) => {
+                retry += 1;
+                let delay = 60u64
+                    .saturating_mul(2u64.saturating_pow(retry - 1))
+                    .min(20 * 60);
+                warn!(
+                    "{} failed with a temporary error, retrying in {} seconds ({}/{}): {}",
+                    title, delay, retry, retries, e
+                );
+                tokio::time::sleep(std::time::Duration::from_secs(delay)).await
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Checks if an error is probably only temporary, e.g. because of a rate limit or server error.
+/// Other client errors (like 400 or 404) are caused by the input and are never temporary.
+fn is_temporary_error(error: &anyhow::Error) -> bool {
+    // no status means that the request itself failed, e.g. because of a timeout
+    let is_temporary_status =
+        |status: Option<u16>| status.map_or(true, |status| status == 429 || status >= 500);
+
+    if let Some(crunchy_error) = error.downcast_ref::<crunchyroll_rs::error::Error>() {
+        match crunchy_error {
+            crunchyroll_rs::error::Error::Request { status, .. } => {
+                is_temporary_status(status.map(|s| s.as_u16()))
+            }
+            crunchyroll_rs::error::Error::Block { .. } => true,
+            _ => false,
+        }
+    } else if let Some(segment_error) = error.downcast_ref::<SegmentError>() {
+        is_temporary_status(segment_error.status.map(|s| s.as_u16()))
+    } else if let Some(reqwest_error) = error.downcast_ref::<reqwest::Error>() {
+        reqwest_error.is_timeout()
+            || reqwest_error.is_connect()
+            || reqwest_error.status().is_some_and(|status| {
+                status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            })
+    } else {
+        false
+    }
+}
+
+/// Only keeps the formats whose video belongs to one of `versions`, ordered like `versions`. The
+/// audios and subtitles of all other formats are moved to the first kept format. If no format
+/// matches, the first one is kept.
+fn select_versions(download_formats: &mut Vec<DownloadFormat>, versions: &[Locale]) {
+    let (mut selected, mut others): (Vec<DownloadFormat>, Vec<DownloadFormat>) =
+        mem::take(download_formats)
+            .into_iter()
+            .partition(|f| versions.contains(&f.video.1));
+    if selected.is_empty() {
+        if others.is_empty() {
+            return;
+        }
+        selected.push(others.remove(0))
+    }
+    selected.sort_by_key(|f| versions.iter().position(|v| v == &f.video.1));
+
+    for other in others {
+        selected[0].audios.extend(other.audios);
+        selected[0].subtitles.extend(other.subtitles)
+    }
+    *download_formats = selected
+}
+
+/// Removes all audios whose locale is also present with a higher bitrate in another (or the same)
+/// format. Every format keeps at least one audio.
+fn dedup_audios(download_formats: &mut [DownloadFormat]) {
+    // the position and bandwidth of the audio with the highest bandwidth per locale
+    let mut best: HashMap<Locale, ((usize, usize), u64)> = HashMap::new();
+    for (i, download_format) in download_formats.iter().enumerate() {
+        for (j, (audio, locale)) in download_format.audios.iter().enumerate() {
+            if !best
+                .get(locale)
+                .is_some_and(|(_, bandwidth)| *bandwidth >= audio.bandwidth)
+            {
+                best.insert(locale.clone(), ((i, j), audio.bandwidth));
+            }
+        }
+    }
+
+    for (i, download_format) in download_formats.iter_mut().enumerate() {
+        let mut keep: Vec<bool> = download_format
+            .audios
+            .iter()
+            .enumerate()
+            .map(|(j, (_, locale))| best.get(locale).unwrap().0 == (i, j))
+            .collect();
+        if !keep.contains(&true) {
+            keep[0] = true
+        }
+
+        let mut keep_iter = keep.into_iter();
+        download_format.audios.retain(|(audio, locale)| {
+            let keep = keep_iter.next().unwrap();
+            if !keep {
+                info!(
+                    "Removing duplicate {} audio of video #{} ({} kbps, keeping {} kbps)",
+                    locale,
+                    i + 1,
+                    audio.bandwidth / 1000,
+                    best.get(locale).unwrap().1 / 1000
+                )
+            }
+            keep
+        })
+    }
+}
+
 fn get_video_streams(path: &Path) -> Result<Option<(Vec<Locale>, Vec<Locale>)>> {
     let video_streams =
         Regex::new(r"(?m)Stream\s#\d+:\d+\((?P<language>.+)\):\s(?P<type>(Audio|Subtitle))")
             .unwrap();
 
-    let ffmpeg = Command::new("ffmpeg")
+    let ffmpeg = Command::new(ffmpeg_path())
         .stdout(Stdio::null())
         .stderr(Stdio::piped())
         .arg("-hide_banner")
@@ -1,3 +1,4 @@
+mod batch;
 mod command;
 
 pub use command::Archive;
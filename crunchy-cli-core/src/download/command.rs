@@ -1,34 +1,53 @@
+use crate::utils::concat::PartCollector;
 use crate::utils::context::Context;
-use crate::utils::download::{DownloadBuilder, DownloadFormat, DownloadFormatMetadata};
-use crate::utils::ffmpeg::{FFmpegPreset, SOFTSUB_CONTAINERS};
+use crate::utils::download::{
+    DownloadBuilder, DownloadFormat, DownloadFormatMetadata, DownloadStats,
+};
+use crate::utils::ffmpeg::{FFmpegCodec, FFmpegPreset, SOFTSUB_CONTAINERS};
 use crate::utils::filter::{Filter, FilterMediaScope};
-use crate::utils::format::{Format, SingleFormat};
-use crate::utils::locale::{resolve_locales, LanguageTagging};
+use crate::utils::fmt::format_size;
+use crate::utils::format::{
+    check_path_template, report_skipped, Format, SingleFormat, SingleFormatCollection,
+    UnavailableError, PLEX_OUTPUT_TEMPLATE,
+};
+use crate::utils::locale::{resolve_locales, LanguageTagging, LocaleMap};
 use crate::utils::log::progress;
-use crate::utils::os::{free_file, has_ffmpeg, is_special_file};
-use crate::utils::parse::parse_url;
-use crate::utils::video::stream_data_from_stream;
+use crate::utils::manifest::dump_manifest;
+use crate::utils::os::{
+    ffmpeg_path, free_file, has_ffmpeg, is_existing_file, is_special_file, should_overwrite,
+};
+use crate::utils::parse::{parse_url, split_url_output};
+use crate::utils::sidecar::{write_description, write_metadata_json, write_nfo, StreamMetadata};
+use crate::utils::video::{print_stream_variants, stream_data_from_stream, BEST_RESOLUTION};
 use crate::Execute;
 use anyhow::bail;
 use anyhow::Result;
+use chrono::TimeDelta;
 use crunchyroll_rs::media::Resolution;
 use crunchyroll_rs::Locale;
-use log::{debug, error, warn};
+use log::{debug, error, info, warn};
 use std::collections::HashMap;
-use std::path::Path;
+use std::ffi::OsStr;
+use std::iter::zip;
+use std::path::{Path, PathBuf};
+
+/// ffmpeg formats which can be streamed to stdout with the codecs Crunchyroll delivers.
+const STDOUT_FORMATS: [&str; 3] = ["mpegts", "matroska", "nut"];
 
 #[derive(Clone, Debug, clap::Parser)]
 #[clap(about = "Download a video")]
 #[command(arg_required_else_help(true))]
 pub struct Download {
-    #[arg(help = format!("Audio language. Can only be used if the provided url(s) point to a series. \
+    #[arg(help = format!("Audio language(s). Can only be used if the provided url(s) point to a series. \
     Available languages are: {}", Locale::all().into_iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", ")))]
-    #[arg(long_help = format!("Audio language. Can only be used if the provided url(s) point to a series. \
+    #[arg(long_help = format!("Audio language(s). Can only be used if the provided url(s) point to a series. \
+    Multiple languages can be given comma separated (e.g. 'en-US,ja-JP'), all of them are muxed as separate audio tracks into one file then. \
+    The video is taken from the first language. \
     Available languages are:\n  {}\nIETF tagged language codes for the shown available locales can be used too", Locale::all().into_iter().map(|l| format!("{:<6} → {}", l.to_string(), l.to_human_readable())).collect::<Vec<String>>().join("\n  ")))]
-    #[arg(short, long, default_value_t = crate::utils::locale::system_locale())]
-    pub(crate) audio: Locale,
+    #[arg(short, long, value_delimiter = ',', default_values_t = vec![crate::utils::locale::system_locale()])]
+    pub(crate) audio: Vec<Locale>,
     #[arg(skip)]
-    output_audio_locale: String,
+    output_audio_locales: Vec<String>,
     #[arg(help = format!("Subtitle language. Available languages are: {}", Locale::all().into_iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", ")))]
     #[arg(long_help = format!("Subtitle language. If set, the subtitle will be burned into the video and cannot be disabled. \
     Available languages are: {}\nIETF tagged language codes for the shown available locales can be used too", Locale::all().into_iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", ")))]
@@ -48,6 +67,7 @@ pub struct Download {
       {height}                   → Height of the video\n  \
       {season_number}            → Number of the season\n  \
       {episode_number}           → Number of the episode\n  \
+      {episode_number_padded}    → Like '{episode_number}' but only integer numbers are zero padded\n  \
       {relative_episode_number}  → Number of the episode relative to its season\n  \
       {sequence_number}          → Like '{episode_number}' but without possible non-number characters\n  \
       {relative_sequence_number} → Like '{relative_episode_number}' but with support for episode 0's and .5's\n  \
@@ -64,21 +84,137 @@ pub struct Download {
     If not set, the '-o'/'--output' flag will be used as name template")]
     #[arg(long)]
     pub(crate) output_specials: Option<String>,
+    #[arg(help = format!("Container format if the output is stdout ('-') or a special file. \
+    Available formats are: {}", STDOUT_FORMATS.join(", ")))]
+    #[arg(long_help = format!("Container format if the output is stdout ('-') or a special file (e.g. a named pipe). \
+    ffmpeg can't detect the format from the file extension in this case, so it must be given explicitly. \
+    Subtitles are always burned into the video and fonts are never attached when piping. \
+    Chapters are only included if the format supports them (matroska). \
+    Available formats are: {}", STDOUT_FORMATS.join(", ")))]
+    #[arg(long, default_value = "mpegts")]
+    pub(crate) output_stdout_format: String,
+    #[arg(help = "Name of the folder the output file is stored in")]
+    #[arg(long_help = "Name of the folder the output file is stored in. \
+    The folder is put in front of the '-o'/'--output' (and '--output-specials') template, so the same pattern as in '-o'/'--output' can be used (e.g. '{series_name}/Season {season_number}'). \
+    Useful to split a series into multiple season folders like media servers expect it")]
+    #[arg(long)]
+    pub(crate) season_folder: Option<String>,
+    #[arg(
+        help = "Name of the output file, without any folder. Overwrites the '-o'/'--output' template"
+    )]
+    #[arg(long_help = "Name of the output file, without any folder. \
+    If set, it is used instead of the '-o'/'--output' template and combined with '--season-folder' as folder. \
+    Supports the same pattern as '-o'/'--output'")]
+    #[arg(long)]
+    pub(crate) episode_file: Option<String>,
 
     #[arg(help = "Sanitize the output file for use with all operating systems. \
     This option only affects template options and not static characters.")]
     #[arg(long, default_value_t = false)]
     pub(crate) universal_output: bool,
+    #[arg(help = "Use the folder and file structure Plex expects for series")]
+    #[arg(
+        long_help = "Use the folder and file structure Plex expects for series: '{series_name}/Season {season_number}/{series_name} - S{season_number}E{episode_number} - {title}.mkv'. \
+    Missing folders are created. \
+    Implies '--universal-output', so that the names are safe on all filesystems"
+    )]
+    #[arg(long, default_value_t = false)]
+    #[arg(conflicts_with_all = ["output", "season_folder", "episode_file"])]
+    pub(crate) plex: bool,
+    #[arg(
+        help = "Use the episode number exactly as Crunchyroll provides it for '{episode_number}'"
+    )]
+    #[arg(
+        long_help = "Use the episode number exactly as Crunchyroll provides it for '{episode_number}' instead of zero padding it. \
+    Crunchyroll episode numbers are not always numeric (e.g. '5.5' or 'SP1'), but are padded like numbers to at least two characters otherwise. \
+    Use '{episode_number_padded}' if integer episode numbers should still be padded"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) episode_naming_from_metadata: bool,
 
     #[arg(help = "Video resolution")]
     #[arg(long_help = "The video resolution. \
     Can either be specified via the pixels (e.g. 1920x1080), the abbreviation for pixels (e.g. 1080p) or 'common-use' words (e.g. best). \
     Specifying the exact pixels is not recommended, use one of the other options instead. \
     Crunchyroll let you choose the quality with pixel abbreviation on their clients, so you might be already familiar with the available options. \
-    The available common-use words are 'best' (choose the best resolution available) and 'worst' (worst resolution available)")]
+    The available common-use words are 'best' (choose the best resolution available) and 'worst' (worst resolution available). \
+    If the specified resolution is not available, the nearest lower resolution is used")]
     #[arg(short, long, default_value = "best")]
     #[arg(value_parser = crate::utils::clap::clap_parse_resolution)]
     pub(crate) resolution: Resolution,
+    #[arg(help = "Minimal bitrate of the video stream. Must be in format of <number>[k|m]")]
+    #[arg(
+        long_help = "Minimal bitrate of the video stream. Must be in format of <number>[k|m] (e.g. 2000k or 2m). \
+    Video streams with a lower bitrate are never chosen, even if `--resolution` falls back to a lower resolution. \
+    If no video stream meets the bitrate, the download fails"
+    )]
+    #[arg(long, value_parser = crate::utils::clap::clap_parse_bitrate)]
+    pub(crate) min_bitrate: Option<u64>,
+    #[arg(
+        help = "Behavior if an audio locale is not available in the chosen resolution. Valid behaviors are 'strict' and 'per-track'"
+    )]
+    #[arg(
+        long_help = "Behavior if an audio locale (besides the first one) is not available in the chosen resolution (or with the chosen minimal bitrate). \
+    'strict' fails the download (default). \
+    'per-track' uses the best available resolution of this audio locale instead, the video of the first audio locale stays untouched"
+    )]
+    #[arg(long, default_value = "strict", value_parser = ["strict", "per-track"])]
+    pub(crate) quality_fallback: String,
+    #[arg(help = "Maximal estimated file size of a video. Must be in format of <number>[k|m|g]")]
+    #[arg(
+        long_help = "Maximal estimated file size of a video. Must be in format of <number>[k|m|g] (e.g. 700m or 1.5g). \
+    The size is estimated from the bandwidth of the chosen streams before anything is downloaded. \
+    What happens with videos which exceed the size is set via `--max-filesize-policy`. \
+    Combine it with `--resolution` to choose a lower resolution for long videos"
+    )]
+    #[arg(long, value_parser = crate::utils::clap::clap_parse_filesize)]
+    pub(crate) max_filesize: Option<u64>,
+    #[arg(
+        help = "Behavior if a video exceeds `--max-filesize`. Valid behaviors are 'skip' and 'error'"
+    )]
+    #[arg(long_help = "Behavior if a video exceeds `--max-filesize`. \
+    'skip' skips the video and continues with the next one. \
+    'error' fails the download (default)")]
+    #[arg(long, default_value = "error", value_parser = ["skip", "error"])]
+    pub(crate) max_filesize_policy: String,
+    #[arg(
+        help = "Container of the output file, regardless of its extension. Valid containers are 'mkv', 'mp4' and 'mov'"
+    )]
+    #[arg(
+        long_help = "Container of the output file, regardless of its extension. Valid containers are 'mkv', 'mp4' and 'mov'. \
+    By default, the container is inferred from the file extension of the output file. \
+    All container specific behavior (softsub support, font attachments, chapters, ...) is based on the given container instead, while the output filename stays as it is. \
+    If the extension of the output file is a different container, a warning is shown and this container is used. \
+    Has no effect if the output is stdout or a special file"
+    )]
+    #[arg(long, value_parser = ["mkv", "mp4", "mov"])]
+    pub(crate) container: Option<String>,
+    #[arg(help = "List all available video and audio variants instead of downloading")]
+    #[arg(
+        long_help = "List all available video and audio variants (id, resolution, fps, bandwidth, codec and audio language) instead of downloading. \
+    The variants are ordered from best to worst, which is also the order in which they're chosen when downloading"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) list_formats: bool,
+    #[arg(help = "List all available audio locales instead of downloading")]
+    #[arg(
+        long_help = "List all available audio locales instead of downloading. \
+    Every locale is printed on its own line, so the output can directly be used for `-a`/`--audio`. \
+    If the url points to multiple videos, the locales of all of them are listed"
+    )]
+    #[arg(long, default_value_t = false)]
+    #[arg(conflicts_with_all = ["list_formats", "list_subtitles"])]
+    pub(crate) list_audios: bool,
+    #[arg(help = "List all available subtitle locales instead of downloading")]
+    #[arg(
+        long_help = "List all available subtitle locales instead of downloading. \
+    Every locale is printed on its own line, so the output can directly be used for `-s`/`--subtitle`. \
+    Closed captions are marked with ' (cc)'. \
+    If the url points to multiple videos, the locales of all of them are listed"
+    )]
+    #[arg(long, default_value_t = false)]
+    #[arg(conflicts_with = "list_formats")]
+    pub(crate) list_subtitles: bool,
 
     #[arg(
         long,
@@ -91,6 +227,22 @@ pub struct Download {
     )]
     #[arg(value_parser = LanguageTagging::parse)]
     pub(crate) language_tagging: Option<LanguageTagging>,
+    #[arg(help = "File which maps locales to the language tags written into the video file")]
+    #[arg(
+        long_help = "File which maps locales to the language tags written into the video file, e.g. to use ISO 639-2 codes which some media servers expect. \
+    Every line has the format <locale>=<tag> (e.g. 'es-419=spa'). \
+    Lines before any section apply to audio and subtitle tracks, lines after an '[audio]' or '[subtitle]' line only to the respective tracks. \
+    Empty lines and lines starting with '#' are ignored. \
+    Takes precedence over `--language-tagging` for the mapped locales"
+    )]
+    #[arg(long)]
+    pub(crate) locale_map: Option<PathBuf>,
+    #[arg(help = "Only use the exact given audio and subtitle locales")]
+    #[arg(long_help = "Only use the exact given audio and subtitle locales. \
+    Without this flag, language codes like 'es' are implicitly resolved to a locale of the same language (e.g. 'es-ES') when `--language-tagging` is used. \
+    With this flag, the download fails instead if the given locale isn't an exact locale")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) strict_locale_match: bool,
 
     #[arg(help = format!("Presets for converting the video to a specific coding format. \
     Available presets: \n  {}", FFmpegPreset::available_matches_human_readable().join("\n  ")))]
@@ -106,17 +258,64 @@ pub struct Download {
     #[arg(
         long_help = "The number of threads used by ffmpeg to generate the output file. \
     Does not work with every codec/preset and is skipped entirely when specifying custom ffmpeg output arguments instead of a preset for `--ffmpeg-preset`. \
-    By default, ffmpeg chooses the thread count which works best for the output codec"
+    By default, all cpu threads are used if the video is re-encoded. If the streams are only copied, ffmpeg chooses the thread count itself"
     )]
     #[arg(long)]
     pub(crate) ffmpeg_threads: Option<usize>,
+    #[arg(help = "Pixel format of the video if it is re-encoded (e.g. yuv420p10le)")]
+    #[arg(
+        long_help = "Pixel format of the video if it is re-encoded (e.g. 'yuv420p' or 'yuv420p10le'). \
+    Only has an effect if the video is re-encoded, e.g. because of `--ffmpeg-preset` or hardsubs. \
+    If custom ffmpeg output arguments which already contain '-pix_fmt' are given via `--ffmpeg-preset`, they take precedence. \
+    Hardware encoders (e.g. nvidia or amd presets) only support a limited set of pixel formats. \
+    By default, the pixel format is chosen by the encoder"
+    )]
+    #[arg(long)]
+    pub(crate) pix_fmt: Option<String>,
 
     #[arg(help = "Skip files which are already existing by their name")]
     #[arg(long, default_value_t = false)]
     pub(crate) skip_existing: bool,
+    #[arg(
+        help = "Only works in combination with `--skip-existing`. Only skip already existing files if their size is at least 80% of the estimated file size"
+    )]
+    #[arg(long_help = "Only works in combination with `--skip-existing`. \
+    Only skip already existing files if their size matches the estimated file size of the episode. \
+    This is helpful to re-download files which are incomplete because of an aborted download. \
+    Because the estimation is based on the stream bandwidth, files are considered as complete if they're at least 80% of the estimated size")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) skip_existing_size: bool,
+    #[arg(
+        help = "What to do if the output file already exists. Valid policies are 'always', 'never' and 'prompt'"
+    )]
+    #[arg(long_help = "What to do if the output file already exists. \
+    'always' overwrites the file, 'never' skips the video and 'prompt' asks interactively (if not run in a terminal, it behaves like 'never'). \
+    If not set, the video is written to a new file with a number appended to its name. \
+    Files which are skipped by `--skip-existing` are never overwritten. \
+    Has no effect if the output is stdout or a special file")]
+    #[arg(long, value_parser = ["always", "never", "prompt"])]
+    pub(crate) overwrite: Option<String>,
     #[arg(help = "Skip special episodes")]
     #[arg(long, default_value_t = false)]
     pub(crate) skip_specials: bool,
+    #[arg(
+        help = "Additionally download the extras (featured music videos like openings and endings) of series"
+    )]
+    #[arg(
+        long_help = "Additionally download the extras (featured music videos like openings and endings) of series. \
+    Only has an effect if a series url is given. Every extra is downloaded as its own video, named after its title"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) include_extras: bool,
+    #[arg(help = "Store trailers and previews in a `Trailers/` folder next to the episodes")]
+    #[arg(
+        long_help = "Store trailers and previews in a `Trailers/` folder next to the episodes. \
+    Crunchyroll lists them as special episodes, an episode is treated as trailer if its episode number or title contains 'Trailer', 'Teaser', 'Preview' or 'PV'. \
+    Trailers are also downloaded if `--skip-specials` is set. \
+    Has no effect if the output is stdout or a special file"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) download_trailers: bool,
 
     #[arg(help = "Includes chapters (e.g. intro, credits, ...)")]
     #[arg(long_help = "Includes chapters (e.g. intro, credits, ...). \
@@ -125,43 +324,445 @@ pub struct Download {
     Also chapters aren't always available, so in this case, just a big 'Episode' chapter from start to end will be created")]
     #[arg(long, default_value_t = false)]
     pub(crate) include_chapters: bool,
+    #[arg(
+        help = "Only include the given chapter types. Implies `--include-chapters`. Valid types are: recap, intro, credits, preview"
+    )]
+    #[arg(long, value_delimiter = ',', value_parser = ["recap", "intro", "credits", "preview"])]
+    pub(crate) chapters: Vec<String>,
+    #[arg(help = "Do not fill the gaps between chapters with 'Episode' chapters")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) no_gap_chapters: bool,
+    #[arg(help = "Do not include chapters. This is the default")]
+    #[arg(long_help = "Do not include chapters. \
+    This is the default, the flag exists to explicitly disable chapters (e.g. in a shell alias). \
+    Conflicts with `--include-chapters` and `--chapters`")]
+    #[arg(long, default_value_t = false)]
+    #[arg(conflicts_with_all = ["include_chapters", "chapters"])]
+    pub(crate) no_chapters: bool,
+    #[arg(help = "Embed the episode / movie thumbnail as cover art")]
+    #[arg(long_help = "Embed the episode / movie thumbnail as cover art. \
+    mkv files get it as `cover.jpg` attachment, mp4 / mov files as attached picture. \
+    Other containers (and mp4 / mov files with hardsubs) can't carry it, so it's skipped in this case")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) thumbnail: bool,
+
+    #[arg(help = "Write a .nfo metadata file next to every downloaded video")]
+    #[arg(
+        long_help = "Write a .nfo metadata file next to every downloaded video. \
+    The file has the same name as the video and contains the title, season and episode number, air date, description and series title. \
+    Media servers like Jellyfin or Kodi use these files to identify the video without having to scrape any metadata. \
+    Has no effect if the output is stdout or a special file"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) write_nfo: bool,
+    #[arg(help = "Write a .txt file with the description next to every downloaded video")]
+    #[arg(
+        long_help = "Write a .txt file with the description next to every downloaded video. \
+    The file has the same name as the video and contains the title, series name, season and episode number, air date and description as plain text. \
+    Independent of `--write-nfo`. \
+    Has no effect if the output is stdout or a special file"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) write_description: bool,
+    #[arg(help = "Write a .json file with all resolved metadata next to every downloaded video")]
+    #[arg(
+        long_help = "Write a .json file with all resolved metadata next to every downloaded video. \
+    The file has the same name as the video and contains the ids, titles, season and episode number, audio and subtitle locales, resolution, duration and skip events (e.g. intro or credits) of the video. \
+    Has no effect if the output is stdout or a special file"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) metadata_json: bool,
+    #[arg(
+        help = "Additionally write subtitles as separate files next to the video, named by the given template"
+    )]
+    #[arg(
+        long_help = "Additionally write subtitles as separate files next to the video. \
+    The given template is inserted between the video name and the subtitle extension, e.g. '{iso639_2}.{forced}' results in 'video.eng.forced.ass'. \
+    The following tokens are available:\n  \
+      {locale}   → Subtitle language (respects '--language-tagging')\n  \
+      {iso639_2} → Three letter ISO 639-2 code of the subtitle language\n  \
+      {cc}       → 'cc' if the subtitle is a closed caption, empty otherwise\n  \
+      {forced}   → 'forced' if the subtitle has the forced flag (closed captions), empty otherwise\n\
+    Has no effect if the output is stdout or a special file"
+    )]
+    #[arg(long)]
+    pub(crate) subtitle_filename_template: Option<String>,
+    #[arg(
+        help = "Additionally write subtitles as separate files next to the video in the given format. Valid formats are 'ass', 'vtt' and 'srt'"
+    )]
+    #[arg(
+        long_help = "Additionally write subtitles as separate files next to the video in the given format, regardless if they're also embedded into the video. \
+    Valid formats are 'ass', 'vtt' (e.g. for web players) and 'srt'. \
+    The files are named after the video with '--subtitle-filename-template' inserted before the extension, which defaults to '{locale}.{cc}' (e.g. 'video.en-US.cc.vtt') if not given. \
+    Has no effect if the output is stdout or a special file"
+    )]
+    #[arg(long, value_parser = ["ass", "vtt", "srt"])]
+    pub(crate) write_subs: Option<String>,
+    #[arg(
+        help = "Shift all subtitle timings by the given milliseconds. Can be set per language with <LANG>=<MS>"
+    )]
+    #[arg(
+        long_help = "Shift all subtitle timings by the given milliseconds (negative values shift them to the front). \
+    To only shift subtitles of a specific language, use <LANG>=<MS> (e.g. 'en-US=-500'). \
+    Can be used multiple times, a language specific offset takes precedence over a global one. \
+    Timings which would be negative after shifting are set to 0"
+    )]
+    #[arg(long, allow_hyphen_values = true, value_parser = crate::utils::clap::clap_parse_subtitle_offset)]
+    pub(crate) subtitle_offset: Vec<(Option<Locale>, TimeDelta)>,
+    #[arg(help = "Override the subtitle styles with the styles of the given .ass file")]
+    #[arg(
+        long_help = "Override the styles of every subtitle with the styles in the `[V4+ Styles]` section of the given .ass file (e.g. created with Aegisub). \
+    A style of a subtitle is replaced if the file contains a style with the same name (e.g. 'Default'), all other styles are left untouched. \
+    Useful to enforce a consistent font, size or outline"
+    )]
+    #[arg(long)]
+    pub(crate) subtitle_style: Option<PathBuf>,
+    #[arg(help = "Keep the partially written output file if ffmpeg fails")]
+    #[arg(long_help = "Keep the partially written output file if ffmpeg fails. \
+    By default, it is deleted so that it isn't mistaken as a complete download. Useful for debugging")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) keep_partial: bool,
+    #[arg(help = "Copy the temporary files of every download to the given directory")]
+    #[arg(
+        long_help = "Copy the temporary files of every download (raw video, audio and subtitle streams, chapters, ...) to the given directory. \
+    They're copied after ffmpeg has run, regardless if it was successful or not, and are prefixed with the output file name. Useful for debugging"
+    )]
+    #[arg(long)]
+    pub(crate) keep_temp: Option<PathBuf>,
+    #[arg(help = "Abort if a video isn't available instead of skipping it")]
+    #[arg(
+        long_help = "Abort if a video isn't available (e.g. because it's region locked) instead of skipping it. \
+    By default, unavailable videos are skipped and listed at the end"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) fail_fast: bool,
+    #[arg(help = "Add a global metadata tag to the output file. Must be provided as 'KEY=VALUE'")]
+    #[arg(
+        long_help = "Add a global metadata tag to the output file. Must be provided as 'KEY=VALUE' (e.g. 'COMMENT=my comment'). \
+    Can be used multiple times to set multiple tags. \
+    mkv files accept any key and store them as matroska tags, other containers (like mp4) only support a specific set of keys (e.g. 'title', 'date', 'comment') and drop all others"
+    )]
+    #[arg(long = "metadata", value_parser = crate::utils::clap::clap_parse_metadata)]
+    pub(crate) container_metadata: Vec<(String, String)>,
+
+    #[arg(
+        help = "Verify that all streams are present in the output file and that it isn't truncated"
+    )]
+    #[arg(
+        long_help = "Verify that all streams are present in the output file and that it isn't truncated. \
+    After the output file is generated, ffprobe is used to check if every video, audio and subtitle stream is present and not empty, and if the length of the file matches the length of the video. \
+    Fails and deletes the output file if a stream is missing or empty or the file is truncated. \
+    Has no effect if the output is stdout or a special file"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) verify_output: bool,
 
     #[arg(help = "Skip any interactive input")]
     #[arg(short, long, default_value_t = false)]
     pub(crate) yes: bool,
 
+    #[arg(help = "Print the download progress as json instead of drawing progress bars")]
+    #[arg(
+        long_help = "Print the download progress as json instead of drawing progress bars. \
+    Every progress update is written as a single json object per line to stderr, e.g. {\"stage\":\"download\",\"message\":\"Downloading video #1\",\"current\":123,\"total\":456,\"bytes_per_sec\":789}. \
+    Useful if the output is parsed by another program"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) progress_json: bool,
+    #[arg(help = "Read the ffmpeg progress from a regular file instead of a named pipe")]
+    #[arg(
+        long_help = "Read the ffmpeg progress from a regular file instead of a named pipe. \
+    On some ffmpeg builds or platforms, writing the progress to a named pipe doesn't work. \
+    This is detected if no progress is received within 10 seconds and the progress file is used automatically then, this flag skips the 10 seconds of waiting. \
+    The progress file is polled, so only use it if the progress doesn't work otherwise"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) no_progress_pipe: bool,
+
+    #[arg(
+        help = "Write the raw stream manifest and segment urls of every video to the given directory"
+    )]
+    #[arg(
+        long_help = "Write the raw stream manifest and segment urls of every video to the given directory. \
+    Useful to debug stream specific issues. \
+    Access tokens in the urls are redacted, use '--no-redact' to keep them"
+    )]
+    #[arg(long)]
+    pub(crate) dump_manifest: Option<PathBuf>,
+    #[arg(help = "Do not redact access tokens when using '--dump-manifest'")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) no_redact: bool,
+
+    #[arg(help = "Only download the audio of a video")]
+    #[arg(long_help = "Only download the audio of a video. \
+    No video, subtitles and chapters are included in the output file. \
+    If '-o'/'--output' is not set, the output file is stored as '.m4a'. \
+    Use '.mp3' as output file extension to re-encode the audio to mp3")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) audio_only: bool,
+    #[arg(help = "Normalize the loudness of the audio")]
+    #[arg(
+        long_help = "Normalize the loudness of the audio via ffmpeg's 'loudnorm' filter. \
+    This requires the audio to be re-encoded (as aac with 192k, unless the ffmpeg preset sets another audio codec), which takes longer and might slightly reduce the audio quality. \
+    Without it, the audio is copied untouched"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) normalize_audio: bool,
+    #[arg(help = "Remove leading and trailing silence of the audio. Requires `--audio-only`")]
+    #[arg(
+        long_help = "Remove leading and trailing silence of the audio via ffmpeg's 'silenceremove' filter, e.g. for soundtracks. \
+    Like `--normalize-audio`, this requires the audio to be re-encoded. \
+    Requires `--audio-only`, as trimming the audio of a video would make it out of sync"
+    )]
+    #[arg(long, default_value_t = false, requires = "audio_only")]
+    pub(crate) trim_silence: bool,
+
+    #[arg(
+        help = "Start the output at the given timestamp. Must be in format of [[HH:]MM:]SS[.mmm]"
+    )]
+    #[arg(
+        long_help = "Start the output at the given timestamp. Must be in format of [[HH:]MM:]SS[.mmm]. \
+    Everything before is cut off. Subtitles and chapters are shifted accordingly"
+    )]
+    #[arg(long, value_parser = crate::utils::clap::clap_parse_timestamp)]
+    pub(crate) start: Option<TimeDelta>,
+    #[arg(help = "End the output at the given timestamp. Must be in format of [[HH:]MM:]SS[.mmm]")]
+    #[arg(
+        long_help = "End the output at the given timestamp. Must be in format of [[HH:]MM:]SS[.mmm]. \
+    Everything after is cut off. The timestamp is relative to the original video, not to '--start'"
+    )]
+    #[arg(long, value_parser = crate::utils::clap::clap_parse_timestamp)]
+    pub(crate) end: Option<TimeDelta>,
+
     #[arg(help = "Force subtitles to be always burnt-in")]
     #[arg(long, default_value_t = false)]
     pub(crate) force_hardsub: bool,
+    #[arg(help = "The crf value which is used when subtitles are burnt into the video")]
+    #[arg(
+        long_help = "The crf (constant rate factor) value which is used when subtitles are burnt into the video. \
+    Burning subtitles into the video requires to re-encode it, a lower value results in a better quality but a bigger file. \
+    Has no effect if the subtitles are not burnt-in or if `--ffmpeg-preset` already sets the quality"
+    )]
+    #[arg(long, default_value_t = 23)]
+    pub(crate) hardsub_crf: u8,
+    #[arg(
+        help = "The encoder preset (e.g. 'slow' or 'veryfast') which is used when subtitles are burnt into the video"
+    )]
+    #[arg(
+        long_help = "The encoder preset (e.g. 'slow' or 'veryfast') which is used when subtitles are burnt into the video. \
+    Slower presets result in a smaller file with the same quality. \
+    Has no effect if the subtitles are not burnt-in"
+    )]
+    #[arg(long)]
+    pub(crate) hardsub_preset: Option<String>,
 
-    #[arg(help = "The number of threads used to download")]
-    #[arg(short, long, default_value_t = num_cpus::get())]
+    #[arg(
+        help = "The number of threads used to download. Use 'auto' to adapt it to the connection"
+    )]
+    #[arg(long_help = "The number of threads used to download. \
+    Use 'auto' to start with 2 threads and adapt the count (max. 16) to the measured segment latency and retry rate: \
+    the thread count is increased as long as the latency stays close to the best one seen, decreased if it rises and halved if segments had to be retried")]
+    #[arg(short, long, default_value_t = num_cpus::get(), value_parser = crate::utils::clap::clap_parse_threads)]
     pub(crate) threads: usize,
 
+    #[arg(
+        help = "Size of the buffer which is used to write downloaded segments to disk. Must be in format of <number>[B|KB|MB]"
+    )]
+    #[arg(
+        long_help = "Size of the buffer which is used to write downloaded segments to disk. Must be in format of <number>[B|KB|MB] (e.g. 512KB or 8MB). \
+    When downloading to slow disks (e.g. USB drives or network shares), writing the segments can slow down the whole download. \
+    A bigger write buffer keeps the segments in memory and writes them in bigger chunks to the disk. \
+    By default, segments are written directly without a buffer"
+    )]
+    #[arg(long, value_parser = crate::utils::clap::clap_parse_size)]
+    pub(crate) write_buffer_size: Option<usize>,
+    #[arg(
+        help = "Write segments which are downloaded out of order to temporary files instead of buffering them in memory"
+    )]
+    #[arg(
+        long_help = "Write segments which are downloaded out of order to temporary files instead of buffering them in memory. \
+    Segments are downloaded in parallel but must be written in order, so if one download thread stalls, all segments downloaded after it are kept in memory until it finishes, which can take up a lot of memory with high bitrate videos. \
+    With this flag, the memory usage stays low at the cost of additional disk writes"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) concurrent_fragment_writes: bool,
+    #[arg(
+        help = "Order in which the download threads fetch the segments of a stream. Valid orders are 'interleaved' and 'sequential'"
+    )]
+    #[arg(
+        long_help = "Order in which the download threads fetch the segments of a stream. \
+    With 'interleaved', every thread downloads the next segment which isn't downloaded yet, so the segments are downloaded (and written) roughly in order. \
+    With 'sequential', the segments are split into one contiguous block per thread, which some CDNs serve faster. \
+    Because the segments must still be written in order, 'sequential' keeps most of the downloaded segments buffered until the first block is finished, combine it with `--concurrent-fragment-writes` to buffer them on disk instead of in memory"
+    )]
+    #[arg(long, default_value = "interleaved", value_parser = ["interleaved", "sequential"])]
+    pub(crate) segment_order: String,
+
+    #[arg(help = "Number of retries if downloading a video segment fails")]
+    #[arg(long_help = "Number of retries if downloading a video segment fails. \
+    If the download of a segment still fails after all retries, the whole download is aborted. \
+    0 disables retries")]
+    #[arg(long, default_value_t = 5)]
+    pub(crate) download_retries: u32,
+    #[arg(help = "Delay in milliseconds before retrying a failed segment download")]
+    #[arg(
+        long_help = "Delay in milliseconds before retrying a failed segment download. \
+    The delay is doubled after every retry (e.g. 500, 1000, 2000, ... with a value of 500). \
+    Useful for rate limited accounts. By default, failed segments are retried immediately"
+    )]
+    #[arg(long, default_value_t = 0)]
+    pub(crate) retry_backoff: u64,
+    #[arg(
+        help = "Timeout in seconds for downloading a single video segment. 0 disables the timeout"
+    )]
+    #[arg(
+        long_help = "Timeout in seconds for downloading a single video segment. \
+    A segment which takes longer is counted as failed and retried, so every timeout uses up one of the `--download-retries`. \
+    Increase it on slow connections where large segments (e.g. of 1080p videos) legitimately take longer. \
+    0 disables the timeout"
+    )]
+    #[arg(long, default_value_t = 60)]
+    pub(crate) segment_timeout: u64,
+
     #[arg(help = "Url(s) to Crunchyroll episodes or series")]
+    #[arg(long_help = "Url(s) to Crunchyroll episodes or series. \
+    A url can be suffixed with '=<output>' (e.g. 'https://www.crunchyroll.com/series/GY8VEQ95Y/darling-in-the-franxx=darling/{title}.mkv') to use another output template than '-o'/'--output' for this url. \
+    This output template is used as it is, '--season-folder' and '--episode-file' are not applied to it")]
     #[arg(required = true)]
     pub(crate) urls: Vec<String>,
+    #[arg(skip)]
+    url_outputs: Vec<Option<String>>,
+    #[arg(skip)]
+    pub(crate) dry_run: bool,
 }
 
 impl Execute for Download {
     fn pre_check(&mut self) -> Result<()> {
+        if self.no_chapters {
+            self.include_chapters = false
+        } else if !self.chapters.is_empty() {
+            self.include_chapters = true
+        }
+        if self.audio_only {
+            // use an audio container if the default output was not changed
+            if self.output == "{title}.mp4" {
+                self.output = "{title}.m4a".to_string()
+            }
+            if self.subtitle.is_some() {
+                warn!("Subtitles are not included if `--audio-only` is set")
+            }
+            if self.audio.len() > 1
+                && Path::new(&self.output)
+                    .extension()
+                    .is_some_and(|ext| ext.to_string_lossy() == "mp3")
+            {
+                bail!("Multiple audio languages cannot be stored in a mp3 file")
+            }
+        }
+
+        if let Some(subtitle_style) = &self.subtitle_style {
+            if !subtitle_style.is_file() {
+                bail!(
+                    "Subtitle style file '{}' does not exist",
+                    subtitle_style.to_string_lossy()
+                )
+            }
+        }
+
+        if !STDOUT_FORMATS.contains(&self.output_stdout_format.as_str()) {
+            bail!(
+                "'{}' is not a supported stdout format. Supported formats are: {}",
+                self.output_stdout_format,
+                STDOUT_FORMATS.join(", ")
+            )
+        }
+
+        if let (Some(start), Some(end)) = (&self.start, &self.end) {
+            if end <= start {
+                bail!("`--end` must be after `--start`")
+            }
+        }
+
+        if self.plex {
+            self.output = PLEX_OUTPUT_TEMPLATE.to_string();
+            self.universal_output = true
+        }
+        if let Some(episode_file) = &self.episode_file {
+            if Path::new(episode_file)
+                .parent()
+                .is_some_and(|p| !p.as_os_str().is_empty())
+            {
+                bail!("`--episode-file` must not contain a folder, use `--season-folder` instead")
+            }
+            self.output.clone_from(episode_file)
+        }
+        if let Some(season_folder) = &self.season_folder {
+            if self.output != "-" && !is_special_file(&self.output) {
+                self.output = Path::new(season_folder)
+                    .join(&self.output)
+                    .to_string_lossy()
+                    .to_string()
+            }
+            if let Some(output_specials) = &mut self.output_specials {
+                if output_specials.as_str() != "-" && !is_special_file(output_specials.as_str()) {
+                    *output_specials = Path::new(season_folder)
+                        .join(output_specials.as_str())
+                        .to_string_lossy()
+                        .to_string()
+                }
+            }
+        }
+
+        check_path_template(&self.output)?;
+        if let Some(output_specials) = &self.output_specials {
+            check_path_template(output_specials)?
+        }
+
         if !has_ffmpeg() {
-            bail!("FFmpeg is needed to run this command")
+            bail!(
+                "FFmpeg is needed to run this command but '{}' could not be executed. Install it or set its path via `--ffmpeg-path` / the `CRUNCHY_CLI_FFMPEG` env variable",
+                ffmpeg_path().to_string_lossy()
+            )
         } else if Path::new(&self.output)
             .extension()
             .unwrap_or_default()
             .is_empty()
+            && self.container.is_none()
             && !is_special_file(&self.output)
             && self.output != "-"
         {
-            bail!("No file extension found. Please specify a file extension (via `-o`) for the output file")
+            bail!("No file extension found. Please specify a file extension (via `-o`) or a container (via `--container`) for the output file")
+        }
+
+        if let Some(container) = &self.container {
+            for output in [Some(&self.output), self.output_specials.as_ref()]
+                .into_iter()
+                .flatten()
+                .filter(|o| !is_special_file(o) && *o != "-")
+            {
+                let ext = Path::new(output)
+                    .extension()
+                    .unwrap_or_default()
+                    .to_string_lossy();
+                if ext != container.as_str() {
+                    warn!(
+                        "The extension of the output file ('{}') doesn't match the container given via `--container` ('{}'). The file is written as {}",
+                        ext, container, container
+                    )
+                }
+            }
         }
 
         if self.subtitle.is_some() {
-            if let Some(ext) = Path::new(&self.output).extension() {
+            if let Some(ext) = self
+                .container
+                .as_ref()
+                .map(OsStr::new)
+                .or_else(|| Path::new(&self.output).extension())
+            {
                 if self.force_hardsub {
                     warn!("Hardsubs are forced. Adding subtitles may take a while")
-                } else if !["mkv", "mov", "mp4"].contains(&ext.to_string_lossy().as_ref()) {
+                } else if !SOFTSUB_CONTAINERS.contains(&ext.to_string_lossy().as_ref()) {
                     warn!("Detected a container which does not support softsubs. Adding subtitles may take a while")
                 }
             }
@@ -172,34 +773,83 @@ impl Execute for Download {
                 .extension()
                 .unwrap_or_default()
                 .is_empty()
+                && self.container.is_none()
                 && !is_special_file(special_output)
                 && special_output != "-"
             {
-                bail!("No file extension found. Please specify a file extension (via `--output-specials`) for the output file")
+                bail!("No file extension found. Please specify a file extension (via `--output-specials`) or a container (via `--container`) for the output file")
             }
-            if let Some(ext) = Path::new(special_output).extension() {
+            if let Some(ext) = self
+                .container
+                .as_ref()
+                .map(OsStr::new)
+                .or_else(|| Path::new(special_output).extension())
+            {
                 if self.force_hardsub {
                     warn!("Hardsubs are forced for special episodes. Adding subtitles may take a while")
-                } else if !["mkv", "mov", "mp4"].contains(&ext.to_string_lossy().as_ref()) {
+                } else if !SOFTSUB_CONTAINERS.contains(&ext.to_string_lossy().as_ref()) {
                     warn!("Detected a container which does not support softsubs. Adding subtitles for special episodes may take a while")
                 }
             }
         }
 
+        if let Some(FFmpegPreset::Predefined(codec @ (FFmpegCodec::H264 | FFmpegCodec::H265), ..)) =
+            &self.ffmpeg_preset
+        {
+            if [Some(&self.output), self.output_specials.as_ref()]
+                .into_iter()
+                .flatten()
+                .any(|o| Path::new(o).extension().unwrap_or_default() == "webm")
+            {
+                bail!(
+                    "WebM only supports vp9 and av1 video, the ffmpeg preset codec {} cannot be used",
+                    codec
+                )
+            }
+        }
+
+        if self.skip_existing_size && !self.skip_existing {
+            warn!("`--skip-existing-size` has no effect if `--skip-existing` is not set")
+        }
+
+        self.url_outputs.clear();
+        for url in self.urls.iter_mut() {
+            let (split_url, url_output) = split_url_output(url.as_str());
+            if let Some(url_output) = &url_output {
+                check_path_template(url_output)?;
+                if Path::new(url_output)
+                    .extension()
+                    .unwrap_or_default()
+                    .is_empty()
+                    && !is_special_file(url_output)
+                    && url_output != "-"
+                {
+                    bail!(
+                        "No file extension found. Please specify a file extension for the output of url {}",
+                        split_url
+                    )
+                }
+            }
+            *url = split_url;
+            self.url_outputs.push(url_output)
+        }
+
         if let Some(language_tagging) = &self.language_tagging {
-            self.audio = resolve_locales(&[self.audio.clone()]).remove(0);
-            self.subtitle = self
-                .subtitle
-                .as_ref()
-                .map(|s| resolve_locales(&[s.clone()]).remove(0));
-            self.output_audio_locale = language_tagging.for_locale(&self.audio);
+            self.audio = resolve_locales(&self.audio, self.strict_locale_match)?;
+            self.subtitle = match &self.subtitle {
+                Some(subtitle) => {
+                    Some(resolve_locales(&[subtitle.clone()], self.strict_locale_match)?.remove(0))
+                }
+                None => None,
+            };
+            self.output_audio_locales = language_tagging.convert_locales(&self.audio);
             self.output_subtitle_locale = self
                 .subtitle
                 .as_ref()
                 .map(|s| language_tagging.for_locale(s))
                 .unwrap_or_default()
         } else {
-            self.output_audio_locale = self.audio.to_string();
+            self.output_audio_locales = self.audio.iter().map(|l| l.to_string()).collect();
             self.output_subtitle_locale = self
                 .subtitle
                 .as_ref()
@@ -207,6 +857,22 @@ impl Execute for Download {
                 .unwrap_or_default();
         }
 
+        if let Some(locale_map) = &self.locale_map {
+            let locale_map = LocaleMap::read(locale_map)?;
+            for (locale, output) in zip(&self.audio, &mut self.output_audio_locales) {
+                if let Some(tag) = locale_map.audio.get(locale) {
+                    output.clone_from(tag)
+                }
+            }
+            if let Some(tag) = self
+                .subtitle
+                .as_ref()
+                .and_then(|s| locale_map.subtitle.get(s))
+            {
+                self.output_subtitle_locale.clone_from(tag)
+            }
+        }
+
         Ok(())
     }
 
@@ -214,28 +880,10 @@ impl Execute for Download {
         if !ctx.crunchy.premium().await {
             warn!("You may not be able to download all requested videos when logging in anonymously or using a non-premium account")
         }
+        let download_stats = DownloadStats::start();
 
         let mut parsed_urls = vec![];
 
-        let output_supports_softsubs = SOFTSUB_CONTAINERS.contains(
-            &Path::new(&self.output)
-                .extension()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .as_ref(),
-        );
-        let special_output_supports_softsubs = if let Some(so) = &self.output_specials {
-            SOFTSUB_CONTAINERS.contains(
-                &Path::new(so)
-                    .extension()
-                    .unwrap_or_default()
-                    .to_string_lossy()
-                    .as_ref(),
-            )
-        } else {
-            output_supports_softsubs
-        };
-
         for (i, url) in self.urls.clone().into_iter().enumerate() {
             let progress_handler = progress!("Parsing url {}", i + 1);
             match parse_url(&ctx.crunchy, url.clone(), true).await {
@@ -247,24 +895,77 @@ impl Execute for Download {
             };
         }
 
+        let mut skipped = vec![];
         for (i, (media_collection, url_filter)) in parsed_urls.into_iter().enumerate() {
+            // the output which was specified via `<url>=<output>` takes precedence over `--output`
+            let output = self.url_outputs[i]
+                .clone()
+                .unwrap_or_else(|| self.output.clone());
+
+            // a container given via `--container` takes precedence over the file extension, like
+            // in the downloader itself
+            let supports_softsubs = |output: &str| {
+                let container = match &self.container {
+                    Some(container) if !is_special_file(output) && output != "-" => {
+                        container.clone()
+                    }
+                    _ => Path::new(output)
+                        .extension()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string(),
+                };
+                SOFTSUB_CONTAINERS.contains(&container.as_str())
+            };
+            let output_supports_softsubs = supports_softsubs(&output);
+            let special_output_supports_softsubs = if let Some(so) = &self.output_specials {
+                supports_softsubs(so)
+            } else {
+                output_supports_softsubs
+            };
+
+            if self.list_audios || self.list_subtitles {
+                let progress_handler = progress!("Fetching series details");
+                let single_format_collection = Filter::new(
+                    url_filter,
+                    Locale::all(),
+                    vec![],
+                    // all available locales are listed, so missing ones are irrelevant
+                    |_, _| Ok(true),
+                    |_, _| Ok(true),
+                    |_| Ok(()),
+                    false,
+                    !self.yes,
+                    self.skip_specials,
+                    ctx.crunchy.premium().await,
+                )
+                .include_extras(self.include_extras)
+                .visit(media_collection)
+                .await?;
+                progress_handler.stop(format!("Loaded series information for url {}", i + 1));
+
+                list_locales(single_format_collection, self.list_subtitles).await?;
+                continue;
+            }
+
             let progress_handler = progress!("Fetching series details");
             let single_format_collection = Filter::new(
                 url_filter,
-                vec![self.audio.clone()],
+                self.audio.clone(),
                 self.subtitle.as_ref().map_or(vec![], |s| vec![s.clone()]),
                 |scope, locales| {
+                    let audios = locales.into_iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", ");
                     match scope {
-                        FilterMediaScope::Series(series) => bail!("Series {} is not available with {} audio", series.title, locales[0]),
+                        FilterMediaScope::Series(series) => bail!("Series {} is not available with {} audio", series.title, audios),
                         FilterMediaScope::Season(season) => {
-                            error!("Season {} is not available with {} audio", season.season_number, locales[0]);
+                            error!("Season {} is not available with {} audio", season.season_number, audios);
                             Ok(false)
                         }
                         FilterMediaScope::Episode(episodes) => {
                             if episodes.len() == 1 {
-                                warn!("Episode {} of season {} is not available with {} audio", episodes[0].sequence_number, episodes[0].season_title, locales[0])
+                                warn!("Episode {} of season {} is not available with {} audio", episodes[0].sequence_number, episodes[0].season_title, audios)
                             } else if episodes.len() == 2 {
-                                warn!("Season {} is only available with {} audio from episode {} to {}", episodes[0].season_number, locales[0], episodes[0].sequence_number, episodes[1].sequence_number)
+                                warn!("Season {} is only available with {} audio from episode {} to {}", episodes[0].season_number, audios, episodes[0].sequence_number, episodes[1].sequence_number)
                             } else {
                                 unimplemented!()
                             }
@@ -295,11 +996,13 @@ impl Execute for Download {
                     warn!("Skipping premium episodes in season {season}");
                     Ok(())
                 },
-                Format::has_relative_fmt(&self.output),
+                Format::has_relative_fmt(&output),
                 !self.yes,
                 self.skip_specials,
                 ctx.crunchy.premium().await,
             )
+            .include_extras(self.include_extras)
+            .include_trailers(self.download_trailers)
             .visit(media_collection)
             .await?;
 
@@ -310,93 +1013,300 @@ impl Execute for Download {
             progress_handler.stop(format!("Loaded series information for url {}", i + 1));
 
             single_format_collection.full_visual_output();
+            if self.download_trailers && !single_format_collection.has_trailers() {
+                info!("Url {} has no trailers", i + 1)
+            }
+
+            if self.list_formats {
+                for single_formats in single_format_collection.into_iter() {
+                    for single_format in single_formats {
+                        if single_format.is_episode() {
+                            println!(
+                                ":: S{:02}E{:0>2} - {} ({} audio)",
+                                single_format.season_number,
+                                single_format.episode_number,
+                                single_format.title,
+                                single_format.audio
+                            )
+                        } else {
+                            println!(":: {} ({} audio)", single_format.title, single_format.audio)
+                        }
+                        let stream = single_format.stream().await?;
+                        print_stream_variants(&stream, &single_format.audio).await?;
+                        stream.invalidate().await?
+                    }
+                }
+                continue;
+            }
 
             let download_builder =
                 DownloadBuilder::new(ctx.client.clone(), ctx.rate_limiter.clone())
                     .default_subtitle(self.subtitle.clone())
                     .force_hardsub(self.force_hardsub)
-                    .output_format(if is_special_file(&self.output) || self.output == "-" {
-                        Some("mpegts".to_string())
+                    .hardsub_crf(self.hardsub_crf)
+                    .hardsub_preset(self.hardsub_preset.clone())
+                    .output_format(if is_special_file(&output) || output == "-" {
+                        Some(self.output_stdout_format.clone())
                     } else {
                         None
                     })
+                    .container(
+                        self.container
+                            .clone()
+                            .filter(|_| !is_special_file(&output) && output != "-"),
+                    )
                     .ffmpeg_preset(self.ffmpeg_preset.clone().unwrap_or_default())
                     .ffmpeg_threads(self.ffmpeg_threads)
+                    .pix_fmt(self.pix_fmt.clone())
                     .threads(self.threads)
-                    .audio_locale_output_map(HashMap::from([(
-                        self.audio.clone(),
-                        self.output_audio_locale.clone(),
-                    )]))
+                    .write_buffer_size(self.write_buffer_size)
+                    .concurrent_fragment_writes(self.concurrent_fragment_writes)
+                    .segment_order(self.segment_order.clone())
+                    .download_retries(self.download_retries)
+                    .download_stats(Some(download_stats.clone()))
+                    .retry_backoff(self.retry_backoff)
+                    .segment_timeout(self.segment_timeout)
+                    .progress_json(self.progress_json)
+                    .progress_pipe(!self.no_progress_pipe)
+                    .verify_output(self.verify_output)
+                    .subtitle_filename_template(self.subtitle_filename_template.clone())
+                    .subtitle_sidecar_format(self.write_subs.clone())
+                    .subtitle_offset(self.subtitle_offset.clone())
+                    .subtitle_style(self.subtitle_style.clone())
+                    .keep_partial(self.keep_partial)
+                    .keep_temp(self.keep_temp.clone())
+                    .chapter_types(self.chapters.clone())
+                    .gap_chapters(!self.no_gap_chapters)
+                    .container_metadata(self.container_metadata.clone())
+                    .dry_run(self.dry_run)
+                    .trim_start(self.start)
+                    .trim_end(self.end)
+                    .audio_only(self.audio_only)
+                    .normalize_audio(self.normalize_audio)
+                    .trim_silence(self.trim_silence)
+                    .audio_locale_output_map(
+                        zip(self.audio.clone(), self.output_audio_locales.clone()).collect(),
+                    )
                     .subtitle_locale_output_map(
                         self.subtitle.as_ref().map_or(HashMap::new(), |s| {
                             HashMap::from([(s.clone(), self.output_subtitle_locale.clone())])
                         }),
                     );
 
+            let mut part_collector = PartCollector::default();
             for mut single_formats in single_format_collection.into_iter() {
-                // the vec contains always only one item
-                let single_format = single_formats.remove(0);
-
-                let (download_format, format) = get_format(
+                if part_collector.skip_part(single_formats[0].part) {
+                    debug!(
+                        "Skipping part {} of {} as its first part was skipped",
+                        single_formats[0].part.unwrap().0,
+                        single_formats[0].title
+                    );
+                    continue;
+                }
+                // the first item is the version with the first requested audio, all following items
+                // are versions with the other requested audios
+                let (download_format, format) = match get_format(
+                    &ctx,
                     &self,
-                    &single_format,
-                    if self.force_hardsub {
+                    &single_formats,
+                    if self.audio_only {
+                        false
+                    } else if self.force_hardsub {
                         true
-                    } else if single_format.is_special() {
+                    } else if single_formats[0].is_special() {
                         !special_output_supports_softsubs
                     } else {
                         !output_supports_softsubs
                     },
                 )
-                .await?;
+                .await
+                {
+                    Ok(format) => format,
+                    Err(e) if !self.fail_fast && e.is::<UnavailableError>() => {
+                        let name = format!(
+                            "{} ({})",
+                            single_formats[0].title, single_formats[0].episode_id
+                        );
+                        warn!("Skipping {}: {}", name, e);
+                        skipped.push((name, e.to_string()));
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                let single_format = single_formats.remove(0);
 
                 let mut downloader = download_builder.clone().build();
+                let stream_metadata = self
+                    .metadata_json
+                    .then(|| StreamMetadata::new(std::slice::from_ref(&download_format)));
                 downloader.add_format(download_format);
 
-                let formatted_path = if format.is_special() {
-                    format.format_path(
-                        self.output_specials
-                            .as_ref()
-                            .map_or((&self.output).into(), |so| so.into()),
-                        self.universal_output,
-                        self.language_tagging.as_ref(),
-                    )
+                if let Some(max_filesize) = self.max_filesize {
+                    let estimated_size = downloader.estimated_file_size();
+                    if estimated_size > max_filesize {
+                        let message = format!(
+                            "estimated file size ({}) exceeds the maximal file size ({})",
+                            format_size(estimated_size),
+                            format_size(max_filesize)
+                        );
+                        if self.max_filesize_policy == "error" {
+                            bail!(
+                                "{} ({}): {}",
+                                single_format.title,
+                                single_format.episode_id,
+                                message
+                            )
+                        }
+                        warn!(
+                            "Skipping {} ({}): {}",
+                            single_format.title, single_format.episode_id, message
+                        );
+                        continue;
+                    }
+                }
+
+                // all parts of a video which is split into multiple parts are stored in the output
+                // file of the first part
+                let path = if let Some(path) = part_collector.following_dst(single_format.part) {
+                    path
                 } else {
-                    format.format_path(
-                        (&self.output).into(),
-                        self.universal_output,
-                        self.language_tagging.as_ref(),
-                    )
-                };
-                let (path, changed) = free_file(formatted_path.clone());
+                    let formatted_path = if format.is_special() {
+                        format.format_path(
+                            self.output_specials
+                                .as_ref()
+                                .map_or((&output).into(), |so| so.into()),
+                            self.universal_output,
+                            self.episode_naming_from_metadata,
+                            self.language_tagging.as_ref(),
+                        )
+                    } else {
+                        format.format_path(
+                            (&output).into(),
+                            self.universal_output,
+                            self.episode_naming_from_metadata,
+                            self.language_tagging.as_ref(),
+                        )
+                    };
+                    let formatted_path = if self.download_trailers && single_format.is_trailer() {
+                        trailer_path(formatted_path)
+                    } else {
+                        formatted_path
+                    };
+                    let (mut path, changed) = free_file(formatted_path.clone());
 
-                if changed && self.skip_existing {
-                    debug!(
-                        "Skipping already existing file '{}'",
-                        formatted_path.to_string_lossy()
-                    );
-                    continue;
-                }
+                    if changed && self.skip_existing {
+                        if is_existing_file(
+                            &formatted_path,
+                            self.skip_existing_size
+                                .then(|| downloader.min_existing_file_size()),
+                        ) {
+                            debug!(
+                                "Skipping already existing file '{}'",
+                                formatted_path.to_string_lossy()
+                            );
+                            continue;
+                        }
+                        // the existing file is empty or smaller than expected, probably because a
+                        // previous download was aborted
+                        debug!(
+                            "Overwriting incomplete file '{}'",
+                            formatted_path.to_string_lossy()
+                        );
+                        path.clone_from(&formatted_path)
+                    } else if let (true, Some(overwrite)) = (changed, &self.overwrite) {
+                        if !should_overwrite(&formatted_path, overwrite) {
+                            info!(
+                                "Skipping already existing file '{}'",
+                                formatted_path.to_string_lossy()
+                            );
+                            continue;
+                        }
+                        path.clone_from(&formatted_path)
+                    }
+                    path
+                };
 
+                part_collector.start_part(single_format.part);
                 format.visual_output(&path);
 
-                downloader.download(&path).await?
+                if let Some(part) = single_format.part.filter(|_| {
+                    !self.dry_run && path.to_string_lossy() != "-" && !is_special_file(&path)
+                }) {
+                    // continue until all parts are downloaded and concatenated
+                    if !part_collector
+                        .download(downloader, &format.title, part, &path)
+                        .await?
+                    {
+                        continue;
+                    }
+                } else {
+                    downloader.download(&path).await?;
+                }
+
+                if self.write_nfo
+                    && !self.dry_run
+                    && path.to_string_lossy() != "-"
+                    && !is_special_file(&path)
+                {
+                    let nfo_path = write_nfo(&format, single_format.is_movie(), &path)?;
+                    debug!("Wrote nfo file '{}'", nfo_path.to_string_lossy())
+                }
+                if self.write_description
+                    && !self.dry_run
+                    && path.to_string_lossy() != "-"
+                    && !is_special_file(&path)
+                {
+                    let description_path =
+                        write_description(&format, single_format.is_movie(), &path)?;
+                    debug!(
+                        "Wrote description file '{}'",
+                        description_path.to_string_lossy()
+                    )
+                }
+                if let Some(stream_metadata) = stream_metadata.as_ref().filter(|_| {
+                    !self.dry_run && path.to_string_lossy() != "-" && !is_special_file(&path)
+                }) {
+                    let json_path = write_metadata_json(
+                        &format,
+                        single_format.is_movie(),
+                        stream_metadata,
+                        &path,
+                    )?;
+                    debug!("Wrote metadata json file '{}'", json_path.to_string_lossy())
+                }
             }
+            part_collector.finish()?;
         }
 
-        Ok(())
+        download_stats.log_summary();
+        report_skipped(&skipped)
+    }
+}
+
+/// Moves `path` into a `Trailers/` folder in the directory of `path`. Stdout and special files are
+/// returned unchanged.
+fn trailer_path(path: PathBuf) -> PathBuf {
+    if path.to_string_lossy() == "-" || is_special_file(&path) {
+        return path;
     }
+    let Some(file_name) = path.file_name().map(|f| f.to_owned()) else {
+        return path;
+    };
+    path.with_file_name("Trailers").join(file_name)
 }
 
 async fn get_format(
+    ctx: &Context,
     download: &Download,
-    single_format: &SingleFormat,
+    single_formats: &[SingleFormat],
     try_peer_hardsubs: bool,
 ) -> Result<(DownloadFormat, Format)> {
+    let single_format = &single_formats[0];
     let stream = single_format.stream().await?;
     let Some((video, audio, contains_hardsub)) = stream_data_from_stream(
         &stream,
         &download.resolution,
+        download.min_bitrate,
         if try_peer_hardsubs {
             download.subtitle.clone()
         } else {
@@ -424,10 +1334,26 @@ async fn get_format(
         }
     };
 
+    if let Some(dump_dir) = download
+        .dump_manifest
+        .as_ref()
+        .filter(|_| !download.dry_run)
+    {
+        dump_manifest(
+            ctx,
+            &stream,
+            &[&video, &audio],
+            &format!("{}_{}", single_format.episode_id, single_format.audio),
+            dump_dir,
+            !download.no_redact,
+        )
+        .await?
+    }
+
     let subtitle = if contains_hardsub {
         None
     } else if let Some(subtitle_locale) = &download.subtitle {
-        if download.audio == Locale::ja_JP {
+        if single_format.audio == Locale::ja_JP {
             stream
                 .subtitles
                 .get(subtitle_locale)
@@ -444,34 +1370,99 @@ async fn get_format(
     } else {
         None
     };
+    if let (Some(subtitle_locale), None, false) = (&download.subtitle, &subtitle, contains_hardsub)
+    {
+        warn!(
+            "{} subtitles are not available for {} ({}), downloading it without subtitles",
+            subtitle_locale,
+            single_format.source_type(),
+            single_format.title
+        )
+    }
+
+    let subtitles = subtitle.map_or(vec![], |s| {
+        vec![(
+            s,
+            single_format.audio != Locale::ja_JP && stream.subtitles.len() == 1,
+        )]
+    });
+
+    // only the audio is taken from all versions except the first one
+    let mut audios = vec![(audio, single_format.audio.clone())];
+    let mut format_pairs = vec![(single_format.clone(), video.clone(), subtitles.clone())];
+    for audio_format in &single_formats[1..] {
+        let audio_stream = audio_format.stream().await?;
+        let stream_data = match stream_data_from_stream(
+            &audio_stream,
+            &download.resolution,
+            download.min_bitrate,
+            None,
+        )
+        .await?
+        {
+            None if download.quality_fallback == "per-track" => {
+                warn!(
+                    "Resolution ({}) is not available for the {} audio of {} ({}), using the best available resolution instead",
+                    download.resolution,
+                    audio_format.audio,
+                    audio_format.source_type(),
+                    audio_format.title
+                );
+                stream_data_from_stream(&audio_stream, &BEST_RESOLUTION, download.min_bitrate, None)
+                    .await?
+            }
+            stream_data => stream_data,
+        };
+        let Some((audio_video, audio, _)) = stream_data else {
+            bail!(
+                "Resolution ({}) is not available for the {} audio of {} ({})",
+                download.resolution,
+                audio_format.audio,
+                audio_format.source_type(),
+                audio_format.title
+            )
+        };
+
+        if let Some(dump_dir) = download
+            .dump_manifest
+            .as_ref()
+            .filter(|_| !download.dry_run)
+        {
+            dump_manifest(
+                ctx,
+                &audio_stream,
+                &[&audio],
+                &format!("{}_{}", audio_format.episode_id, audio_format.audio),
+                dump_dir,
+                !download.no_redact,
+            )
+            .await?
+        }
+
+        audios.push((audio, audio_format.audio.clone()));
+        format_pairs.push((audio_format.clone(), audio_video, vec![]));
+        audio_stream.invalidate().await?;
+    }
 
     let download_format = DownloadFormat {
-        video: (video.clone(), single_format.audio.clone()),
-        audios: vec![(audio, single_format.audio.clone())],
-        subtitles: subtitle.clone().map_or(vec![], |s| {
-            vec![(
-                s,
-                single_format.audio != Locale::ja_JP && stream.subtitles.len() == 1,
-            )]
-        }),
+        video: (video, single_format.audio.clone()),
+        audios,
+        subtitles,
         metadata: DownloadFormatMetadata {
             skip_events: if download.include_chapters {
                 single_format.skip_events().await?
             } else {
                 None
             },
+            thumbnail: if download.thumbnail {
+                single_format.thumbnail()
+            } else {
+                None
+            },
+            video_label: None,
         },
     };
-    let mut format = Format::from_single_formats(vec![(
-        single_format.clone(),
-        video,
-        subtitle.map_or(vec![], |s| {
-            vec![(
-                s,
-                single_format.audio != Locale::ja_JP && stream.subtitles.len() == 1,
-            )]
-        }),
-    )]);
+    let mut format = Format::from_single_formats(format_pairs);
     if contains_hardsub {
         let (_, subs) = format.locales.get_mut(0).unwrap();
         subs.push(download.subtitle.clone().unwrap())
@@ -481,3 +1472,52 @@ async fn get_format(
 
     Ok((download_format, format))
 }
+
+/// Print the audio (or subtitle, if `subtitles` is set) locales of all videos in `collection`, one
+/// locale per line.
+async fn list_locales(collection: SingleFormatCollection, subtitles: bool) -> Result<()> {
+    let mut audio_locales: Vec<Locale> = vec![];
+    let mut subtitle_locales: Vec<(Locale, bool)> = vec![];
+    for single_formats in collection.into_iter() {
+        for single_format in single_formats {
+            if !subtitles {
+                if !audio_locales.contains(&single_format.audio) {
+                    audio_locales.push(single_format.audio)
+                }
+                continue;
+            }
+
+            let stream = single_format.stream().await?;
+            for locale in stream
+                .subtitles
+                .keys()
+                .map(|l| (l.clone(), false))
+                .chain(stream.captions.keys().map(|l| (l.clone(), true)))
+            {
+                if !subtitle_locales.contains(&locale) {
+                    subtitle_locales.push(locale)
+                }
+            }
+            stream.invalidate().await?
+        }
+    }
+
+    if subtitles {
+        subtitle_locales
+            .sort_by(|(a, a_cc), (b, b_cc)| a.to_string().cmp(&b.to_string()).then(a_cc.cmp(b_cc)));
+        for (locale, cc) in subtitle_locales {
+            if cc {
+                println!("{} (cc)", locale)
+            } else {
+                println!("{}", locale)
+            }
+        }
+    } else {
+        audio_locales.sort_by_key(|l| l.to_string());
+        for locale in audio_locales {
+            println!("{}", locale)
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,379 @@
+use crate::utils::context::Context;
+use crate::utils::download::{
+    estimate_stream_data_file_size, skip_events_chapters, write_ffmpeg_chapters,
+};
+use crate::utils::filter::Filter;
+use crate::utils::fmt::format_size;
+use crate::utils::format::SingleFormat;
+use crate::utils::log::progress;
+use crate::utils::parse::parse_url;
+use crate::Execute;
+use anyhow::bail;
+use anyhow::Result;
+use chrono::TimeDelta;
+use crunchyroll_rs::media::{SkipEvents, SkipEventsEvent, Stream, StreamData};
+use crunchyroll_rs::Locale;
+use log::info;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, clap::Parser)]
+#[clap(about = "Show the available resolutions, audios and subtitles of videos")]
+#[command(arg_required_else_help(true))]
+pub struct Info {
+    #[arg(help = format!("Audio languages to show. Can be used multiple times. \
+    Available languages are: {}", Locale::all().into_iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", ")))]
+    #[arg(long_help = format!("Audio languages to show. Can be used multiple times. \
+    Available languages are:\n  {}\nIETF tagged language codes for the shown available locales can be used too", Locale::all().into_iter().map(|l| format!("{:<6} → {}", l.to_string(), l.to_human_readable())).collect::<Vec<String>>().join("\n  ")))]
+    #[arg(short, long, default_values_t = Locale::all())]
+    pub(crate) audio: Vec<Locale>,
+
+    #[arg(help = "Skip special episodes")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) skip_specials: bool,
+
+    #[arg(help = "Print the information as json")]
+    #[arg(long_help = "Print the information as json. \
+    Every version contains the audio locale and bandwidth, all available resolutions (with fps, bandwidth and estimated file size) and all available subtitles. \
+    The bandwidth can be used as value for `--min-bitrate` of the download and archive command")]
+    #[arg(long, alias = "print-variants-json", default_value_t = false)]
+    pub(crate) json: bool,
+
+    #[arg(
+        help = "Write the skip events (recap, intro, credits, preview) to a file instead of showing the information"
+    )]
+    #[arg(
+        long_help = "Write the skip events (recap, intro, credits, preview) to a file instead of showing the information. \
+    If the file has a '.json' extension, the start and end (in seconds) of every event of all videos are written as json. \
+    Otherwise, the events are written as ffmpeg metadata chapters, which only works if the url(s) point to a single video. \
+    No streams are fetched in this mode"
+    )]
+    #[arg(long, conflicts_with = "json")]
+    pub(crate) chapters_out: Option<PathBuf>,
+
+    #[arg(help = "Skip any interactive input")]
+    #[arg(short, long, default_value_t = false)]
+    pub(crate) yes: bool,
+
+    #[arg(help = "Url(s) to Crunchyroll episodes or series")]
+    #[arg(required = true)]
+    pub(crate) urls: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct InfoMedia {
+    id: String,
+    #[serde(rename = "type")]
+    source_type: String,
+    title: String,
+    series_id: String,
+    series_name: String,
+    season_id: String,
+    season_number: u32,
+    episode_number: String,
+    sequence_number: f32,
+    versions: Vec<InfoVersion>,
+}
+
+#[derive(Serialize)]
+struct InfoVersion {
+    audio: String,
+    audio_bandwidth: u64,
+    drm: bool,
+    resolutions: Vec<InfoResolution>,
+    subtitles: Vec<InfoSubtitle>,
+}
+
+#[derive(Serialize)]
+struct InfoResolution {
+    width: u64,
+    height: u64,
+    fps: f64,
+    bandwidth: u64,
+    estimated_file_size: u64,
+}
+
+#[derive(Serialize)]
+struct InfoSubtitle {
+    locale: String,
+    cc: bool,
+}
+
+#[derive(Serialize)]
+struct InfoChapters {
+    id: String,
+    title: String,
+    series_name: String,
+    season_number: u32,
+    episode_number: String,
+    duration: f64,
+    recap: Option<InfoChapter>,
+    intro: Option<InfoChapter>,
+    credits: Option<InfoChapter>,
+    preview: Option<InfoChapter>,
+}
+
+#[derive(Serialize)]
+struct InfoChapter {
+    start: f32,
+    end: f32,
+}
+
+impl From<&SkipEventsEvent> for InfoChapter {
+    fn from(value: &SkipEventsEvent) -> Self {
+        Self {
+            start: value.start,
+            end: value.end,
+        }
+    }
+}
+
+impl Execute for Info {
+    async fn execute(self, ctx: Context) -> Result<()> {
+        let mut parsed_urls = vec![];
+
+        for (i, url) in self.urls.clone().into_iter().enumerate() {
+            let progress_handler = progress!("Parsing url {}", i + 1);
+            match parse_url(&ctx.crunchy, url.clone(), true).await {
+                Ok((media_collection, url_filter)) => {
+                    progress_handler.stop(format!("Parsed url {}", i + 1));
+                    parsed_urls.push((media_collection, url_filter))
+                }
+                Err(e) => bail!("url {} could not be parsed: {}", url, e),
+            };
+        }
+
+        let mut json_output = vec![];
+        let mut chapters = vec![];
+
+        for (i, (media_collection, url_filter)) in parsed_urls.into_iter().enumerate() {
+            let progress_handler = progress!("Fetching series details");
+            let single_format_collection = Filter::new(
+                url_filter,
+                self.audio.clone(),
+                vec![],
+                // only the available audios and subtitles are shown, so missing ones are irrelevant
+                |_, _| Ok(true),
+                |_, _| Ok(true),
+                |_| Ok(()),
+                false,
+                !self.yes,
+                self.skip_specials,
+                ctx.crunchy.premium().await,
+            )
+            .visit(media_collection)
+            .await?;
+
+            if single_format_collection.is_empty() {
+                progress_handler.stop(format!("Skipping url {} (no matching videos found)", i + 1));
+                continue;
+            }
+            progress_handler.stop(format!("Loaded series information for url {}", i + 1));
+
+            for single_formats in single_format_collection.into_iter() {
+                if self.chapters_out.is_some() {
+                    let first = single_formats.first().unwrap();
+                    chapters.push((first.clone(), first.skip_events().await?));
+                    continue;
+                }
+
+                let mut versions = vec![];
+                for single_format in &single_formats {
+                    let stream = single_format.stream().await?;
+                    let version = get_version(single_format, &stream).await;
+                    stream.invalidate().await?;
+                    versions.push(version?);
+                }
+
+                let first = single_formats.first().unwrap();
+                let media = InfoMedia {
+                    id: first.episode_id.clone(),
+                    source_type: first.source_type(),
+                    title: first.title.clone(),
+                    series_id: first.series_id.clone(),
+                    series_name: first.series_name.clone(),
+                    season_id: first.season_id.clone(),
+                    season_number: first.season_number,
+                    episode_number: first.episode_number.clone(),
+                    sequence_number: first.sequence_number,
+                    versions,
+                };
+                if self.json {
+                    json_output.push(media)
+                } else {
+                    print_info(first, &media)
+                }
+            }
+        }
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&json_output)?)
+        }
+        if let Some(chapters_out) = &self.chapters_out {
+            write_chapters(chapters_out, chapters)?;
+            info!("Wrote chapters to '{}'", chapters_out.to_string_lossy())
+        }
+
+        Ok(())
+    }
+}
+
+async fn get_version(single_format: &SingleFormat, stream: &Stream) -> Result<InfoVersion> {
+    let Some((mut videos, mut audios)) = stream.stream_data(None).await? else {
+        bail!(
+            "No stream data available for {} {}",
+            single_format.source_type(),
+            single_format.title
+        )
+    };
+    videos.sort_by(|a, b| a.bandwidth.cmp(&b.bandwidth).reverse());
+    audios.sort_by(|a, b| a.bandwidth.cmp(&b.bandwidth).reverse());
+
+    // the best audio is always used when downloading, so its size is added to every resolution
+    let audio_size = audios.first().map_or(0, stream_data_file_size);
+    let resolutions = videos
+        .iter()
+        .map(|video| {
+            let (width, height) = video.resolution().map_or((0, 0), |r| (r.width, r.height));
+            InfoResolution {
+                width,
+                height,
+                fps: video.fps().unwrap_or_default(),
+                bandwidth: video.bandwidth,
+                estimated_file_size: stream_data_file_size(video) + audio_size,
+            }
+        })
+        .collect();
+
+    let mut subtitles: Vec<InfoSubtitle> = stream
+        .subtitles
+        .keys()
+        .map(|locale| InfoSubtitle {
+            locale: locale.to_string(),
+            cc: false,
+        })
+        .chain(stream.captions.keys().map(|locale| InfoSubtitle {
+            locale: locale.to_string(),
+            cc: true,
+        }))
+        .collect();
+    subtitles.sort_by(|a, b| a.locale.cmp(&b.locale).then(a.cc.cmp(&b.cc)));
+
+    Ok(InfoVersion {
+        audio: single_format.audio.to_string(),
+        audio_bandwidth: audios.first().map_or(0, |a| a.bandwidth),
+        drm: videos.iter().chain(audios.iter()).any(|s| s.drm.is_some()),
+        resolutions,
+        subtitles,
+    })
+}
+
+/// Write the skip events of the given videos to `path`. If `path` has a '.json' extension, the
+/// events of all videos are written as json, otherwise they are written as ffmpeg metadata, which
+/// only supports a single video.
+fn write_chapters(path: &Path, chapters: Vec<(SingleFormat, Option<SkipEvents>)>) -> Result<()> {
+    if path.extension().is_some_and(|e| e == "json") {
+        let output: Vec<InfoChapters> = chapters
+            .into_iter()
+            .map(|(single_format, skip_events)| InfoChapters {
+                id: single_format.episode_id,
+                title: single_format.title,
+                series_name: single_format.series_name,
+                season_number: single_format.season_number,
+                episode_number: single_format.episode_number,
+                duration: single_format.duration.num_milliseconds() as f64 / 1000.0,
+                recap: skip_events
+                    .as_ref()
+                    .and_then(|s| s.recap.as_ref().map(|e| e.into())),
+                intro: skip_events
+                    .as_ref()
+                    .and_then(|s| s.intro.as_ref().map(|e| e.into())),
+                credits: skip_events
+                    .as_ref()
+                    .and_then(|s| s.credits.as_ref().map(|e| e.into())),
+                preview: skip_events
+                    .as_ref()
+                    .and_then(|s| s.preview.as_ref().map(|e| e.into())),
+            })
+            .collect();
+        fs::write(path, serde_json::to_string_pretty(&output)?)?;
+        return Ok(());
+    }
+
+    if chapters.len() != 1 {
+        bail!(
+            "Chapters can only be written as ffmpeg metadata for a single video, but {} videos were found. Use a '.json' file to write the chapters of multiple videos",
+            chapters.len()
+        )
+    }
+    let (single_format, skip_events) = &chapters[0];
+    let mut events = skip_events
+        .as_ref()
+        .map_or(vec![], |skip_events| skip_events_chapters(skip_events, &[]));
+    let mut file = fs::File::create(path)?;
+    write_ffmpeg_chapters(
+        &mut file,
+        single_format.duration,
+        TimeDelta::zero(),
+        &mut events,
+        true,
+    )
+}
+
+fn stream_data_file_size(stream_data: &StreamData) -> u64 {
+    estimate_stream_data_file_size(stream_data, &stream_data.segments())
+}
+
+fn print_info(single_format: &SingleFormat, media: &InfoMedia) {
+    if single_format.is_episode() {
+        println!(
+            ":: S{:02}E{:0>2} - {} ({})",
+            media.season_number, media.episode_number, media.title, media.id
+        )
+    } else {
+        println!(":: {} ({})", media.title, media.id)
+    }
+
+    for version in &media.versions {
+        println!(
+            "\tAudio: {}{}",
+            version.audio,
+            if version.drm { " (DRM protected)" } else { "" }
+        );
+        println!(
+            "\t  Resolutions: {}",
+            version
+                .resolutions
+                .iter()
+                .map(|r| format!(
+                    "{}x{} ({} kbps, ~{})",
+                    r.width,
+                    r.height,
+                    r.bandwidth / 1000,
+                    format_size(r.estimated_file_size)
+                ))
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+        println!(
+            "\t  Subtitles: {}",
+            if version.subtitles.is_empty() {
+                "none".to_string()
+            } else {
+                version
+                    .subtitles
+                    .iter()
+                    .map(|s| {
+                        if s.cc {
+                            format!("{} (CC)", s.locale)
+                        } else {
+                            s.locale.clone()
+                        }
+                    })
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            }
+        )
+    }
+}
@@ -4,7 +4,7 @@ use anyhow::bail;
 use anyhow::Result;
 use clap::Parser;
 use crunchyroll_rs::crunchyroll::SessionToken;
-use log::info;
+use log::{info, warn};
 use std::fs;
 use std::path::PathBuf;
 
@@ -14,28 +14,58 @@ pub struct Login {
     #[arg(help = "Remove your stored credentials (instead of saving them)")]
     #[arg(long)]
     pub remove: bool,
+    #[arg(help = "Show the account which is currently logged in (instead of saving it)")]
+    #[arg(
+        long_help = "Show the account which is currently logged in (instead of saving it). \
+    Prints the profile name, email and if the account has premium, which is required for premium only videos and full resolution"
+    )]
+    #[arg(long, conflicts_with = "remove")]
+    pub check: bool,
+
+    #[arg(skip)]
+    pub keyring: bool,
 }
 
 impl Execute for Login {
     async fn execute(self, ctx: Context) -> Result<()> {
-        if let Some(login_file_path) = session_file_path() {
-            fs::create_dir_all(login_file_path.parent().unwrap())?;
+        if self.check {
+            return print_login(&ctx).await;
+        }
 
-            match ctx.crunchy.session_token().await {
-                SessionToken::RefreshToken(refresh_token) => {
-                    fs::write(login_file_path, format!("refresh_token:{}", refresh_token))?
-                }
-                SessionToken::EtpRt(_) => bail!("Login with etp_rt isn't supported anymore. Please use your credentials to login"),
-                SessionToken::Anonymous => bail!("Anonymous login cannot be saved"),
-            }
+        let session = match ctx.crunchy.session_token().await {
+            SessionToken::RefreshToken(refresh_token) => format!("refresh_token:{}", refresh_token),
+            SessionToken::EtpRt(_) => bail!(
+                "Login with etp_rt isn't supported anymore. Please use your credentials to login"
+            ),
+            SessionToken::Anonymous => bail!("Anonymous login cannot be saved"),
+        };
+        write_session(&session, self.keyring)?;
 
-            info!("Saved login");
+        info!("Saved login");
 
-            Ok(())
-        } else {
-            bail!("Cannot find config path")
-        }
+        Ok(())
+    }
+}
+
+/// Print the account and its premium status of the current login.
+async fn print_login(ctx: &Context) -> Result<()> {
+    if matches!(ctx.crunchy.session_token().await, SessionToken::Anonymous) {
+        println!("Logged in anonymously");
+        println!("Premium: no (premium only videos are not available)");
+        return Ok(());
+    }
+
+    let account = ctx.crunchy.account().await?;
+    println!("Profile name: {}", account.profile_name);
+    println!("Email:        {}", account.email);
+    println!("Account id:   {}", account.account_id);
+    if ctx.crunchy.premium().await {
+        println!("Premium:      yes (all videos, dubs and resolutions are available)")
+    } else {
+        println!("Premium:      no (premium only videos are not available and some dubs or resolutions may be missing)")
     }
+
+    Ok(())
 }
 
 #[derive(Clone, Debug, Parser)]
@@ -53,3 +83,107 @@ pub struct LoginMethod {
 pub fn session_file_path() -> Option<PathBuf> {
     dirs::config_dir().map(|config_dir| config_dir.join("crunchy-cli").join("session"))
 }
+
+const KEYRING_SERVICE: &str = "crunchy-cli";
+const KEYRING_USER: &str = "session";
+
+/// Read the stored session. If `keyring` is true, the session is read from the os keyring first
+/// and the session file is only used as fallback.
+pub fn read_session(keyring: bool) -> Result<Option<String>> {
+    if keyring {
+        match keyring_get() {
+            Ok(Some(session)) => return Ok(Some(session)),
+            Ok(None) => (),
+            Err(e) => warn!(
+                "Failed to read login from keyring, falling back to session file: {}",
+                e
+            ),
+        }
+    }
+
+    if let Some(login_file_path) = session_file_path() {
+        if login_file_path.exists() {
+            return Ok(Some(fs::read_to_string(login_file_path)?));
+        }
+    }
+    Ok(None)
+}
+
+/// Store the session. If `keyring` is true, the session is stored in the os keyring and an
+/// existing session file is removed, so no plaintext copy of the current session is left on disk.
+/// If the keyring is not available, the session file is used as fallback.
+fn write_session(session: &str, keyring: bool) -> Result<()> {
+    if keyring {
+        match keyring_set(session) {
+            Ok(_) => {
+                if let Some(login_file_path) = session_file_path() {
+                    let _ = fs::remove_file(login_file_path);
+                }
+                return Ok(());
+            }
+            Err(e) => warn!(
+                "Failed to store login in keyring, falling back to session file: {}",
+                e
+            ),
+        }
+    }
+
+    if let Some(login_file_path) = session_file_path() {
+        fs::create_dir_all(login_file_path.parent().unwrap())?;
+        fs::write(login_file_path, session)?;
+        Ok(())
+    } else {
+        bail!("Cannot find config path")
+    }
+}
+
+/// Remove the stored session from the session file and, if `keyring` is true, from the os keyring.
+pub fn remove_session(keyring: bool) {
+    if keyring {
+        let _ = keyring_delete();
+    }
+    if let Some(session_file) = session_file_path() {
+        let _ = fs::remove_file(session_file);
+    }
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_get() -> Result<Option<String>, String> {
+    match keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER).and_then(|e| e.get_password()) {
+        Ok(session) => Ok(Some(session)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_set(session: &str) -> Result<(), String> {
+    keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)
+        .and_then(|e| e.set_password(session))
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_delete() -> Result<(), String> {
+    keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)
+        .and_then(|e| e.delete_password())
+        .map_err(|e| e.to_string())
+}
+
+// if crunchy-cli is built without keyring support (e.g. for environments which have no keyring
+// anyway), every keyring operation fails and the session file is used instead
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_get() -> Result<Option<String>, String> {
+    Err("crunchy-cli was built without keyring support".to_string())
+}
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_set(_session: &str) -> Result<(), String> {
+    Err("crunchy-cli was built without keyring support".to_string())
+}
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_delete() -> Result<(), String> {
+    Err("crunchy-cli was built without keyring support".to_string())
+}
@@ -1,3 +1,3 @@
 mod command;
 
-pub use command::{session_file_path, Login, LoginMethod};
+pub use command::{read_session, remove_session, Login, LoginMethod};
@@ -0,0 +1,136 @@
+use crate::utils::context::Context;
+use crate::utils::download::DownloadBuilder;
+use crate::utils::ffmpeg::FFmpegPreset;
+use crate::utils::os::{ffmpeg_path, free_file, has_ffmpeg};
+use crate::Execute;
+use anyhow::bail;
+use anyhow::Result;
+use crunchyroll_rs::Locale;
+use log::warn;
+use reqwest::Client;
+use std::path::PathBuf;
+
+#[derive(Clone, Debug, clap::Parser)]
+#[clap(about = "Mux already downloaded video, audio and subtitle files into one file")]
+#[command(arg_required_else_help(true))]
+pub struct Mux {
+    #[arg(help = "The video file")]
+    #[arg(long)]
+    pub(crate) video: PathBuf,
+    #[arg(help = "Audio file with its language. Must be in format of <LANG>=<PATH>")]
+    #[arg(
+        long_help = "Audio file with its language. Must be in format of <LANG>=<PATH> (e.g. 'ja-JP=audio.m4a'). \
+    Can be used multiple times, the audios are muxed in the given order"
+    )]
+    #[arg(long, value_parser = crate::utils::clap::clap_parse_locale_path)]
+    pub(crate) audio: Vec<(Locale, PathBuf)>,
+    #[arg(help = "Subtitle file with its language. Must be in format of <LANG>=<PATH>")]
+    #[arg(
+        long_help = "Subtitle file with its language. Must be in format of <LANG>=<PATH> (e.g. 'en-US=subtitle.ass'). \
+    Can be used multiple times, the subtitles are muxed in the given order. \
+    The subtitles are used as they are, they're neither fixed nor converted like when downloading"
+    )]
+    #[arg(long, value_parser = crate::utils::clap::clap_parse_locale_path)]
+    pub(crate) subtitle: Vec<(Locale, PathBuf)>,
+
+    #[arg(help = "Name of the output file")]
+    #[arg(long_help = "Name of the output file. \
+    If the file already exists, a number is appended to the name. \
+    Like with `download`, the subtitles are burned into the video if the container doesn't support soft subtitles")]
+    #[arg(short, long)]
+    pub(crate) output: PathBuf,
+
+    #[arg(help = format!("Presets for converting the video to a specific coding format. \
+    Available presets: \n  {}", FFmpegPreset::available_matches_human_readable().join("\n  ")))]
+    #[arg(long_help = format!("Presets for converting the video to a specific coding format. \
+    If you need more specific ffmpeg customizations you can pass ffmpeg output arguments instead of a preset as value. \
+    Available presets: \n  {}", FFmpegPreset::available_matches_human_readable().join("\n  ")))]
+    #[arg(long)]
+    #[arg(value_parser = FFmpegPreset::parse)]
+    pub(crate) ffmpeg_preset: Option<FFmpegPreset>,
+    #[arg(
+        help = "Set which subtitle language should be set as default / auto shown when starting a video"
+    )]
+    #[arg(long)]
+    pub(crate) default_subtitle: Option<Locale>,
+    #[arg(
+        help = "Set which audio language should be set as default / auto selected when starting a video"
+    )]
+    #[arg(
+        long_help = "Set which audio language should be set as default / auto selected when starting a video. \
+    Without it, players usually pick the first audio track"
+    )]
+    #[arg(long)]
+    pub(crate) default_audio: Option<Locale>,
+}
+
+impl Execute for Mux {
+    fn pre_check(&mut self) -> Result<()> {
+        if !has_ffmpeg() {
+            bail!(
+                "FFmpeg is needed to run this command but '{}' could not be executed. Install it or set its path via `--ffmpeg-path` / the `CRUNCHY_CLI_FFMPEG` env variable",
+                ffmpeg_path().to_string_lossy()
+            )
+        }
+
+        for path in [&self.video]
+            .into_iter()
+            .chain(self.audio.iter().map(|(_, p)| p))
+            .chain(self.subtitle.iter().map(|(_, p)| p))
+        {
+            if !path.is_file() {
+                bail!("File '{}' does not exist", path.to_string_lossy())
+            }
+        }
+        if let Some(default_audio) = &self.default_audio {
+            if !self.audio.iter().any(|(l, _)| l == default_audio) {
+                bail!(
+                    "Default audio {} is none of the given audios",
+                    default_audio
+                )
+            }
+        }
+        if let Some(default_subtitle) = &self.default_subtitle {
+            if !self.subtitle.iter().any(|(l, _)| l == default_subtitle) {
+                bail!(
+                    "Default subtitle {} is none of the given subtitles",
+                    default_subtitle
+                )
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn execute(self, _ctx: Context) -> Result<()> {
+        self.run().await
+    }
+}
+
+impl Mux {
+    /// Mux the files. Unlike all other commands, no Crunchyroll session is needed for this.
+    pub(crate) async fn run(self) -> Result<()> {
+        let downloader = DownloadBuilder::new(Client::new(), None)
+            .ffmpeg_preset(self.ffmpeg_preset.unwrap_or_default())
+            .default_subtitle(self.default_subtitle)
+            .default_audio(self.default_audio)
+            .build();
+
+        let (path, changed) = free_file(self.output);
+        if changed {
+            warn!(
+                "Output file already exists, writing to '{}' instead",
+                path.to_string_lossy()
+            )
+        }
+
+        downloader
+            .mux(
+                &path,
+                self.video,
+                self.audio.into_iter().map(|(l, p)| (p, l)).collect(),
+                self.subtitle.into_iter().map(|(l, p)| (p, l)).collect(),
+            )
+            .await
+    }
+}
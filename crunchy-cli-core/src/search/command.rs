@@ -8,6 +8,7 @@ use crunchyroll_rs::common::StreamExt;
 use crunchyroll_rs::search::QueryResults;
 use crunchyroll_rs::{Episode, Locale, MediaCollection, MovieListing, MusicVideo, Series};
 use log::warn;
+use serde::Serialize;
 use std::sync::Arc;
 
 #[derive(Debug, clap::Parser)]
@@ -37,6 +38,22 @@ pub struct Search {
     #[arg(long, default_value_t = 0)]
     search_music_limit: u32,
 
+    #[arg(help = "Only list series and movie results with their url and id, max. <LIMIT> of each")]
+    #[arg(
+        long_help = "Only list series and movie results with their url and id, max. <LIMIT> of each. \
+            Unlike the default output, the results are not expanded into their seasons / episodes, which makes the output suitable to be passed to `archive` or `download`"
+    )]
+    #[arg(long)]
+    limit: Option<u32>,
+    #[arg(
+        help = "Only list results of the given type with their url and id. Valid types are: series, movie"
+    )]
+    #[arg(long = "type", value_parser = ["series", "movie"])]
+    result_type: Option<String>,
+    #[arg(help = "List series and movie results with their url and id as json")]
+    #[arg(long, default_value_t = false)]
+    json: bool,
+
     /// Format of the output text.
     ///
     /// You can specify keywords in a specific pattern and they will get replaced in the output text.
@@ -115,6 +132,10 @@ impl Execute for Search {
             warn!("The `{{{{stream.is_drm}}}}` option is deprecated as it isn't reliable anymore and will be removed soon")
         }
 
+        if self.limit.is_some() || self.result_type.is_some() || self.json {
+            return list_results(&self, ctx.crunchy.query(&self.input)).await;
+        }
+
         let input = if crunchyroll_rs::parse::parse_url(&self.input).is_some() {
             match parse_url(&ctx.crunchy, self.input.clone(), true).await {
                 Ok(ok) => vec![ok],
@@ -182,6 +203,74 @@ macro_rules! resolve_query {
     };
 }
 
+#[derive(Serialize)]
+struct SearchResult {
+    #[serde(rename = "type")]
+    result_type: &'static str,
+    id: String,
+    title: String,
+    url: String,
+}
+
+impl From<Series> for SearchResult {
+    fn from(value: Series) -> Self {
+        Self {
+            result_type: "series",
+            url: format!(
+                "https://www.crunchyroll.com/series/{}/{}",
+                value.id, value.slug_title
+            ),
+            id: value.id,
+            title: value.title,
+        }
+    }
+}
+
+impl From<MovieListing> for SearchResult {
+    fn from(value: MovieListing) -> Self {
+        Self {
+            result_type: "movie",
+            url: format!(
+                "https://www.crunchyroll.com/watch/{}/{}",
+                value.id, value.slug_title
+            ),
+            id: value.id,
+            title: value.title,
+        }
+    }
+}
+
+/// Print the series and movie listing results of a query with their url and id instead of
+/// expanding them via [`Format`].
+async fn list_results(search: &Search, query_results: QueryResults) -> Result<()> {
+    let limit = search.limit.unwrap_or(10);
+    let mut results: Vec<SearchResult> = vec![];
+
+    if search.result_type.as_deref() != Some("movie") {
+        let mut series = vec![];
+        resolve_query!(limit, series, query_results.series);
+        results.extend(series.into_iter().map(SearchResult::from))
+    }
+    if search.result_type.as_deref() != Some("series") {
+        let mut movie_listings = vec![];
+        resolve_query!(limit, movie_listings, query_results.movie_listing);
+        results.extend(movie_listings.into_iter().map(SearchResult::from))
+    }
+
+    if search.json {
+        println!("{}", serde_json::to_string_pretty(&results)?)
+    } else {
+        for result in results {
+            println!(
+                "{}\t{}\t{}\t{}",
+                result.url, result.id, result.result_type, result.title
+            )
+        }
+    }
+
+    Ok(())
+}
+
 async fn resolve_query(
     search: &Search,
     query_results: QueryResults,
@@ -0,0 +1,95 @@
+use crate::utils::os::cache_dir;
+use anyhow::{bail, Result};
+use chrono::{TimeDelta, Utc};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const METADATA_CACHE_FILE: &str = "episodes.json";
+
+/// Cache which maps episodes to the output file they were archived to. It's stored as json in the
+/// metadata [`cache_dir`] and makes it possible to skip already archived episodes without resolving
+/// their streams again. The key of an episode is chosen by the caller and should contain everything
+/// which changes the output file besides the episode id.
+pub struct MetadataCache {
+    path: PathBuf,
+    ttl: TimeDelta,
+    entries: HashMap<String, (i64, PathBuf)>,
+}
+
+impl MetadataCache {
+    /// Load the cache. Entries which are older than `ttl` are dropped.
+    pub fn load(ttl: TimeDelta) -> Result<Self> {
+        let path = cache_dir("metadata")?.join(METADATA_CACHE_FILE);
+        let mut cache = Self {
+            path,
+            ttl,
+            entries: HashMap::new(),
+        };
+        if !cache.path.exists() {
+            return Ok(cache);
+        }
+
+        let Ok(Value::Object(entries)) = serde_json::from_str(&fs::read_to_string(&cache.path)?)
+        else {
+            bail!(
+                "Metadata cache '{}' is corrupted, use `--no-cache` to reset it",
+                cache.path.to_string_lossy()
+            )
+        };
+        for (key, entry) in entries {
+            let (Some(timestamp), Some(path)) = (
+                entry.get("timestamp").and_then(|t| t.as_i64()),
+                entry.get("path").and_then(|p| p.as_str()),
+            ) else {
+                continue;
+            };
+            if !cache.is_expired(timestamp) {
+                cache.entries.insert(key, (timestamp, PathBuf::from(path)));
+            }
+        }
+        Ok(cache)
+    }
+
+    /// Remove the cache file, if it exists.
+    pub fn clear() -> Result<()> {
+        let path = cache_dir("metadata")?.join(METADATA_CACHE_FILE);
+        if path.exists() {
+            fs::remove_file(path)?
+        }
+        Ok(())
+    }
+
+    /// Get the output file the episode with `key` was archived to, if it is cached and not
+    /// expired.
+    pub fn get(&self, key: &str) -> Option<&Path> {
+        self.entries
+            .get(key)
+            .filter(|(timestamp, _)| !self.is_expired(*timestamp))
+            .map(|(_, path)| path.as_path())
+    }
+
+    pub fn insert(&mut self, key: String, path: PathBuf) {
+        self.entries.insert(key, (Utc::now().timestamp(), path));
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let entries: Map<String, Value> = self
+            .entries
+            .iter()
+            .map(|(key, (timestamp, path))| {
+                (
+                    key.clone(),
+                    json!({"timestamp": timestamp, "path": path.to_string_lossy()}),
+                )
+            })
+            .collect();
+        fs::write(&self.path, serde_json::to_string(&entries)?)?;
+        Ok(())
+    }
+
+    fn is_expired(&self, timestamp: i64) -> bool {
+        Utc::now().timestamp() - timestamp > self.ttl.num_seconds()
+    }
+}
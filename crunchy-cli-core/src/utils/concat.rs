@@ -0,0 +1,181 @@
+use crate::utils::download::{get_length, Downloader};
+use crate::utils::log::progress;
+use crate::utils::os::{ffmpeg_path, tempfile};
+use anyhow::{bail, Result};
+use chrono::TimeDelta;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tempfile::TempPath;
+
+/// Collects the parts of a video which is split into multiple parts (see `SingleFormat::part`).
+/// Every part is downloaded into a temporary file and all parts are concatenated into the output
+/// file of the first part after the last part is downloaded.
+#[derive(Default)]
+pub struct PartCollector {
+    dst: Option<PathBuf>,
+    parts: Vec<(TempPath, String)>,
+    /// If the first part of the current video was skipped, e.g. because the output file already
+    /// exists.
+    first_part_skipped: bool,
+}
+
+impl PartCollector {
+    /// Returns `true` if the given part is a following part of a video whose first part was
+    /// skipped. A video is always skipped as a whole, so following parts are never downloaded into
+    /// their own file. Must be called for every video before it's processed.
+    pub fn skip_part(&mut self, part: Option<(u32, u32)>) -> bool {
+        match part {
+            // the first part counts as skipped until `start_part` is called for it
+            Some((1, _)) => {
+                self.first_part_skipped = true;
+                false
+            }
+            Some(_) => self.first_part_skipped,
+            None => false,
+        }
+    }
+
+    /// Marks the given part as not skipped. Must be called when it's clear that the part is
+    /// downloaded.
+    pub fn start_part(&mut self, part: Option<(u32, u32)>) {
+        if let Some((1, _)) = part {
+            self.first_part_skipped = false
+        }
+    }
+
+    /// Returns the output path of the video if the given part is a following part of the video
+    /// whose parts are currently collected.
+    pub fn following_dst(&self, part: Option<(u32, u32)>) -> Option<PathBuf> {
+        match part {
+            Some((part, _)) if part > 1 && !self.parts.is_empty() => self.dst.clone(),
+            _ => None,
+        }
+    }
+
+    /// Download the given part. Returns `true` if it was the last part and all parts are
+    /// concatenated into `dst`.
+    pub async fn download(
+        &mut self,
+        downloader: Downloader,
+        title: &str,
+        part: (u32, u32),
+        dst: &Path,
+    ) -> Result<bool> {
+        // a new video starts before all parts of the previous one were downloaded, e.g. because
+        // some parts were skipped
+        if part.0 == 1 {
+            self.finish()?
+        }
+        if self.parts.is_empty() {
+            self.dst = Some(dst.to_path_buf())
+        }
+
+        let path = tempfile(format!(
+            ".{}",
+            dst.extension().unwrap_or_default().to_string_lossy()
+        ))?
+        .into_temp_path();
+        downloader.download(&path).await?;
+        self.parts.push((path, title.to_string()));
+
+        if part.0 < part.1 {
+            return Ok(false);
+        }
+        self.finish()?;
+        Ok(true)
+    }
+
+    /// Concatenate all collected parts, if any.
+    pub fn finish(&mut self) -> Result<()> {
+        if self.parts.is_empty() {
+            return Ok(());
+        }
+        let parts: Vec<(TempPath, String)> = self.parts.drain(..).collect();
+        concat_parts(&parts, self.dst.as_ref().unwrap())
+    }
+}
+
+/// Concatenate the given parts into `dst`. A chapter is added for every part, so the part
+/// boundaries are still recognizable in the output file.
+fn concat_parts(parts: &[(TempPath, String)], dst: &Path) -> Result<()> {
+    let progress_handler = progress!("Concatenating {} parts", parts.len());
+
+    let (mut list_file, list_path) = tempfile(".txt")?.into_parts();
+    for (path, _) in parts {
+        // the concat demuxer requires single quotes in paths to be escaped like this
+        writeln!(
+            list_file,
+            "file '{}'",
+            path.to_string_lossy().replace('\'', r"'\''")
+        )?;
+    }
+
+    // if all parts have the same title, they are distinguished by their number
+    let same_titles = parts.iter().all(|(_, title)| title == &parts[0].1);
+    let (mut chapter_file, chapter_path) = tempfile(".chapter")?.into_parts();
+    writeln!(chapter_file, ";FFMETADATA1")?;
+    let mut start = TimeDelta::zero();
+    for (i, (path, title)) in parts.iter().enumerate() {
+        let end = start + get_length(path)?;
+        writeln!(chapter_file, "[CHAPTER]")?;
+        writeln!(chapter_file, "TIMEBASE=1/1000")?;
+        writeln!(chapter_file, "START={}", start.num_milliseconds())?;
+        writeln!(chapter_file, "END={}", end.num_milliseconds())?;
+        if same_titles {
+            writeln!(chapter_file, "title=Part {}", i + 1)?;
+        } else {
+            writeln!(chapter_file, "title={}", title)?;
+        }
+        start = end
+    }
+
+    if let Some(parent) = dst.parent() {
+        if !parent.as_os_str().is_empty() && !parent.exists() {
+            std::fs::create_dir_all(parent)?
+        }
+    }
+
+    let mut args = vec![
+        "-y".to_string(),
+        "-hide_banner".to_string(),
+        "-f".to_string(),
+        "concat".to_string(),
+        "-safe".to_string(),
+        "0".to_string(),
+        "-i".to_string(),
+        list_path.to_string_lossy().to_string(),
+        "-f".to_string(),
+        "ffmetadata".to_string(),
+        "-i".to_string(),
+        chapter_path.to_string_lossy().to_string(),
+        "-map".to_string(),
+        "0".to_string(),
+        "-map_chapters".to_string(),
+        "1".to_string(),
+        "-c".to_string(),
+        "copy".to_string(),
+    ];
+    if ["mov", "mp4"].contains(
+        &dst.extension()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .as_ref(),
+    ) {
+        args.extend(["-movflags".to_string(), "+faststart".to_string()])
+    }
+    args.push(dst.to_string_lossy().to_string());
+
+    let ffmpeg = Command::new(ffmpeg_path())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .args(args)
+        .output()?;
+    if !ffmpeg.status.success() {
+        bail!("{}", String::from_utf8_lossy(&ffmpeg.stderr))
+    }
+
+    progress_handler.stop(format!("Concatenated {} parts", parts.len()));
+
+    Ok(())
+}
@@ -1,35 +1,41 @@
-use crate::utils::ffmpeg::FFmpegPreset;
+use crate::utils::ffmpeg::{FFmpegError, FFmpegPreset, SOFTSUB_CONTAINERS};
 use crate::utils::filter::real_dedup_vec;
-use crate::utils::fmt::format_time_delta;
-use crate::utils::log::progress;
-use crate::utils::os::{cache_dir, is_special_file, temp_directory, temp_named_pipe, tempfile};
+use crate::utils::fmt::{format_size, format_time_delta};
+use crate::utils::locale::iso639_2_code;
+use crate::utils::log::{progress, progress_hidden, tab_info};
+use crate::utils::os::{
+    cache_dir, ffmpeg_path, ffprobe_path, is_special_file, sanitize, temp_directory,
+    temp_named_pipe, temp_tail_file, tempfile,
+};
 use crate::utils::rate_limit::RateLimiterService;
 use crate::utils::sync::{sync_audios, SyncAudio};
 use anyhow::{bail, Result};
 use chrono::{NaiveTime, TimeDelta};
 use crunchyroll_rs::media::{SkipEvents, SkipEventsEvent, StreamData, StreamSegment, Subtitle};
 use crunchyroll_rs::Locale;
-use indicatif::{ProgressBar, ProgressDrawTarget, ProgressFinish, ProgressStyle};
-use log::{debug, warn, LevelFilter};
+use futures_util::StreamExt;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressFinish, ProgressStyle};
+use log::{debug, info, warn, LevelFilter};
 use regex::Regex;
 use reqwest::Client;
 use rsubs_lib::{SSA, VTT};
 use std::borrow::Borrow;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashMap};
-use std::io::Write;
-use std::ops::Add;
+use std::io::{BufWriter, Write};
+use std::ops::{Add, Deref};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU64, AtomicUsize};
 use std::sync::Arc;
-use std::time::Duration;
-use std::{env, fs};
+use std::time::{Duration, Instant};
+use std::{env, fmt, fs};
 use tempfile::TempPath;
 use time::Time;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
 use tokio::select;
 use tokio::sync::mpsc::unbounded_channel;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Semaphore, SemaphorePermit};
 use tokio::task::JoinSet;
 use tokio_util::sync::CancellationToken;
 use tower_service::Service;
@@ -54,67 +60,178 @@ impl MergeBehavior {
     }
 }
 
+/// Builder for a [`Downloader`]. Every option has a setter with the same name, options which are not
+/// set fall back to the defaults of the cli.
 #[derive(Clone, derive_setters::Setters)]
 pub struct DownloadBuilder {
     client: Client,
     rate_limiter: Option<RateLimiterService>,
     ffmpeg_preset: FFmpegPreset,
     default_subtitle: Option<Locale>,
+    default_audio: Option<Locale>,
     output_format: Option<String>,
+    container: Option<String>,
     audio_sort: Option<Vec<Locale>>,
     subtitle_sort: Option<Vec<Locale>>,
+    subtitle_sort_by_audio: bool,
     force_hardsub: bool,
+    hardsub: Option<Locale>,
+    hardsub_crf: u8,
+    hardsub_preset: Option<String>,
     download_fonts: bool,
+    font_dir: Option<PathBuf>,
     no_closed_caption: bool,
+    progress_json: bool,
+    progress_pipe: bool,
+    audio_only: bool,
+    normalize_audio: bool,
+    trim_silence: bool,
+    verify_output: bool,
+    subtitle_filename_template: Option<String>,
+    subtitle_sidecar_format: Option<String>,
+    subtitle_offset: Vec<(Option<Locale>, TimeDelta)>,
+    subtitle_style: Option<PathBuf>,
+    keep_partial: bool,
+    keep_temp: Option<PathBuf>,
+    chapter_types: Vec<String>,
+    gap_chapters: bool,
+    container_metadata: Vec<(String, String)>,
+    dry_run: bool,
+    trim_start: Option<TimeDelta>,
+    trim_end: Option<TimeDelta>,
+    multi_progress: Option<MultiProgress>,
+    concurrent_space: Option<Arc<AtomicU64>>,
+    download_stats: Option<DownloadStats>,
     merge_sync_tolerance: Option<u32>,
     merge_sync_precision: Option<u32>,
     threads: usize,
     ffmpeg_threads: Option<usize>,
+    pix_fmt: Option<String>,
+    write_buffer_size: Option<usize>,
+    concurrent_fragment_writes: bool,
+    segment_order: String,
+    download_retries: u32,
+    retry_backoff: u64,
+    segment_timeout: u64,
     audio_locale_output_map: HashMap<Locale, String>,
     subtitle_locale_output_map: HashMap<Locale, String>,
 }
 
 impl DownloadBuilder {
+    /// Create a new builder. All requests (segments, subtitles, fonts, ...) are made with `client`,
+    /// `rate_limiter` optionally limits the download speed of them.
     pub fn new(client: Client, rate_limiter: Option<RateLimiterService>) -> DownloadBuilder {
         Self {
             client,
             rate_limiter,
             ffmpeg_preset: FFmpegPreset::default(),
             default_subtitle: None,
+            default_audio: None,
             output_format: None,
+            container: None,
             audio_sort: None,
             subtitle_sort: None,
+            subtitle_sort_by_audio: false,
             force_hardsub: false,
+            hardsub: None,
+            hardsub_crf: 23,
+            hardsub_preset: None,
             download_fonts: false,
+            font_dir: None,
             no_closed_caption: false,
+            progress_json: false,
+            progress_pipe: true,
+            audio_only: false,
+            normalize_audio: false,
+            trim_silence: false,
+            verify_output: false,
+            subtitle_filename_template: None,
+            subtitle_sidecar_format: None,
+            subtitle_offset: vec![],
+            subtitle_style: None,
+            keep_partial: false,
+            keep_temp: None,
+            chapter_types: vec![],
+            gap_chapters: true,
+            container_metadata: vec![],
+            dry_run: false,
+            trim_start: None,
+            trim_end: None,
+            multi_progress: None,
+            concurrent_space: None,
+            download_stats: None,
             merge_sync_tolerance: None,
             merge_sync_precision: None,
             threads: num_cpus::get(),
             ffmpeg_threads: None,
+            pix_fmt: None,
+            write_buffer_size: None,
+            concurrent_fragment_writes: false,
+            segment_order: "interleaved".to_string(),
+            download_retries: 5,
+            retry_backoff: 0,
+            segment_timeout: 60,
             audio_locale_output_map: HashMap::new(),
             subtitle_locale_output_map: HashMap::new(),
         }
     }
 
+    /// Build the [`Downloader`]. At least one [`DownloadFormat`] must be added to it via
+    /// [`Downloader::add_format`] before downloading.
     pub fn build(self) -> Downloader {
         Downloader {
             client: self.client,
             rate_limiter: self.rate_limiter,
             ffmpeg_preset: self.ffmpeg_preset,
             default_subtitle: self.default_subtitle,
+            default_audio: self.default_audio,
             output_format: self.output_format,
+            container: self.container,
             audio_sort: self.audio_sort,
             subtitle_sort: self.subtitle_sort,
+            subtitle_sort_by_audio: self.subtitle_sort_by_audio,
 
             force_hardsub: self.force_hardsub,
+            hardsub: self.hardsub,
+            hardsub_crf: self.hardsub_crf,
+            hardsub_preset: self.hardsub_preset,
             download_fonts: self.download_fonts,
+            font_dir: self.font_dir,
             no_closed_caption: self.no_closed_caption,
+            progress_json: self.progress_json,
+            progress_pipe: self.progress_pipe,
+            audio_only: self.audio_only,
+            normalize_audio: self.normalize_audio,
+            trim_silence: self.trim_silence,
+            verify_output: self.verify_output,
+            subtitle_filename_template: self.subtitle_filename_template,
+            subtitle_sidecar_format: self.subtitle_sidecar_format,
+            subtitle_offset: self.subtitle_offset,
+            subtitle_style: self.subtitle_style,
+            keep_partial: self.keep_partial,
+            keep_temp: self.keep_temp,
+            chapter_types: self.chapter_types,
+            gap_chapters: self.gap_chapters,
+            container_metadata: self.container_metadata,
+            dry_run: self.dry_run,
+            trim_start: self.trim_start,
+            trim_end: self.trim_end,
+            multi_progress: self.multi_progress,
+            concurrent_space: self.concurrent_space,
+            download_stats: self.download_stats,
 
             merge_sync_tolerance: self.merge_sync_tolerance,
             merge_sync_precision: self.merge_sync_precision,
 
             download_threads: self.threads,
             ffmpeg_threads: self.ffmpeg_threads,
+            pix_fmt: self.pix_fmt,
+            write_buffer_size: self.write_buffer_size,
+            concurrent_fragment_writes: self.concurrent_fragment_writes,
+            segment_order: self.segment_order,
+            download_retries: self.download_retries,
+            retry_backoff: self.retry_backoff,
+            segment_timeout: self.segment_timeout,
 
             formats: vec![],
 
@@ -124,57 +241,186 @@ impl DownloadBuilder {
     }
 }
 
+/// All inputs of the ffmpeg command which generates the output file.
+struct MuxInputs<'a> {
+    videos: Vec<FFmpegVideoMeta>,
+    audios: Vec<FFmpegAudioMeta>,
+    subtitles: Vec<FFmpegSubtitleMeta>,
+    fonts: Vec<PathBuf>,
+    chapters: Option<((fs::File, TempPath), Vec<(&'a str, &'a SkipEventsEvent)>)>,
+    thumbnail: Option<TempPath>,
+    /// Streams the videos and audios were downloaded from, used to download an input again if
+    /// ffmpeg reports invalid data in it.
+    sources: Vec<&'a StreamData>,
+    max_frames: u64,
+    output_len: TimeDelta,
+    trim_start: TimeDelta,
+    synced: bool,
+    fmt_space: usize,
+}
+
+/// Path of a file which is muxed into the output file. Downloaded files are temporary and removed
+/// when dropped, files which were given by the user (see [`Downloader::mux`]) are kept.
+enum InputPath {
+    Temp(TempPath),
+    Local(PathBuf),
+}
+
+impl Deref for InputPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            InputPath::Temp(path) => path,
+            InputPath::Local(path) => path,
+        }
+    }
+}
+
+impl AsRef<Path> for InputPath {
+    fn as_ref(&self) -> &Path {
+        self
+    }
+}
+
+impl From<TempPath> for InputPath {
+    fn from(path: TempPath) -> Self {
+        InputPath::Temp(path)
+    }
+}
+
 struct FFmpegVideoMeta {
-    path: TempPath,
+    path: InputPath,
     length: TimeDelta,
     start_time: Option<TimeDelta>,
+    /// The video label, or its number (`#<n>`) if the format has none.
+    label: String,
+}
+
+/// Get the ffmpeg arguments which set the 'forced' flag to CC subtitles. Only the `cc` flag of the
+/// subtitles is checked, not their title.
+fn forced_subtitle_args(subtitles: &[FFmpegSubtitleMeta]) -> Vec<String> {
+    subtitles
+        .iter()
+        .enumerate()
+        .filter(|(_, subtitle)| subtitle.cc)
+        .flat_map(|(i, _)| [format!("-disposition:s:s:{}", i), "forced".to_string()])
+        .collect()
+}
+
+/// Get the label of the video at `idx`, which is used to name the tracks belonging to it.
+fn video_label(videos: &[FFmpegVideoMeta], idx: usize) -> String {
+    videos
+        .get(idx)
+        .map_or_else(|| format!("#{}", idx + 1), |v| v.label.clone())
 }
 
 struct FFmpegAudioMeta {
-    path: TempPath,
+    path: InputPath,
     locale: Locale,
     start_time: Option<TimeDelta>,
     video_idx: usize,
 }
 
 struct FFmpegSubtitleMeta {
-    path: TempPath,
+    path: InputPath,
     locale: Locale,
+    /// If the subtitle is a closed caption. This is set when the subtitles are collected (cc
+    /// subtitles come from the captions of a stream) and never derived from the subtitle title,
+    /// which only gets ' (CC)' appended for display. Every cc subtitle gets the 'forced'
+    /// disposition.
     cc: bool,
     start_time: Option<TimeDelta>,
     video_idx: usize,
 }
 
+/// A video with its audio and subtitle tracks. Multiple formats can be muxed into one output file.
 pub struct DownloadFormat {
+    /// The video stream and the audio locale it belongs to.
     pub video: (StreamData, Locale),
+    /// Audio streams with their locale.
     pub audios: Vec<(StreamData, Locale)>,
+    /// Subtitles and if they're closed captions.
     pub subtitles: Vec<(Subtitle, bool)>,
     pub metadata: DownloadFormatMetadata,
 }
 
 pub struct DownloadFormatMetadata {
+    /// Skip events which are written as chapters.
     pub skip_events: Option<SkipEvents>,
+    /// Url of an image which is embedded as cover art.
+    pub thumbnail: Option<String>,
+    /// Human readable name of the video. If multiple videos are muxed into one file, their tracks
+    /// are named by it instead of their number.
+    pub video_label: Option<String>,
 }
 
+/// Percentage of the estimated file size an existing file must have to be skipped by
+/// `--skip-existing-size`. The estimated file size is calculated from the stream bandwidth and is
+/// mostly a bit bigger than the actual file, so it can't be used as exact minimum. Keep the
+/// `--skip-existing-size` help texts in sync when changing this.
+const MIN_EXISTING_FILE_SIZE_PERCENT: u64 = 80;
+
+/// Downloads and muxes [`DownloadFormat`]s into a single output file. Created via
+/// [`DownloadBuilder`].
 pub struct Downloader {
     client: Client,
     rate_limiter: Option<RateLimiterService>,
 
     ffmpeg_preset: FFmpegPreset,
     default_subtitle: Option<Locale>,
+    default_audio: Option<Locale>,
     output_format: Option<String>,
+    container: Option<String>,
     audio_sort: Option<Vec<Locale>>,
     subtitle_sort: Option<Vec<Locale>>,
+    subtitle_sort_by_audio: bool,
 
     force_hardsub: bool,
+    hardsub: Option<Locale>,
+    hardsub_crf: u8,
+    hardsub_preset: Option<String>,
     download_fonts: bool,
+    font_dir: Option<PathBuf>,
     no_closed_caption: bool,
+    progress_json: bool,
+    progress_pipe: bool,
+    audio_only: bool,
+    normalize_audio: bool,
+    trim_silence: bool,
+    verify_output: bool,
+    subtitle_filename_template: Option<String>,
+    subtitle_sidecar_format: Option<String>,
+    subtitle_offset: Vec<(Option<Locale>, TimeDelta)>,
+    subtitle_style: Option<PathBuf>,
+    keep_partial: bool,
+    keep_temp: Option<PathBuf>,
+    chapter_types: Vec<String>,
+    gap_chapters: bool,
+    container_metadata: Vec<(String, String)>,
+    dry_run: bool,
+    trim_start: Option<TimeDelta>,
+    trim_end: Option<TimeDelta>,
+    /// Set if multiple downloads are running concurrently, so their progress bars are not drawn
+    /// over each other.
+    multi_progress: Option<MultiProgress>,
+    /// The estimated space which is required by all concurrently running downloads.
+    concurrent_space: Option<Arc<AtomicU64>>,
+    /// Bytes downloaded by all downloaders of this run.
+    download_stats: Option<DownloadStats>,
 
     merge_sync_tolerance: Option<u32>,
     merge_sync_precision: Option<u32>,
 
     download_threads: usize,
     ffmpeg_threads: Option<usize>,
+    pix_fmt: Option<String>,
+    write_buffer_size: Option<usize>,
+    concurrent_fragment_writes: bool,
+    segment_order: String,
+    download_retries: u32,
+    retry_backoff: u64,
+    segment_timeout: u64,
 
     formats: Vec<DownloadFormat>,
 
@@ -183,14 +429,45 @@ pub struct Downloader {
 }
 
 impl Downloader {
+    /// Add a format which is muxed into the output file. The first added format is the primary one,
+    /// its video is the default video track.
     pub fn add_format(&mut self, format: DownloadFormat) {
         self.formats.push(format);
     }
 
+    /// Estimated size of all video and audio streams of the added formats together.
+    pub fn estimated_file_size(&self) -> u64 {
+        let mut estimated_size = 0;
+        for format in &self.formats {
+            estimated_size +=
+                estimate_stream_data_file_size(&format.video.0, &format.video.0.segments());
+            for (audio, _) in &format.audios {
+                estimated_size += estimate_stream_data_file_size(audio, &audio.segments())
+            }
+        }
+        estimated_size
+    }
+
+    /// Minimal size an already existing output file must have to be considered as completely
+    /// downloaded, see [`MIN_EXISTING_FILE_SIZE_PERCENT`].
+    pub fn min_existing_file_size(&self) -> u64 {
+        self.estimated_file_size() / 100 * MIN_EXISTING_FILE_SIZE_PERCENT
+    }
+
+    /// Download all added formats and mux them into `dst`. `dst` can also be `-` to write to
+    /// stdout.
     pub async fn download(mut self, dst: &Path) -> Result<()> {
+        // if other downloads are running at the same time, the space they require must be
+        // available too. the reserved space is released when this function returns
+        let reserved_space =
+            ReservedSpace::new(self.concurrent_space.clone(), self.estimated_file_size());
+
         // `.unwrap_or_default()` here unless https://doc.rust-lang.org/stable/std/path/fn.absolute.html
         // gets stabilized as the function might throw error on weird file paths
-        let required = self.check_free_space(dst).await.unwrap_or_default();
+        let required = self
+            .check_free_space(dst, reserved_space.total())
+            .await
+            .unwrap_or_default();
         if let Some((path, tmp_required)) = &required.0 {
             let kb = (*tmp_required as f64) / 1024.0;
             let mb = kb / 1024.0;
@@ -223,6 +500,16 @@ impl Downloader {
             });
         }
         for format in self.formats.iter_mut() {
+            // drop unwanted subtitles already here, so they are neither downloaded nor considered
+            // when e.g. looking for the default subtitle
+            if self.no_closed_caption {
+                format.subtitles.retain(|(_, cc)| !cc)
+            }
+            // audio only outputs have no video track subtitles and chapters could be related to
+            if self.audio_only {
+                format.subtitles.clear();
+                format.metadata.skip_events = None
+            }
             if let Some(audio_sort_locales) = &self.audio_sort {
                 format.audios.sort_by(|(_, a), (_, b)| {
                     audio_sort_locales
@@ -232,13 +519,29 @@ impl Downloader {
                 })
             }
             if let Some(subtitle_sort) = &self.subtitle_sort {
+                // subtitles which have the same locale as an audio are ordered like the audios, so
+                // that the n-th subtitle track matches the n-th audio track where possible
+                let audios = &format.audios;
+                let audio_position = |locale: &Locale| {
+                    audios
+                        .iter()
+                        .position(|(_, l)| l == locale)
+                        .filter(|_| self.subtitle_sort_by_audio)
+                        .unwrap_or(usize::MAX)
+                };
                 format
                     .subtitles
                     .sort_by(|(a_subtitle, a_cc), (b_subtitle, b_cc)| {
-                        let ordering = subtitle_sort
-                            .iter()
-                            .position(|l| l == &a_subtitle.locale)
-                            .cmp(&subtitle_sort.iter().position(|l| l == &b_subtitle.locale));
+                        let ordering = audio_position(&a_subtitle.locale)
+                            .cmp(&audio_position(&b_subtitle.locale))
+                            .then_with(|| {
+                                subtitle_sort
+                                    .iter()
+                                    .position(|l| l == &a_subtitle.locale)
+                                    .cmp(
+                                        &subtitle_sort.iter().position(|l| l == &b_subtitle.locale),
+                                    )
+                            });
                         if matches!(ordering, Ordering::Equal) {
                             a_cc.cmp(b_cc).reverse()
                         } else {
@@ -248,10 +551,17 @@ impl Downloader {
             }
         }
 
+        if self.dry_run {
+            self.print_dry_run_command(dst);
+            return Ok(());
+        }
+
         let mut video_offset = None;
+        let mut synced = false;
         let mut audio_offsets = HashMap::new();
         let mut subtitle_offsets = HashMap::new();
         let mut raw_audios = vec![];
+        let mut audio_sources = vec![];
         let mut videos = vec![];
         let mut audios = vec![];
         let mut subtitles = vec![];
@@ -285,7 +595,8 @@ impl Downloader {
                     locale: locale.clone(),
                     sample_rate: stream_data.sampling_rate().unwrap(),
                     video_idx: i,
-                })
+                });
+                audio_sources.push(stream_data.clone())
             }
         }
 
@@ -366,6 +677,7 @@ impl Downloader {
 
                 self.formats = vec![root_format];
                 video_offset = offsets.get(&root_format_idx).copied();
+                synced = true;
                 for raw_audio in raw_audios.iter_mut() {
                     raw_audio.video_idx = root_format_idx;
                 }
@@ -382,15 +694,30 @@ impl Downloader {
         // add audio metadata
         for raw_audio in raw_audios {
             audios.push(FFmpegAudioMeta {
-                path: raw_audio.path,
+                path: raw_audio.path.into(),
                 locale: raw_audio.locale,
                 start_time: audio_offsets.get(&raw_audio.format_id).copied(),
                 video_idx: raw_audio.video_idx,
             })
         }
 
+        // there is no video to get the length from if only the audio is downloaded, so the length
+        // is calculated from the audio segments instead
+        if self.audio_only {
+            for stream_data in &audio_sources {
+                let len = len_from_segments(&stream_data.segments());
+                if max_len < len {
+                    max_len = len
+                }
+            }
+        }
+
         // downloads all videos
         for (i, format) in self.formats.iter().enumerate() {
+            if self.audio_only {
+                break;
+            }
+
             let path = self
                 .download_video(
                     &format.video.0,
@@ -412,18 +739,41 @@ impl Downloader {
             }
 
             videos.push(FFmpegVideoMeta {
-                path,
+                path: path.into(),
                 length: len,
                 start_time: video_offset,
+                label: format
+                    .metadata
+                    .video_label
+                    .clone()
+                    .unwrap_or_else(|| format!("#{}", i + 1)),
             })
         }
 
+        // if the output is trimmed, it only contains a part of the downloaded streams
+        let trim_start = self.trim_start.unwrap_or_default();
+        let output_len = self.trim_end.map_or(max_len, |end| end.min(max_len)) - trim_start;
+        if output_len <= TimeDelta::zero() {
+            bail!(
+                "The start of the trimmed output ({}) is after the end of the video ({})",
+                format_time_delta(&trim_start),
+                format_time_delta(&max_len)
+            )
+        }
+        if output_len < max_len {
+            max_frames = (max_frames as f64 * output_len.num_milliseconds() as f64
+                / max_len.num_milliseconds() as f64) as u64
+        }
+
         for (i, format) in self.formats.iter().enumerate() {
             if format.subtitles.is_empty() {
                 continue;
             }
 
-            let progress_spinner = if log::max_level() == LevelFilter::Info {
+            let progress_spinner = if log::max_level() == LevelFilter::Info
+                && !self.progress_json
+                && !progress_hidden()
+            {
                 let progress_spinner = ProgressBar::new_spinner()
                     .with_style(
                         ProgressStyle::with_template(
@@ -437,47 +787,67 @@ impl Downloader {
                         .tick_strings(&["—", "\\", "|", "/", ""]),
                     )
                     .with_finish(ProgressFinish::Abandon);
+                let progress_spinner = self.add_progress(progress_spinner);
                 progress_spinner.enable_steady_tick(Duration::from_millis(100));
                 Some(progress_spinner)
             } else {
                 None
             };
 
-            for (j, (subtitle, cc)) in format.subtitles.iter().enumerate() {
-                if *cc && self.no_closed_caption {
-                    continue;
-                }
-
-                if let Some(pb) = &progress_spinner {
-                    let mut progress_message = pb.message();
-                    if !progress_message.is_empty() {
-                        progress_message += ", "
-                    }
-                    progress_message += &subtitle.locale.to_string();
-                    if *cc {
-                        progress_message += " (CC)";
-                    }
-                    if i.min(videos.len() - 1) != 0 {
-                        progress_message += &format!(" [Video: #{}]", i + 1);
-                    }
-                    pb.set_message(progress_message)
-                }
+            // all subtitles are downloaded at the same time, so the message contains all of them
+            // right from the start
+            if let Some(pb) = &progress_spinner {
+                pb.set_message(
+                    format
+                        .subtitles
+                        .iter()
+                        .map(|(subtitle, cc)| {
+                            let mut progress_message = subtitle.locale.to_string();
+                            if *cc {
+                                progress_message += " (CC)";
+                            }
+                            if i.min(videos.len() - 1) != 0 {
+                                progress_message +=
+                                    &format!(" [Video: {}]", video_label(&videos, i));
+                            }
+                            progress_message
+                        })
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                )
+            }
 
-                let path = self
-                    .download_subtitle(subtitle.clone(), videos[i.min(videos.len() - 1)].length)
-                    .await?;
-                debug!(
-                    "Downloaded {} subtitles{}",
-                    subtitle.locale,
-                    cc.then_some(" (cc)").unwrap_or_default(),
-                );
-                subtitles.push(FFmpegSubtitleMeta {
-                    path,
-                    locale: subtitle.locale.clone(),
-                    cc: *cc,
-                    start_time: subtitle_offsets.get(&j).cloned(),
-                    video_idx: i,
-                })
+            let downloader = &self;
+            let subtitle_offsets = &subtitle_offsets;
+            let video_length = videos[i.min(videos.len() - 1)].length;
+            // `buffered` keeps the order of the subtitles, which is important as they are already
+            // sorted
+            let mut subtitle_downloads =
+                futures_util::stream::iter(format.subtitles.iter().enumerate())
+                    .map(|(j, (subtitle, cc))| async move {
+                        let path = downloader
+                            .download_subtitle(subtitle.clone(), video_length)
+                            .await?;
+                        debug!(
+                            "Downloaded {} subtitles{}",
+                            subtitle.locale,
+                            cc.then_some(" (cc)").unwrap_or_default(),
+                        );
+                        Ok::<FFmpegSubtitleMeta, anyhow::Error>(FFmpegSubtitleMeta {
+                            path: path.into(),
+                            locale: subtitle.locale.clone(),
+                            cc: *cc,
+                            start_time: subtitle_offsets.get(&j).cloned(),
+                            video_idx: i,
+                        })
+                    })
+                    .buffered(if self.download_threads == 0 {
+                        AUTO_THREADS_START
+                    } else {
+                        self.download_threads
+                    });
+            while let Some(subtitle) = subtitle_downloads.next().await {
+                subtitles.push(subtitle?)
             }
         }
 
@@ -486,30 +856,34 @@ impl Downloader {
                 let (file, path) = tempfile(".chapter")?.into_parts();
                 chapters = Some((
                     (file, path),
-                    [
-                        skip_events.recap.as_ref().map(|e| ("Recap", e)),
-                        skip_events.intro.as_ref().map(|e| ("Intro", e)),
-                        skip_events.credits.as_ref().map(|e| ("Credits", e)),
-                        skip_events.preview.as_ref().map(|e| ("Preview", e)),
-                    ]
-                    .into_iter()
-                    .flatten()
-                    .collect::<Vec<(&str, &SkipEventsEvent)>>(),
+                    skip_events_chapters(skip_events, &self.chapter_types),
                 ));
             }
         }
 
-        if self.download_fonts
-            && !self.force_hardsub
-            && dst.extension().unwrap_or_default().to_str().unwrap() == "mkv"
+        let mut thumbnail = None;
+        if let Some(url) = self
+            .formats
+            .iter()
+            .find_map(|f| f.metadata.thumbnail.as_ref())
         {
+            match self.download_thumbnail(url).await {
+                Ok(path) => thumbnail = Some(path),
+                Err(e) => warn!("Failed to download thumbnail, skipping it: {}", e),
+            }
+        }
+
+        if self.download_fonts && !self.force_hardsub && self.container(dst) == "mkv" {
             let mut font_names = vec![];
             for subtitle in subtitles.iter() {
                 font_names.extend(get_subtitle_stats(&subtitle.path)?)
             }
             real_dedup_vec(&mut font_names);
 
-            let progress_spinner = if log::max_level() == LevelFilter::Info {
+            let progress_spinner = if log::max_level() == LevelFilter::Info
+                && !self.progress_json
+                && !progress_hidden()
+            {
                 let progress_spinner = ProgressBar::new_spinner()
                     .with_style(
                         ProgressStyle::with_template(
@@ -523,6 +897,7 @@ impl Downloader {
                         .tick_strings(&["—", "\\", "|", "/", ""]),
                     )
                     .with_finish(ProgressFinish::Abandon);
+                let progress_spinner = self.add_progress(progress_spinner);
                 progress_spinner.enable_steady_tick(Duration::from_millis(100));
                 Some(progress_spinner)
             } else {
@@ -537,23 +912,128 @@ impl Downloader {
                     progress_message += &font_name;
                     pb.set_message(progress_message)
                 }
-                if let Some((font, cached)) = self.download_font(&font_name).await? {
-                    if cached {
-                        if let Some(pb) = &progress_spinner {
-                            let mut progress_message = pb.message();
-                            progress_message += " (cached)";
-                            pb.set_message(progress_message)
-                        }
-                        debug!("Downloaded font {} (cached)", font_name);
-                    } else {
-                        debug!("Downloaded font {}", font_name);
+                if let Some((font, origin)) = self.download_font(&font_name).await? {
+                    let suffix = match origin {
+                        FontOrigin::Downloaded => "",
+                        FontOrigin::Cached => " (cached)",
+                        FontOrigin::Local => " (local)",
+                    };
+                    if let Some(pb) = &progress_spinner {
+                        let mut progress_message = pb.message();
+                        progress_message += suffix;
+                        pb.set_message(progress_message)
                     }
+                    debug!("Downloaded font {}{}", font_name, suffix);
 
                     fonts.push(font)
+                } else {
+                    warn!(
+                        "Font '{}' is used by a subtitle but could not be found",
+                        font_name
+                    )
                 }
             }
         }
 
+        // the streams of the downloaded videos and audios, in the same order as the ffmpeg inputs
+        let sources = self
+            .formats
+            .iter()
+            .take(videos.len())
+            .map(|f| &f.video.0)
+            .chain(audio_sources.iter())
+            .collect();
+        self.mux_inputs(
+            dst,
+            MuxInputs {
+                videos,
+                audios,
+                subtitles,
+                fonts,
+                chapters,
+                thumbnail,
+                sources,
+                max_frames,
+                output_len,
+                trim_start,
+                synced,
+                fmt_space,
+            },
+        )
+        .await
+    }
+
+    /// Mux already existing files into `dst` without downloading anything. The files are used as
+    /// they are, so e.g. subtitles are neither fixed nor converted. Useful to troubleshoot muxing
+    /// issues or to re-mux tracks after they were edited.
+    pub async fn mux(
+        &self,
+        dst: &Path,
+        video: PathBuf,
+        audios: Vec<(PathBuf, Locale)>,
+        subtitles: Vec<(PathBuf, Locale)>,
+    ) -> Result<()> {
+        let (length, fps) = get_video_stats(&video)?;
+
+        self.mux_inputs(
+            dst,
+            MuxInputs {
+                videos: vec![FFmpegVideoMeta {
+                    path: InputPath::Local(video),
+                    length,
+                    start_time: None,
+                    label: "#1".to_string(),
+                }],
+                audios: audios
+                    .into_iter()
+                    .map(|(path, locale)| FFmpegAudioMeta {
+                        path: InputPath::Local(path),
+                        locale,
+                        start_time: None,
+                        video_idx: 0,
+                    })
+                    .collect(),
+                subtitles: subtitles
+                    .into_iter()
+                    .map(|(path, locale)| FFmpegSubtitleMeta {
+                        path: InputPath::Local(path),
+                        locale,
+                        cc: false,
+                        start_time: None,
+                        video_idx: 0,
+                    })
+                    .collect(),
+                fonts: vec![],
+                chapters: None,
+                thumbnail: None,
+                sources: vec![],
+                max_frames: (length.num_milliseconds() as f64 / 1000.0 * fps) as u64,
+                output_len: length,
+                trim_start: TimeDelta::zero(),
+                synced: false,
+                fmt_space: 0,
+            },
+        )
+        .await
+    }
+
+    /// Build the ffmpeg command from all inputs and run it to generate `dst`.
+    async fn mux_inputs(&self, dst: &Path, inputs: MuxInputs<'_>) -> Result<()> {
+        let MuxInputs {
+            videos,
+            audios,
+            subtitles,
+            fonts,
+            mut chapters,
+            thumbnail,
+            sources,
+            max_frames,
+            output_len,
+            trim_start,
+            synced,
+            fmt_space,
+        } = inputs;
+
         let mut input = vec![];
         let mut maps = vec![];
         let mut attachments = vec![];
@@ -563,6 +1043,7 @@ impl Downloader {
             if let Some(start_time) = meta.start_time {
                 input.extend(["-itsoffset".to_string(), format_time_delta(&start_time)])
             }
+            input.extend(self.trim_input_args());
             input.extend(["-i".to_string(), meta.path.to_string_lossy().to_string()]);
             maps.extend(["-map".to_string(), i.to_string()]);
             metadata.extend([
@@ -572,7 +1053,7 @@ impl Downloader {
                     if videos.len() == 1 {
                         "Default".to_string()
                     } else {
-                        format!("#{}", i + 1)
+                        meta.label.clone()
                     }
                 ),
             ]);
@@ -584,6 +1065,31 @@ impl Downloader {
             if let Some(start_time) = meta.start_time {
                 input.extend(["-itsoffset".to_string(), format_time_delta(&start_time)])
             }
+            input.extend(self.trim_input_args());
+            // synced audios of other formats may be longer than the video they're merged into
+            // (e.g. because of a longer recap). they're cut at the video end, as they would
+            // otherwise extend the output with audio only parts. the cut is done via the input
+            // duration, so the audio doesn't need to be re-encoded like with an 'atrim' filter
+            if synced && self.trim_start.is_none() && self.trim_end.is_none() {
+                if let Some(video) = videos.get(meta.video_idx) {
+                    let video_end = video.start_time.unwrap_or_default() + video.length;
+                    let audio_start = meta.start_time.unwrap_or_default();
+                    let audio_len = get_length(&meta.path)?;
+                    if audio_start + audio_len
+                        > video_end + TimeDelta::milliseconds(SYNC_CUT_TOLERANCE)
+                    {
+                        debug!(
+                            "Cutting {} audio to the video end ({} longer)",
+                            meta.locale,
+                            format_time_delta(&(audio_start + audio_len - video_end))
+                        );
+                        input.extend([
+                            "-t".to_string(),
+                            format_time_delta(&(video_end - audio_start)),
+                        ])
+                    }
+                }
+            }
             input.extend(["-i".to_string(), meta.path.to_string_lossy().to_string()]);
             maps.extend(["-map".to_string(), (i + videos.len()).to_string()]);
             metadata.extend([
@@ -603,9 +1109,9 @@ impl Downloader {
                         meta.locale.to_human_readable()
                     } else {
                         format!(
-                            "{} [Video: #{}]",
+                            "{} [Video: {}]",
                             meta.locale.to_human_readable(),
-                            meta.video_idx + 1
+                            video_label(&videos, meta.video_idx)
                         )
                     }
                 ),
@@ -616,15 +1122,36 @@ impl Downloader {
             attachments.extend(["-attach".to_string(), font.to_string_lossy().to_string()]);
             metadata.extend([
                 format!("-metadata:s:t:{}", i),
-                "mimetype=font/woff2".to_string(),
+                format!("mimetype={}", font_mimetype(font)),
             ])
         }
+        if let Some(path) = &thumbnail {
+            if self.container(dst) == "mkv" {
+                attachments.extend(["-attach".to_string(), path.to_string_lossy().to_string()]);
+                metadata.extend([
+                    format!("-metadata:s:t:{}", fonts.len()),
+                    "mimetype=image/jpeg".to_string(),
+                    format!("-metadata:s:t:{}", fonts.len()),
+                    "filename=cover.jpg".to_string(),
+                ])
+            }
+        }
 
         // this formats are supporting embedding subtitles into the video container instead of
         // burning it into the video stream directly
-        let container_supports_softsubs = !self.force_hardsub
-            && ["mkv", "mov", "mp4"]
-                .contains(&dst.extension().unwrap_or_default().to_str().unwrap());
+        let container_supports_softsubs =
+            !self.force_hardsub && SOFTSUB_CONTAINERS.contains(&self.container(dst).as_str());
+        // the subtitle which is burned into the video. a subtitle which is explicitly set to be
+        // burned in is additionally embedded as soft subtitle if the container supports it,
+        // otherwise the default subtitle is burned in as there's no other way to show it
+        let hardsub_position = match &self.hardsub {
+            Some(hardsub) => subtitles.iter().position(|meta| &meta.locale == hardsub),
+            None if !container_supports_softsubs => self
+                .default_subtitle
+                .as_ref()
+                .and_then(|locale| subtitles.iter().position(|meta| &meta.locale == locale)),
+            None => None,
+        };
 
         if container_supports_softsubs {
             for (i, meta) in subtitles.iter().enumerate() {
@@ -653,7 +1180,7 @@ impl Downloader {
                             title += " (CC)"
                         }
                         if videos.len() > 1 {
-                            title += &format!(" [Video: #{}]", meta.video_idx + 1)
+                            title += &format!(" [Video: {}]", video_label(&videos, meta.video_idx))
                         }
                         title
                     }),
@@ -662,102 +1189,153 @@ impl Downloader {
         }
 
         if let Some(((file, path), chapters)) = chapters.as_mut() {
-            write_ffmpeg_chapters(file, max_len, chapters)?;
-            input.extend(["-i".to_string(), path.to_string_lossy().to_string()]);
+            write_ffmpeg_chapters(file, output_len, trim_start, chapters, self.gap_chapters)?;
+            let chapter_idx = (videos.len()
+                + audios.len()
+                + container_supports_softsubs
+                    .then_some(subtitles.len())
+                    .unwrap_or_default())
+            .to_string();
+            // the input format is set explicitly as some ffmpeg versions are not detecting the
+            // chapter file as ffmetadata, which results in missing chapters in mp4 / mov files
+            input.extend([
+                "-f".to_string(),
+                "ffmetadata".to_string(),
+                "-i".to_string(),
+                path.to_string_lossy().to_string(),
+            ]);
             maps.extend([
                 "-map_metadata".to_string(),
-                (videos.len()
+                chapter_idx.clone(),
+                "-map_chapters".to_string(),
+                chapter_idx,
+            ]);
+            if ["mov", "mp4"].contains(&self.container(dst).as_str()) {
+                maps.extend(["-movflags".to_string(), "+faststart".to_string()])
+            }
+        }
+
+        // mp4 / mov have no attachments, the thumbnail is muxed as an additional video stream with
+        // the 'attached_pic' disposition instead. this doesn't work with hardsubs as the subtitle
+        // filter would be applied to the thumbnail too
+        let mut thumbnail_stream = None;
+        if let Some(path) = &thumbnail {
+            let extension = self.container(dst);
+            if ["mov", "mp4"].contains(&extension.as_str()) && hardsub_position.is_none() {
+                let thumbnail_idx = videos.len()
                     + audios.len()
                     + container_supports_softsubs
                         .then_some(subtitles.len())
-                        .unwrap_or_default())
-                .to_string(),
-            ])
+                        .unwrap_or_default()
+                    + chapters.is_some() as usize;
+                input.extend(["-i".to_string(), path.to_string_lossy().to_string()]);
+                maps.extend(["-map".to_string(), thumbnail_idx.to_string()]);
+                thumbnail_stream = Some(videos.len())
+            } else if extension != "mkv" {
+                debug!("Skipping thumbnail as the output container can't carry it")
+            }
         }
 
-        let preset_custom = matches!(self.ffmpeg_preset, FFmpegPreset::Custom(_));
-        let (input_presets, mut output_presets) = self.ffmpeg_preset.into_input_output_args();
-        let fifo = temp_named_pipe()?;
+        // global container tags. they're added after the chapter metadata mapping, so they overwrite
+        // tags with the same key. mkv accepts any key, other containers only support a specific set
+        // of keys and silently drop all others
+        for (key, value) in &self.container_metadata {
+            metadata.extend(["-metadata".to_string(), format!("{}={}", key, value)])
+        }
 
-        let mut command_args = vec![
-            "-y".to_string(),
-            "-hide_banner".to_string(),
-            "-vstats_file".to_string(),
-            fifo.path().to_string_lossy().to_string(),
-        ];
+        // the default preset is custom too, but it only copies the streams
+        let preset_custom = matches!(self.ffmpeg_preset, FFmpegPreset::Custom(_))
+            && self.ffmpeg_preset != FFmpegPreset::default();
+        let (input_presets, mut output_presets) =
+            self.ffmpeg_preset.clone().into_input_output_args();
+
+        let mut command_args = vec![];
         command_args.extend(input_presets);
         command_args.extend(input);
         command_args.extend(maps);
         command_args.extend(attachments);
         command_args.extend(metadata);
-        if !preset_custom {
-            if let Some(ffmpeg_threads) = self.ffmpeg_threads {
-                command_args.extend(vec!["-threads".to_string(), ffmpeg_threads.to_string()])
+
+        // webm only supports vp8 / vp9 / av1 video and opus / vorbis audio, so the h264 / aac
+        // streams delivered by crunchyroll must be re-encoded. subtitles must be webvtt
+        if self.container(dst) == "webm" {
+            remove_codec_copy(&mut output_presets);
+            if !output_presets.iter().any(|p| p == "-c:v") {
+                output_presets.extend(
+                    ["-crf", "31", "-b:v", "0", "-c:v", "libvpx-vp9"].map(|s| s.to_string()),
+                )
+            }
+            if !output_presets.iter().any(|p| p == "-c:a") {
+                output_presets.extend(["-c:a".to_string(), "libopus".to_string()])
+            }
+            if container_supports_softsubs && !subtitles.is_empty() {
+                output_presets.extend(["-c:s".to_string(), "webvtt".to_string()])
             }
         }
 
-        // set default subtitle
-        if let Some(default_subtitle) = self.default_subtitle {
-            if let Some(position) = subtitles.iter().position(|m| m.locale == default_subtitle) {
-                if container_supports_softsubs {
-                    match dst.extension().unwrap_or_default().to_str().unwrap() {
-                        "mov" | "mp4" => output_presets.extend([
-                            "-movflags".to_string(),
-                            "faststart".to_string(),
-                            "-c:s".to_string(),
-                            "mov_text".to_string(),
-                        ]),
-                        _ => (),
-                    }
-                } else {
-                    // remove '-c:v copy' and '-c:a copy' from output presets as its causes issues with
-                    // burning subs into the video
-                    let mut last = String::new();
-                    let mut remove_count = 0;
-                    for (i, s) in output_presets.clone().iter().enumerate() {
-                        if (last == "-c:v" || last == "-c:a") && s == "copy" {
-                            // remove last
-                            output_presets.remove(i - remove_count - 1);
-                            remove_count += 1;
-                            output_presets.remove(i - remove_count);
-                            remove_count += 1;
-                        }
-                        last.clone_from(s);
+        // burn the hardsub subtitle into the video
+        if let Some(position) = hardsub_position {
+            // remove '-c:v copy' and '-c:a copy' from output presets as its causes issues with
+            // burning subs into the video
+            remove_codec_copy(&mut output_presets);
+            // burning subs requires a re-encode. without explicit quality settings ffmpeg
+            // chooses the encoder defaults, so the quality is set here if the ffmpeg
+            // preset doesn't already do it
+            if !output_presets.iter().any(|p| p == "-crf" || p == "-q:v") {
+                output_presets.extend(["-crf".to_string(), self.hardsub_crf.to_string()])
+            }
+            if let Some(hardsub_preset) = &self.hardsub_preset {
+                output_presets.extend(["-preset".to_string(), hardsub_preset.clone()])
+            }
+
+            output_presets.extend([
+                "-vf".to_string(),
+                format!(
+                    "ass='{}'",
+                    // ffmpeg doesn't removes all ':' and '\' from the filename when using
+                    // the ass filter. well, on windows these characters are used in
+                    // absolute paths, so they have to be correctly escaped here
+                    if cfg!(windows) {
+                        subtitles
+                            .get(position)
+                            .unwrap()
+                            .path
+                            .to_str()
+                            .unwrap()
+                            .replace('\\', "\\\\")
+                            .replace(':', "\\:")
+                    } else {
+                        subtitles
+                            .get(position)
+                            .unwrap()
+                            .path
+                            .to_string_lossy()
+                            .to_string()
                     }
+                ),
+            ])
+        }
 
-                    output_presets.extend([
-                        "-vf".to_string(),
-                        format!(
-                            "ass='{}'",
-                            // ffmpeg doesn't removes all ':' and '\' from the filename when using
-                            // the ass filter. well, on windows these characters are used in
-                            // absolute paths, so they have to be correctly escaped here
-                            if cfg!(windows) {
-                                subtitles
-                                    .get(position)
-                                    .unwrap()
-                                    .path
-                                    .to_str()
-                                    .unwrap()
-                                    .replace('\\', "\\\\")
-                                    .replace(':', "\\:")
-                            } else {
-                                subtitles
-                                    .get(position)
-                                    .unwrap()
-                                    .path
-                                    .to_string_lossy()
-                                    .to_string()
-                            }
-                        ),
-                    ])
+        // set default subtitle
+        if let Some(default_subtitle) = &self.default_subtitle {
+            if container_supports_softsubs
+                && subtitles.iter().any(|m| &m.locale == default_subtitle)
+            {
+                match self.container(dst).as_str() {
+                    "mov" | "mp4" => output_presets.extend([
+                        "-movflags".to_string(),
+                        "faststart".to_string(),
+                        "-c:s".to_string(),
+                        "mov_text".to_string(),
+                    ]),
+                    _ => (),
                 }
             }
 
             if container_supports_softsubs {
                 if let Some(position) = subtitles
                     .iter()
-                    .position(|meta| meta.locale == default_subtitle)
+                    .position(|meta| &meta.locale == default_subtitle)
                 {
                     command_args.extend([
                         format!("-disposition:s:s:{}", position),
@@ -767,17 +1345,98 @@ impl Downloader {
             }
         }
 
-        // set the 'forced' flag to CC subtitles
-        for (i, subtitle) in subtitles.iter().enumerate() {
-            if !subtitle.cc {
-                continue;
+        command_args.extend(forced_subtitle_args(&subtitles));
+
+        // players usually pick the first audio track, so the default flag is set explicitly on the
+        // preferred audio and cleared from all others
+        if let Some(default_audio) = &self.default_audio {
+            if let Some(position) = audios.iter().position(|meta| &meta.locale == default_audio) {
+                for i in 0..audios.len() {
+                    command_args.extend([
+                        format!("-disposition:a:{}", i),
+                        if i == position { "default" } else { "0" }.to_string(),
+                    ])
+                }
+            } else {
+                warn!(
+                    "Default audio {} is not available, the first audio track is used as default",
+                    default_audio
+                )
+            }
+        }
+
+        if !preset_custom {
+            // when copying the streams, ffmpeg barely uses the cpu and the thread count doesn't
+            // matter. when re-encoding (e.g. because of hardsubs), all cpu threads are used if no
+            // thread count is given
+            let reencode = !output_presets
+                .windows(2)
+                .any(|w| w[0] == "-c:v" && w[1] == "copy");
+            if let Some(ffmpeg_threads) =
+                self.ffmpeg_threads.or_else(|| reencode.then(num_cpus::get))
+            {
+                command_args.extend(vec!["-threads".to_string(), ffmpeg_threads.to_string()])
+            }
+        }
+
+        // the pixel format can only be changed if the video is re-encoded. a pixel format which is
+        // already set by the preset (e.g. via custom ffmpeg arguments) takes precedence
+        if let Some(pix_fmt) = &self.pix_fmt {
+            if self.audio_only
+                || output_presets
+                    .windows(2)
+                    .any(|w| w[0] == "-c:v" && w[1] == "copy")
+            {
+                debug!(
+                    "Ignoring pixel format '{}' as the video isn't re-encoded",
+                    pix_fmt
+                )
+            } else if !output_presets.iter().any(|p| p == "-pix_fmt") {
+                output_presets.extend(["-pix_fmt".to_string(), pix_fmt.clone()])
+            }
+        }
+
+        // silence trimming and loudness normalization are audio filters, so the audio must be
+        // re-encoded if any of them is used. without them, the audio is left untouched
+        let audio_filters: Vec<&str> = [
+            self.trim_silence.then_some(TRIM_SILENCE_FILTER),
+            self.normalize_audio.then_some("loudnorm"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if !audio_filters.is_empty() && !audios.is_empty() {
+            remove_audio_codec_copy(&mut output_presets);
+            // mp3 output gets its own encoder further down
+            let mp3 = self.audio_only && self.container(dst) == "mp3";
+            if !mp3 && !output_presets.iter().any(|p| p == "-c:a") {
+                output_presets
+                    .extend(["-c:a", "aac", "-b:a", AUDIO_REENCODE_BITRATE].map(|s| s.to_string()))
             }
+            output_presets.extend(["-filter:a".to_string(), audio_filters.join(",")])
+        }
 
-            command_args.extend([format!("-disposition:s:s:{}", i), "forced".to_string()])
+        // the thumbnail must not be re-encoded by the video codec of the preset
+        if let Some(i) = thumbnail_stream {
+            output_presets.extend([
+                format!("-c:v:{}", i),
+                "copy".to_string(),
+                format!("-disposition:v:{}", i),
+                "attached_pic".to_string(),
+            ])
         }
 
         command_args.extend(output_presets);
-        if let Some(output_format) = self.output_format {
+        // mp3 cannot contain the aac audio delivered by crunchyroll, so it must be re-encoded
+        if self.audio_only && self.container(dst) == "mp3" {
+            command_args.extend([
+                "-c:a".to_string(),
+                "libmp3lame".to_string(),
+                "-q:a".to_string(),
+                "2".to_string(),
+            ])
+        }
+        if let Some(output_format) = self.ffmpeg_output_format() {
             command_args.extend(["-f".to_string(), output_format]);
         }
 
@@ -802,54 +1461,350 @@ impl Downloader {
             }
         }
 
-        let ffmpeg = Command::new("ffmpeg")
-            // pass ffmpeg stdout to real stdout only if output file is stdout
-            .stdout(if dst.to_str().unwrap() == "-" {
-                Stdio::inherit()
-            } else {
-                Stdio::null()
-            })
-            .stderr(Stdio::piped())
-            .args(command_args)
-            .spawn()?;
-        let ffmpeg_progress_cancel = CancellationToken::new();
-        let ffmpeg_progress_cancellation_token = ffmpeg_progress_cancel.clone();
-        let ffmpeg_progress = tokio::spawn(async move {
-            ffmpeg_progress(
-                max_frames,
-                fifo,
-                format!("{:<1$}", "Generating output file", fmt_space + 1),
-                ffmpeg_progress_cancellation_token,
-            )
-            .await
+        // the guard is created after all temporary files, so it's dropped (and copies them) before
+        // they're removed
+        let _keep_temp = self.keep_temp.as_ref().map(|dir| {
+            let mut files = vec![];
+            for (i, video) in videos.iter().enumerate() {
+                files.push((format!("video-{}", i), video.path.to_path_buf()))
+            }
+            for (i, audio) in audios.iter().enumerate() {
+                files.push((
+                    format!("audio-{}-{}", i, audio.locale),
+                    audio.path.to_path_buf(),
+                ))
+            }
+            for (i, subtitle) in subtitles.iter().enumerate() {
+                files.push((
+                    format!(
+                        "subtitle-{}-{}{}",
+                        i,
+                        subtitle.locale,
+                        if subtitle.cc { "-cc" } else { "" }
+                    ),
+                    subtitle.path.to_path_buf(),
+                ))
+            }
+            if let Some(((_, path), _)) = &chapters {
+                files.push(("chapters".to_string(), path.to_path_buf()))
+            }
+            if let Some(path) = &thumbnail {
+                files.push(("cover".to_string(), path.to_path_buf()))
+            }
+            KeepTemp {
+                dir: dir.clone(),
+                prefix: dst
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                files,
+            }
         });
 
-        let result = ffmpeg.wait_with_output()?;
-        if !result.status.success() {
+        // a segment might be corrupted even though it was downloaded without any transport error.
+        // ffmpeg then fails with an 'Invalid data' error. if this happens, the affected input is
+        // downloaded again and ffmpeg is re-run once
+        let mut redownloaded = false;
+        let progress_json = self.progress_json;
+        loop {
+            let multi_progress = self.multi_progress.clone();
+            let mut args = vec!["-y".to_string(), "-hide_banner".to_string()];
+            // ffmpeg writes the vstats to a named pipe, which doesn't work reliable with every
+            // ffmpeg build. the progress file is a regular file which is read like `tail -f`
+            // instead, which works everywhere but is polled. it's always written, so that it can
+            // be used as fallback if no vstats are received
+            let vstats: Option<Box<dyn AsyncRead + Send + Unpin>> = if self.progress_pipe {
+                let fifo = temp_named_pipe()?;
+                args.extend([
+                    "-vstats_file".to_string(),
+                    fifo.path().to_string_lossy().to_string(),
+                ]);
+                Some(Box::new(fifo))
+            } else {
+                None
+            };
+            let progress_file = temp_tail_file()?;
+            args.extend([
+                "-progress".to_string(),
+                progress_file.path().to_string_lossy().to_string(),
+            ]);
+            let progress_stats: Box<dyn AsyncRead + Send + Unpin> = Box::new(progress_file);
+            args.extend(command_args.clone());
+
+            let ffmpeg = Command::new(ffmpeg_path())
+                // pass ffmpeg stdout to real stdout only if output file is stdout
+                .stdout(if dst.to_str().unwrap() == "-" {
+                    Stdio::inherit()
+                } else {
+                    Stdio::null()
+                })
+                .stderr(Stdio::piped())
+                .args(args)
+                .spawn()?;
+            let ffmpeg_progress_cancel = CancellationToken::new();
+            let ffmpeg_progress_cancellation_token = ffmpeg_progress_cancel.clone();
+            let ffmpeg_progress = tokio::spawn(async move {
+                ffmpeg_progress(
+                    max_frames,
+                    vstats,
+                    progress_stats,
+                    format!("{:<1$}", "Generating output file", fmt_space + 1),
+                    progress_json,
+                    multi_progress,
+                    ffmpeg_progress_cancellation_token,
+                )
+                .await
+            });
+
+            let result = ffmpeg.wait_with_output()?;
+            if result.status.success() {
+                ffmpeg_progress_cancel.cancel();
+                ffmpeg_progress.await??;
+
+                if self.verify_output && dst.to_string_lossy() != "-" && !is_special_file(dst) {
+                    if let Err(e) = verify_output(
+                        dst,
+                        output_len,
+                        videos.len(),
+                        audios.len(),
+                        if container_supports_softsubs {
+                            subtitles.len()
+                        } else {
+                            0
+                        },
+                    ) {
+                        // remove the broken output file so that it isn't mistaken as a complete
+                        // download, e.g. by `--skip-existing`
+                        fs::remove_file(dst)?;
+                        return Err(e);
+                    }
+                    debug!("Verified output file '{}'", dst.to_string_lossy())
+                }
+                if (self.subtitle_filename_template.is_some()
+                    || self.subtitle_sidecar_format.is_some())
+                    && dst.to_string_lossy() != "-"
+                    && !is_special_file(dst)
+                {
+                    self.write_subtitle_sidecars(
+                        dst,
+                        self.subtitle_filename_template
+                            .as_deref()
+                            .unwrap_or(DEFAULT_SUBTITLE_FILENAME_TEMPLATE),
+                        self.subtitle_sidecar_format.as_deref().unwrap_or("ass"),
+                        &subtitles,
+                    )?
+                }
+                return Ok(());
+            }
             ffmpeg_progress.abort();
-            bail!("{}", String::from_utf8_lossy(result.stderr.as_slice()))
+
+            let stderr = String::from_utf8_lossy(result.stderr.as_slice()).to_string();
+            let invalid_input = find_invalid_ffmpeg_input(
+                &stderr,
+                &videos
+                    .iter()
+                    .map(|v| &*v.path)
+                    .chain(audios.iter().map(|a| &*a.path))
+                    .collect::<Vec<&Path>>(),
+            );
+            // inputs which weren't downloaded (see `Downloader::mux`) cannot be downloaded again
+            let Some(idx) = invalid_input.filter(|idx| !redownloaded && *idx < sources.len())
+            else {
+                // a partially written output file would be mistaken as a complete download, e.g.
+                // by `--skip-existing`
+                if !self.keep_partial && dst.to_string_lossy() != "-" && !is_special_file(dst) {
+                    let _ = fs::remove_file(dst);
+                }
+                debug!("ffmpeg output: {}", stderr);
+                // the full ffmpeg log is mostly very long, the actual error is in the last lines
+                let lines: Vec<&str> = stderr.lines().collect();
+                bail!(FFmpegError(
+                    lines[lines.len().saturating_sub(FFMPEG_ERROR_LINES)..].join("\n")
+                ))
+            };
+            redownloaded = true;
+
+            if idx < videos.len() {
+                warn!(
+                    "ffmpeg reported invalid data in video #{}, downloading it again",
+                    idx + 1
+                );
+                let mut file = fs::File::create(&videos[idx].path)?;
+                self.download_segments(
+                    &mut file,
+                    format!(
+                        "{:<1$}",
+                        format!("Downloading video #{}", idx + 1),
+                        fmt_space
+                    ),
+                    sources[idx],
+                    None,
+                )
+                .await?;
+            } else {
+                let audio_idx = idx - videos.len();
+                let locale = &audios[audio_idx].locale;
+                warn!(
+                    "ffmpeg reported invalid data in {} audio, downloading it again",
+                    locale
+                );
+                let mut file = fs::File::create(&audios[audio_idx].path)?;
+                self.download_segments(
+                    &mut file,
+                    format!("{:<1$}", format!("Downloading {} audio", locale), fmt_space),
+                    sources[idx],
+                    None,
+                )
+                .await?;
+            }
         }
-        ffmpeg_progress_cancel.cancel();
-        ffmpeg_progress.await?
     }
 
-    async fn check_free_space(
+    /// Add the progress bar to [`Downloader::multi_progress`], if set.
+    fn add_progress(&self, progress: ProgressBar) -> ProgressBar {
+        if let Some(multi_progress) = &self.multi_progress {
+            multi_progress.add(progress)
+        } else {
+            progress
+        }
+    }
+
+    /// Write all subtitles next to `dst`, converted to `format` (`ass`, `vtt` or `srt`). The
+    /// subtitle file name is the name of `dst` with the rendered `template` in front of the
+    /// extension, e.g. `video.eng.forced.ass`.
+    fn write_subtitle_sidecars(
         &self,
         dst: &Path,
-    ) -> Result<(Option<(PathBuf, u64)>, Option<(PathBuf, u64)>)> {
-        let mut all_stream_data = vec![];
-        for format in &self.formats {
-            all_stream_data.push(&format.video.0);
-            all_stream_data.extend(format.audios.iter().map(|(a, _)| a))
+        template: &str,
+        format: &str,
+        subtitles: &[FFmpegSubtitleMeta],
+    ) -> Result<()> {
+        let stem = dst.file_stem().unwrap_or_default().to_string_lossy();
+        let mut written: Vec<PathBuf> = vec![];
+
+        for subtitle in subtitles {
+            let rendered = template
+                .replace(
+                    "{locale}",
+                    self.subtitle_locale_output_map
+                        .get(&subtitle.locale)
+                        .unwrap_or(&subtitle.locale.to_string()),
+                )
+                .replace("{iso639_2}", &iso639_2_code(&subtitle.locale))
+                .replace("{cc}", if subtitle.cc { "cc" } else { "" })
+                // cc subtitles get the 'forced' flag in the output file too
+                .replace("{forced}", if subtitle.cc { "forced" } else { "" });
+            // empty tokens would leave multiple or trailing dots in the filename
+            let suffix = rendered
+                .split('.')
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<&str>>()
+                .join(".");
+            let name = format!("{}.{}.{}", stem, sanitize(suffix, true, false), format);
+            let path = dst.with_file_name(name);
+
+            if written.contains(&path) {
+                warn!(
+                    "Subtitle file '{}' was already written for another subtitle, skipping {} subtitle. Use a more specific subtitle filename template (e.g. with '{{cc}}') to prevent this",
+                    path.to_string_lossy(),
+                    subtitle.locale
+                );
+                continue;
+            }
+            // the downloaded subtitles are always ass, so they're only converted if another format
+            // is requested
+            match format {
+                "vtt" => fs::write(
+                    &path,
+                    SSA::parse(fs::read_to_string(&subtitle.path)?)?
+                        .to_vtt()
+                        .to_string(),
+                )?,
+                "srt" => fs::write(
+                    &path,
+                    SSA::parse(fs::read_to_string(&subtitle.path)?)?
+                        .to_srt()
+                        .to_string(),
+                )?,
+                _ => {
+                    fs::copy(&subtitle.path, &path)?;
+                }
+            }
+            debug!("Wrote subtitle file '{}'", path.to_string_lossy());
+            written.push(path)
         }
-        let mut estimated_required_space: u64 = 0;
-        for stream_data in all_stream_data {
-            let segments = stream_data.segments();
 
-            // sum the length of all streams up
-            estimated_required_space += estimate_stream_data_file_size(stream_data, &segments);
+        Ok(())
+    }
+
+    /// The container of the output file which all container specific decisions (softsubs, fonts,
+    /// chapters, ...) are based on. A container set via [`DownloadBuilder::container`] takes
+    /// precedence over the extension of `dst`.
+    fn container(&self, dst: &Path) -> String {
+        self.container.clone().unwrap_or_else(|| {
+            dst.extension()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        })
+    }
+
+    /// The format which is passed to ffmpeg via `-f`. If no format is set explicitly but a
+    /// container is, ffmpeg must be told about it as it would infer the format from the file
+    /// extension otherwise.
+    fn ffmpeg_output_format(&self) -> Option<String> {
+        self.output_format
+            .clone()
+            .or_else(|| match self.container.as_deref() {
+                Some("mkv") => Some("matroska".to_string()),
+                Some(container) => Some(container.to_string()),
+                None => None,
+            })
+    }
+
+    /// Print the ffmpeg command which would be used to generate the output file. As nothing is
+    /// downloaded, the inputs are only placeholders for the files which would have been downloaded.
+    fn print_dry_run_command(&self, dst: &Path) {
+        let (input_presets, output_presets) = self.ffmpeg_preset.clone().into_input_output_args();
+
+        let mut args = vec!["ffmpeg".to_string()];
+        args.extend(input_presets);
+        for (i, format) in self.formats.iter().enumerate() {
+            if !self.audio_only {
+                args.extend(["-i".to_string(), format!("<video #{}>", i + 1)])
+            }
+            for (_, locale) in &format.audios {
+                args.extend(["-i".to_string(), format!("<{} audio>", locale)])
+            }
+            for (subtitle, cc) in &format.subtitles {
+                args.extend([
+                    "-i".to_string(),
+                    format!(
+                        "<{} subtitle{}>",
+                        subtitle.locale,
+                        cc.then_some(" (cc)").unwrap_or_default()
+                    ),
+                ])
+            }
+        }
+        args.extend(output_presets);
+        if let Some(output_format) = self.ffmpeg_output_format() {
+            args.extend(["-f".to_string(), output_format])
         }
+        args.push(dst.to_string_lossy().to_string());
 
+        tab_info!(
+            "FFmpeg command: {}",
+            shlex::try_join(args.iter().map(|a| a.as_str())).unwrap_or_else(|_| args.join(" "))
+        )
+    }
+
+    async fn check_free_space(
+        &self,
+        dst: &Path,
+        estimated_required_space: u64,
+    ) -> Result<(Option<(PathBuf, u64)>, Option<(PathBuf, u64)>)> {
         let tmp_stat = fs2::statvfs(temp_directory()).unwrap();
         let mut dst_file = if dst.is_absolute() {
             dst.to_path_buf()
@@ -929,27 +1884,43 @@ impl Downloader {
             "vtt" => VTT::parse(String::from_utf8_lossy(&buf))?.to_ssa(),
             _ => bail!("unknown subtitle format: {}", subtitle.format),
         };
-        // subtitles aren't always correct sorted and video players may have issues with that. to
-        // prevent issues, the subtitles are sorted
-        // (https://github.com/crunchy-labs/crunchy-cli/issues/208)
-        ass.events.sort_by(|a, b| a.start.cmp(&b.start));
-        // it might be the case that the start and/or end time are greater than the actual video
-        // length. this might also result in issues with video players, thus the times are stripped
-        // to be at most as long as `max_length`
-        // (https://github.com/crunchy-labs/crunchy-cli/issues/32)
-        for i in (0..ass.events.len()).rev() {
-            let max_len = Time::from_hms(0, 0, 0)
-                .unwrap()
-                .add(Duration::from_millis(max_length.num_milliseconds() as u64));
-
-            if ass.events[i].start > max_len {
-                if ass.events[i].end > max_len {
-                    ass.events[i].start = max_len
-                }
-                ass.events[i].end = max_len
-            } else {
-                break;
-            }
+        // shift all events by the subtitle offset. a language specific offset takes precedence over
+        // the global one. the events are shifted before sorting and stripping them to `max_length`,
+        // so both still apply to the shifted times
+        if let Some((_, offset)) = self
+            .subtitle_offset
+            .iter()
+            .rev()
+            .find(|(locale, _)| locale.as_ref() == Some(&subtitle.locale))
+            .or_else(|| {
+                self.subtitle_offset
+                    .iter()
+                    .rev()
+                    .find(|(locale, _)| locale.is_none())
+            })
+        {
+            shift_subtitle_events(&mut ass, *offset)
+        }
+        sort_subtitle_events(&mut ass, max_length);
+
+        // if the output is trimmed, the subtitles are shifted to the new start and all events which
+        // are not within the trimmed time window are removed
+        if self.trim_start.is_some() || self.trim_end.is_some() {
+            let to_millis = |t: Time| (t - Time::MIDNIGHT).whole_milliseconds() as i64;
+            let from_millis = |ms: i64| Time::MIDNIGHT.add(Duration::from_millis(ms as u64));
+            let trim_start = self.trim_start.unwrap_or_default().num_milliseconds();
+            let trim_end = self.trim_end.map(|e| e.num_milliseconds());
+
+            ass.events.retain_mut(|event| {
+                let start = to_millis(event.start).max(trim_start);
+                let end = trim_end.map_or(to_millis(event.end), |e| to_millis(event.end).min(e));
+                if end <= start {
+                    return false;
+                }
+                event.start = from_millis(start - trim_start);
+                event.end = from_millis(end - trim_start);
+                true
+            })
         }
 
         // without this additional info, subtitle look very messy in some video player
@@ -958,6 +1929,10 @@ impl Downloader {
             .additional_fields
             .insert("ScaledBorderAndShadow".to_string(), "yes".to_string());
 
+        if let Some(subtitle_style) = &self.subtitle_style {
+            apply_subtitle_style(&mut ass, &SSA::parse(fs::read_to_string(subtitle_style)?)?)
+        }
+
         let tempfile = tempfile(".ass")?;
         let path = tempfile.into_temp_path();
 
@@ -966,31 +1941,78 @@ impl Downloader {
         Ok(path)
     }
 
-    async fn download_font(&self, name: &str) -> Result<Option<(PathBuf, bool)>> {
-        let Some((_, font_file)) = FONTS.iter().find(|(f, _)| f == &name) else {
+    /// Input arguments which are trimming an input to the `trim_start` and `trim_end` time window.
+    fn trim_input_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if let Some(trim_start) = &self.trim_start {
+            args.extend(["-ss".to_string(), format_time_delta(trim_start)])
+        }
+        if let Some(trim_end) = &self.trim_end {
+            args.extend([
+                "-t".to_string(),
+                format_time_delta(&(*trim_end - self.trim_start.unwrap_or_default())),
+            ])
+        }
+        args
+    }
+
+    async fn download_thumbnail(&self, url: &str) -> Result<TempPath> {
+        let image = self
+            .client
+            .get(url)
+            .timeout(Duration::from_secs(60))
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let (mut file, path) = tempfile(".jpg")?.into_parts();
+        file.write_all(&image)?;
+        Ok(path)
+    }
+
+    async fn download_font(&self, name: &str) -> Result<Option<(PathBuf, FontOrigin)>> {
+        let font_file = FONTS
+            .iter()
+            .find(|(f, _)| f == &name)
+            .map(|(_, font_file)| *font_file);
+
+        if let Some(font_dir) = &self.font_dir {
+            if let Some(file) = find_local_font(font_dir, name, font_file)? {
+                return Ok(Some((file, FontOrigin::Local)));
+            }
+        }
+
+        let Some(font_file) = font_file else {
             return Ok(None);
         };
 
         let cache_dir = cache_dir("fonts")?;
         let file = cache_dir.join(font_file);
         if file.exists() {
-            return Ok(Some((file, true)));
+            return Ok(Some((file, FontOrigin::Cached)));
         }
 
         // the speed limiter does not apply to this
-        let font = self
+        let response = self
             .client
             .get(format!(
                 "https://static.crunchyroll.com/vilos-v2/web/vilos/assets/libass-fonts/{}",
                 font_file
             ))
             .send()
-            .await?
-            .bytes()
-            .await?;
+            .await
+            .and_then(|r| r.error_for_status());
+        let font = match response {
+            Ok(response) => response.bytes().await?,
+            Err(e) => {
+                warn!("Failed to download font '{}': {}", name, e);
+                return Ok(None);
+            }
+        };
         fs::write(&file, font)?;
 
-        Ok(Some((file, false)))
+        Ok(Some((file, FontOrigin::Downloaded)))
     }
 
     async fn download_segments(
@@ -1010,42 +2032,81 @@ impl Downloader {
 
         let count = Arc::new(Mutex::new(0));
 
-        let progress = if log::max_level() == LevelFilter::Info {
+        let progress = if log::max_level() == LevelFilter::Info
+            && (self.progress_json || !progress_hidden())
+        {
             let estimated_file_size = estimate_stream_data_file_size(stream_data, &segments);
 
-            let progress = ProgressBar::new(estimated_file_size)
-                .with_style(
-                    ProgressStyle::with_template(
-                        ":: {msg} {bytes:>10} {bytes_per_sec:>12} [{wide_bar}] {percent:>3}%",
-                    )
-                    .unwrap()
-                    .progress_chars("##-"),
+            // the progress bar is still used to track the progress in json mode, but it's not drawn
+            let progress = ProgressBar::with_draw_target(
+                Some(estimated_file_size),
+                if self.progress_json {
+                    ProgressDrawTarget::hidden()
+                } else {
+                    ProgressDrawTarget::stderr()
+                },
+            )
+            .with_style(
+                ProgressStyle::with_template(
+                    ":: {msg} {bytes:>10} {bytes_per_sec:>12} [{wide_bar}] {percent:>3}% {eta:>4}",
                 )
-                .with_message(message)
-                .with_finish(ProgressFinish::Abandon);
-            Some(progress)
+                .unwrap()
+                .progress_chars("##-"),
+            )
+            .with_message(message)
+            .with_finish(ProgressFinish::Abandon);
+            Some(if self.progress_json {
+                progress
+            } else {
+                self.add_progress(progress)
+            })
         } else {
             None
         };
 
-        let cpus = self.download_threads.min(segments.len());
-        let mut segs: Vec<Vec<StreamSegment>> = Vec::with_capacity(cpus);
-        for _ in 0..cpus {
-            segs.push(vec![])
-        }
-        for (i, segment) in segments.clone().into_iter().enumerate() {
-            segs[i - ((i / cpus) * cpus)].push(segment);
+        // a `download_threads` value of 0 means that the thread count is tuned automatically. in
+        // this case the maximal number of threads is spawned but only as many as the tuner allows
+        // are downloading at the same time
+        let tuner = (self.download_threads == 0).then(|| Arc::new(ThreadTuner::new()));
+        let cpus = if tuner.is_some() {
+            AUTO_THREADS_MAX
+        } else {
+            self.download_threads
         }
+        .min(segments.len());
+        // with the 'interleaved' segment order, all threads take the next segment to download from
+        // this shared index, so the segments are downloaded roughly in order regardless of how many
+        // threads are currently active. with the 'sequential' order, every thread has its own index
+        // and downloads a contiguous block of segments instead
+        let next_segment = Arc::new(AtomicUsize::new(0));
+        let shared_segments = Arc::new(segments.clone());
+        let block_size = segments.len().div_ceil(cpus.max(1));
+
+        // a buffer capacity of 0 writes everything directly to the underlying writer
+        let mut writer = BufWriter::with_capacity(self.write_buffer_size.unwrap_or(0), writer);
+        let mut last_flush = Instant::now();
 
         let (sender, mut receiver) = unbounded_channel();
 
         let mut join_set: JoinSet<Result<()>> = JoinSet::new();
-        for num in 0..cpus {
+        for i in 0..cpus {
             let thread_sender = sender.clone();
-            let thread_segments = segs.remove(0);
+            let thread_segments = shared_segments.clone();
+            let (thread_next_segment, thread_end_segment) = if self.segment_order == "sequential" {
+                (
+                    Arc::new(AtomicUsize::new(i * block_size)),
+                    ((i + 1) * block_size).min(total_segments),
+                )
+            } else {
+                (next_segment.clone(), total_segments)
+            };
+            let thread_tuner = tuner.clone();
             let thread_client = self.client.clone();
             let mut thread_rate_limiter = self.rate_limiter.clone();
             let thread_count = count.clone();
+            let thread_retries = self.download_retries;
+            let thread_retry_backoff = self.retry_backoff;
+            let thread_segment_timeout = self.segment_timeout;
             join_set.spawn(async move {
                 let after_download_sender = thread_sender.clone();
 
@@ -1053,30 +2114,62 @@ impl Downloader {
                 // catch errors which get returned with `...?` and `bail!(...)` and that the thread
                 // itself can report that an error has occurred
                 let download = || async move {
-                    for (i, segment) in thread_segments.into_iter().enumerate() {
+                    loop {
+                        let permit = match &thread_tuner {
+                            Some(tuner) => Some(tuner.semaphore.acquire().await?),
+                            None => None,
+                        };
+                        let pos = thread_next_segment.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                        if pos >= thread_end_segment {
+                            break;
+                        }
+                        let Some(segment) = thread_segments.get(pos) else {
+                            break;
+                        };
+                        let start = Instant::now();
+
                         let mut retry_count = 0;
                         let buf = loop {
-                            let request = thread_client
-                                .get(&segment.url)
-                                .timeout(Duration::from_secs(60));
+                            let mut request = thread_client.get(&segment.url);
+                            // a timeout of 0 waits until the segment is downloaded, regardless how long it takes
+                            if thread_segment_timeout > 0 {
+                                request = request.timeout(Duration::from_secs(thread_segment_timeout));
+                            }
                             let response = if let Some(rate_limiter) = &mut thread_rate_limiter {
                                 rate_limiter.call(request.build()?).await.map_err(anyhow::Error::new)
                             } else {
                                 request.send().await.map_err(anyhow::Error::new)
                             };
 
-                            let err = match response {
+                            // a segment is corrupt if the response has an error status (the body
+                            // is an error page then) or is empty. corrupt segments are fetched
+                            // again like segments which failed to download
+                            let (err, corrupt, status) = match response {
+                                Ok(r) if !r.status().is_success() => (anyhow::anyhow!("invalid response status {}", r.status()), true, Some(r.status())),
                                 Ok(r) => match r.bytes().await {
+                                    Ok(b) if b.is_empty() => (anyhow::anyhow!("empty response"), true, None),
                                     Ok(b) => break b.to_vec(),
-                                    Err(e) => anyhow::Error::new(e)
+                                    Err(e) => (anyhow::Error::new(e), false, None)
                                 }
-                                Err(e) => e,
+                                Err(e) => (e, false, None),
                             };
 
-                            if retry_count == 5 {
-                                bail!("Max retry count reached ({}), multiple errors occurred while receiving segment {}: {}", retry_count, num + (i * cpus), err)
+                            if retry_count == thread_retries {
+                                return Err(SegmentError {
+                                    message: format!("Max retry count reached ({}), multiple errors occurred while receiving segment {}: {}", retry_count, pos, err),
+                                    status,
+                                }.into())
+                            }
+                            if corrupt {
+                                debug!("Received corrupt segment {} ({}). Fetching it again, {} out of {} retries left", pos, err, thread_retries - retry_count, thread_retries);
+                            } else {
+                                debug!("Failed to download segment {} ({}). Retrying, {} out of {} retries left", pos, err, thread_retries - retry_count, thread_retries);
+                            }
+
+                            if thread_retry_backoff > 0 {
+                                let backoff = thread_retry_backoff.saturating_mul(2u64.saturating_pow(retry_count));
+                                tokio::time::sleep(Duration::from_millis(backoff)).await
                             }
-                            debug!("Failed to download segment {} ({}). Retrying, {} out of 5 retries left", num + (i * cpus), err, 5 - retry_count);
 
                             retry_count += 1;
                         };
@@ -1084,15 +2177,20 @@ impl Downloader {
                         let mut c = thread_count.lock().await;
                         debug!(
                             "Downloaded segment [{}/{} {:.2}%] {}",
-                            num + (i * cpus) + 1,
+                            pos + 1,
                             total_segments,
                             ((*c + 1) as f64 / total_segments as f64) * 100f64,
                             segment.url
                         );
 
-                        thread_sender.send((num as i32 + (i * cpus) as i32, buf))?;
+                        thread_sender.send((pos as i32, buf))?;
 
                         *c += 1;
+                        drop(c);
+
+                        if let (Some(tuner), Some(permit)) = (&thread_tuner, permit) {
+                            tuner.finish(permit, start.elapsed(), retry_count)
+                        }
                     }
                     Ok(())
                 };
@@ -1115,20 +2213,49 @@ impl Downloader {
         // the segment number and the values the corresponding bytes
         let mut data_pos = 0;
         let mut buf: BTreeMap<i32, Vec<u8>> = BTreeMap::new();
+        // if `concurrent_fragment_writes` is set, out of order segments are written to temporary
+        // files instead of `buf` to bound the memory usage. the files are created via `tempfile` so
+        // they're also removed on ctrl-c
+        let mut file_buf: BTreeMap<i32, TempPath> = BTreeMap::new();
+        // the size of the segments which are downloaded so far. the bandwidth of a stream is only an
+        // average value which can be quite off for variable bitrate streams, so after some
+        // segments are downloaded, their actual size is used to extrapolate the total size
+        let total_secs: f64 = segments.iter().map(|s| s.length.as_secs_f64()).sum();
+        let mut downloaded_count = 0;
+        let mut downloaded_bytes = 0;
+        let mut downloaded_secs = 0.0;
         while let Some((pos, bytes)) = receiver.recv().await {
             // if the position is lower than 0, an error occurred in the sending download thread
             if pos < 0 {
                 break;
             }
 
+            let segment_len = segments.get(pos as usize).unwrap().length;
+            let bytes_len = bytes.len() as u64;
+            downloaded_count += 1;
+            downloaded_bytes += bytes_len;
+            downloaded_secs += segment_len.as_secs_f64();
+            if let Some(download_stats) = &self.download_stats {
+                download_stats
+                    .bytes
+                    .fetch_add(bytes_len, std::sync::atomic::Ordering::SeqCst);
+            }
+
             if let Some(p) = &progress {
-                let progress_len = p.length().unwrap();
-                let estimated_segment_len = (stream_data.bandwidth / 8)
-                    * segments.get(pos as usize).unwrap().length.as_secs();
-                let bytes_len = bytes.len() as u64;
+                if downloaded_count >= ESTIMATE_SAMPLE_SEGMENTS && downloaded_secs > 0.0 {
+                    let bytes_per_sec = downloaded_bytes as f64 / downloaded_secs;
+                    let remaining_bytes = (total_secs - downloaded_secs).max(0.0) * bytes_per_sec;
+                    p.set_length(downloaded_bytes + remaining_bytes as u64)
+                } else {
+                    let progress_len = p.length().unwrap();
+                    let estimated_segment_len = (stream_data.bandwidth / 8) * segment_len.as_secs();
+                    p.set_length(progress_len - estimated_segment_len + bytes_len)
+                }
+                p.inc(bytes_len);
 
-                p.set_length(progress_len - estimated_segment_len + bytes_len);
-                p.inc(bytes_len)
+                if self.progress_json {
+                    print_json_progress("download", p, true)
+                }
             }
 
             // check if the currently sent bytes are the next in the buffer. if so, write them directly
@@ -1137,13 +2264,21 @@ impl Downloader {
             if data_pos == pos {
                 writer.write_all(bytes.borrow())?;
                 data_pos += 1;
+            } else if self.concurrent_fragment_writes {
+                let (mut file, path) = tempfile(format!(".segment-{}", pos))?.into_parts();
+                file.write_all(bytes.borrow())?;
+                file_buf.insert(pos, path);
             } else {
                 buf.insert(pos, bytes);
             }
             // check if the buffer contains the next segment(s)
-            while let Some(b) = buf.remove(&data_pos) {
-                writer.write_all(b.borrow())?;
-                data_pos += 1;
+            write_buffered_segments(&mut writer, &mut data_pos, &mut buf, &mut file_buf)?;
+
+            // flush the write buffer from time to time, so it's written in smaller chunks instead
+            // of one big write when the buffer is full
+            if last_flush.elapsed() >= Duration::from_secs(1) {
+                writer.flush()?;
+                last_flush = Instant::now();
             }
         }
 
@@ -1153,35 +2288,373 @@ impl Downloader {
         }
 
         // write the remaining buffer, if existent
-        while let Some(b) = buf.remove(&data_pos) {
-            writer.write_all(b.borrow())?;
-            data_pos += 1;
-        }
+        write_buffered_segments(&mut writer, &mut data_pos, &mut buf, &mut file_buf)?;
 
-        if !buf.is_empty() {
+        if !buf.is_empty() || !file_buf.is_empty() {
             bail!(
                 "Download buffer is not empty. Remaining segments: {}",
                 buf.into_keys()
+                    .chain(file_buf.into_keys())
                     .map(|k| k.to_string())
                     .collect::<Vec<String>>()
                     .join(", ")
             )
         }
 
+        writer.flush()?;
+
         Ok(())
     }
 }
 
-fn estimate_stream_data_file_size(stream_data: &StreamData, segments: &[StreamSegment]) -> u64 {
+/// Download and mux the given formats into `dst`. This is the same pipeline the `download` and
+/// `archive` commands are using, without any of their cli specific handling (output templates,
+/// skipping existing files, ...).
+pub async fn download_formats(
+    builder: DownloadBuilder,
+    formats: Vec<DownloadFormat>,
+    dst: &Path,
+) -> Result<()> {
+    if formats.is_empty() {
+        bail!("At least one format is required to download")
+    }
+
+    let mut downloader = builder.build();
+    for format in formats {
+        downloader.add_format(format)
+    }
+    downloader.download(dst).await
+}
+
+/// Copies temporary files to a directory when dropped, used for `--keep-temp`. Copying on drop keeps
+/// the files regardless of how the download ends.
+struct KeepTemp {
+    dir: PathBuf,
+    prefix: String,
+    /// Name (without extension) and path of every file to copy.
+    files: Vec<(String, PathBuf)>,
+}
+
+impl Drop for KeepTemp {
+    fn drop(&mut self) {
+        if let Err(e) = fs::create_dir_all(&self.dir) {
+            warn!(
+                "Failed to create directory {} to keep temporary files: {}",
+                self.dir.to_string_lossy(),
+                e
+            );
+            return;
+        }
+        for (name, path) in &self.files {
+            let mut file_name = format!("{}.{}", self.prefix, name);
+            if let Some(extension) = path.extension() {
+                file_name += &format!(".{}", extension.to_string_lossy())
+            }
+            let target = self.dir.join(sanitize(file_name, true, false));
+            match fs::copy(path, &target) {
+                Ok(_) => info!("Kept temporary file {}", target.to_string_lossy()),
+                Err(e) => warn!(
+                    "Failed to keep temporary file {}: {}",
+                    path.to_string_lossy(),
+                    e
+                ),
+            }
+        }
+    }
+}
+
+/// Error if a segment couldn't be downloaded within all retries.
+#[derive(Debug)]
+pub struct SegmentError {
+    pub message: String,
+    /// The status of the last response, if the segment failed because of an error status.
+    pub status: Option<reqwest::StatusCode>,
+}
+
+impl fmt::Display for SegmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for SegmentError {}
+
+/// Bytes which are downloaded by all downloaders that share this, used to show a summary at the end
+/// of a run.
+#[derive(Clone)]
+pub struct DownloadStats {
+    start: Instant,
+    bytes: Arc<AtomicU64>,
+}
+
+impl DownloadStats {
+    pub fn start() -> Self {
+        Self {
+            start: Instant::now(),
+            bytes: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Log the total downloaded bytes, the elapsed time and the average throughput since
+    /// [`DownloadStats::start`]. Nothing is logged if nothing was downloaded.
+    pub fn log_summary(&self) {
+        let bytes = self.bytes.load(std::sync::atomic::Ordering::SeqCst);
+        if bytes == 0 {
+            return;
+        }
+        let elapsed = self.start.elapsed();
+
+        info!(
+            "Downloaded {} in {} ({}/s)",
+            format_size(bytes),
+            format_time_delta(&TimeDelta::from_std(elapsed).unwrap_or_default()),
+            format_size((bytes as f64 / elapsed.as_secs_f64().max(1.0)) as u64)
+        )
+    }
+}
+
+/// Space which is reserved by a download in [`Downloader::concurrent_space`]. The space is released
+/// again when this is dropped.
+struct ReservedSpace {
+    concurrent_space: Option<Arc<AtomicU64>>,
+    size: u64,
+    total: u64,
+}
+
+impl ReservedSpace {
+    fn new(concurrent_space: Option<Arc<AtomicU64>>, size: u64) -> Self {
+        let total = if let Some(concurrent_space) = &concurrent_space {
+            concurrent_space.fetch_add(size, std::sync::atomic::Ordering::SeqCst) + size
+        } else {
+            size
+        };
+        Self {
+            concurrent_space,
+            size,
+            total,
+        }
+    }
+
+    /// The space which is required by this and all other concurrently running downloads.
+    fn total(&self) -> u64 {
+        self.total
+    }
+}
+
+impl Drop for ReservedSpace {
+    fn drop(&mut self) {
+        if let Some(concurrent_space) = &self.concurrent_space {
+            concurrent_space.fetch_sub(self.size, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+}
+
+/// Write all segments which directly follow `data_pos` from the in-memory buffer or the temporary
+/// segment files to `writer`.
+fn write_buffered_segments(
+    writer: &mut impl Write,
+    data_pos: &mut i32,
+    buf: &mut BTreeMap<i32, Vec<u8>>,
+    file_buf: &mut BTreeMap<i32, TempPath>,
+) -> Result<()> {
+    loop {
+        if let Some(b) = buf.remove(data_pos) {
+            writer.write_all(b.borrow())?;
+        } else if let Some(path) = file_buf.remove(data_pos) {
+            std::io::copy(&mut fs::File::open(&path)?, writer)?;
+        } else {
+            break;
+        }
+        *data_pos += 1;
+    }
+    Ok(())
+}
+
+/// Number of concurrent segment downloads `--threads auto` starts with.
+const AUTO_THREADS_START: usize = 2;
+/// Maximal number of concurrent segment downloads when using `--threads auto`.
+const AUTO_THREADS_MAX: usize = 16;
+/// Number of downloaded segments after which `--threads auto` re-evaluates the thread count.
+const AUTO_THREADS_WINDOW: usize = 8;
+
+/// Adapts the number of concurrent segment downloads when `--threads auto` is used. The tuner
+/// starts with [`AUTO_THREADS_START`] threads and re-evaluates the thread count every
+/// [`AUTO_THREADS_WINDOW`] downloaded segments:
+/// - if any segment in the window had to be retried, the thread count is halved
+/// - if the average segment latency of the window is less than 1.5 times the best average latency
+///   seen so far, the thread count is increased by one
+/// - otherwise the connection is considered saturated and the thread count is decreased by one
+///
+/// The thread count always stays between 1 and [`AUTO_THREADS_MAX`].
+struct ThreadTuner {
+    semaphore: Semaphore,
+    state: std::sync::Mutex<ThreadTunerState>,
+}
+
+#[derive(Default)]
+struct ThreadTunerState {
+    threads: usize,
+    // permits which are removed from the semaphore instead of being released when the thread
+    // count was lowered
+    excess: usize,
+    window_segments: usize,
+    window_latency: Duration,
+    window_retries: u32,
+    best_latency: Option<Duration>,
+}
+
+impl ThreadTuner {
+    fn new() -> Self {
+        Self {
+            semaphore: Semaphore::new(AUTO_THREADS_START),
+            state: std::sync::Mutex::new(ThreadTunerState {
+                threads: AUTO_THREADS_START,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Records a downloaded segment and releases the permit which was acquired to download it.
+    fn finish(&self, permit: SemaphorePermit<'_>, latency: Duration, retries: u32) {
+        let mut state = self.state.lock().unwrap();
+        state.window_segments += 1;
+        state.window_latency += latency;
+        state.window_retries += retries;
+
+        if state.window_segments >= AUTO_THREADS_WINDOW {
+            let average_latency = state.window_latency / state.window_segments as u32;
+            let best_latency = state
+                .best_latency
+                .map_or(average_latency, |best| best.min(average_latency));
+
+            let threads = if state.window_retries > 0 {
+                (state.threads / 2).max(1)
+            } else if average_latency.as_secs_f64() < best_latency.as_secs_f64() * 1.5 {
+                (state.threads + 1).min(AUTO_THREADS_MAX)
+            } else {
+                (state.threads - 1).max(1)
+            };
+            if threads > state.threads {
+                // pending removals are canceled first before new permits are added
+                let added = threads - state.threads;
+                let canceled = added.min(state.excess);
+                state.excess -= canceled;
+                self.semaphore.add_permits(added - canceled)
+            } else {
+                state.excess += state.threads - threads
+            }
+            if threads != state.threads {
+                debug!(
+                    "Adjusted download threads from {} to {} (avg. segment latency {}ms, {} retries)",
+                    state.threads,
+                    threads,
+                    average_latency.as_millis(),
+                    state.window_retries
+                )
+            }
+
+            state.threads = threads;
+            state.best_latency = Some(best_latency);
+            state.window_segments = 0;
+            state.window_latency = Duration::ZERO;
+            state.window_retries = 0;
+        }
+
+        if state.excess > 0 {
+            state.excess -= 1;
+            permit.forget()
+        }
+    }
+}
+
+/// Milliseconds a synced audio may be longer than the video it is merged into before it is cut.
+const SYNC_CUT_TOLERANCE: i64 = 500;
+
+/// Number of downloaded segments after which the total size of a stream is extrapolated from the
+/// actual size of the downloaded segments instead of the stream bandwidth.
+const ESTIMATE_SAMPLE_SEGMENTS: usize = 5;
+
+/// Remove '-c:v copy' and '-c:a copy' from the given ffmpeg arguments.
+fn remove_codec_copy(args: &mut Vec<String>) {
+    let mut last = String::new();
+    let mut remove_count = 0;
+    for (i, s) in args.clone().iter().enumerate() {
+        if (last == "-c:v" || last == "-c:a") && s == "copy" {
+            // remove last
+            args.remove(i - remove_count - 1);
+            remove_count += 1;
+            args.remove(i - remove_count);
+            remove_count += 1;
+        }
+        last.clone_from(s);
+    }
+}
+
+/// Remove only '-c:a copy' from the given ffmpeg arguments.
+fn remove_audio_codec_copy(args: &mut Vec<String>) {
+    while let Some(i) = args
+        .windows(2)
+        .position(|w| w[0] == "-c:a" && w[1] == "copy")
+    {
+        args.drain(i..i + 2);
+    }
+}
+
+/// Find the input which caused ffmpeg to fail with an 'Invalid data' error. `inputs` must be in the
+/// same order as they were passed to ffmpeg. Returns the index of the input in `inputs`.
+fn find_invalid_ffmpeg_input(stderr: &str, inputs: &[&Path]) -> Option<usize> {
+    let input_idx = Regex::new(r"input (file |#)(?P<idx>\d+)").unwrap();
+
+    for line in stderr.lines().rev() {
+        if !line.contains("Invalid data") {
+            continue;
+        }
+        // errors while opening an input are prefixed with the input path
+        // (e.g. `/tmp/video.mp4: Invalid data found when processing input`)
+        if let Some(idx) = inputs
+            .iter()
+            .position(|i| line.starts_with(&format!("{}:", i.to_string_lossy())))
+        {
+            return Some(idx);
+        }
+        // errors while demuxing are referencing the input by its index
+        // (e.g. `Error demuxing input file 1: Invalid data found when processing input`)
+        if let Some(capture) = input_idx.captures(line) {
+            let idx: usize = capture.name("idx").unwrap().as_str().parse().ok()?;
+            if idx < inputs.len() {
+                return Some(idx);
+            }
+        }
+    }
+    None
+}
+
+pub fn estimate_stream_data_file_size(stream_data: &StreamData, segments: &[StreamSegment]) -> u64 {
     (stream_data.bandwidth / 8) * segments.iter().map(|s| s.length.as_secs()).sum::<u64>()
 }
 
+/// Seconds to wait for the first ffmpeg progress line. If nothing is received until then, the
+/// progress bar is replaced by a spinner as the progress probably doesn't work with this ffmpeg.
+const FFMPEG_PROGRESS_TIMEOUT: u64 = 10;
+
+/// Number of ffmpeg stderr lines which are shown if ffmpeg fails.
+const FFMPEG_ERROR_LINES: usize = 20;
+
+/// Filename template of subtitle sidecar files if only the format but no template is given.
+const DEFAULT_SUBTITLE_FILENAME_TEMPLATE: &str = "{locale}.{cc}";
+/// Removes leading silence, reverses the audio to remove the (now leading) trailing silence and
+/// reverses it back again. Silence in the middle of the audio is kept.
+const TRIM_SILENCE_FILTER: &str = "silenceremove=start_periods=1:start_threshold=-50dB,areverse,silenceremove=start_periods=1:start_threshold=-50dB,areverse";
+/// Bitrate of audio which must be re-encoded because of audio filters. Crunchyroll delivers the
+/// audio with roughly this bitrate, so the file size stays about the same.
+const AUDIO_REENCODE_BITRATE: &str = "192k";
+
 /// Get the length and fps of a video.
-fn get_video_stats(path: &Path) -> Result<(TimeDelta, f64)> {
+pub fn get_video_stats(path: &Path) -> Result<(TimeDelta, f64)> {
     let video_length = Regex::new(r"Duration:\s(?P<time>\d+:\d+:\d+\.\d+),")?;
     let video_fps = Regex::new(r"(?P<fps>[\d/.]+)\sfps")?;
 
-    let ffmpeg = Command::new("ffmpeg")
+    let ffmpeg = Command::new(ffmpeg_path())
         .stdout(Stdio::null())
         .stderr(Stdio::piped())
         .arg("-y")
@@ -1189,6 +2662,9 @@ fn get_video_stats(path: &Path) -> Result<(TimeDelta, f64)> {
         .args(["-i", path.to_str().unwrap()])
         .output()?;
     let ffmpeg_output = String::from_utf8(ffmpeg.stderr)?;
+    if ffmpeg_output.contains("Duration: N/A") {
+        bail!("Video has no duration, live content is not supported")
+    }
     let length_caps = video_length
         .captures(ffmpeg_output.as_str())
         .ok_or(anyhow::anyhow!(
@@ -1202,12 +2678,156 @@ fn get_video_stats(path: &Path) -> Result<(TimeDelta, f64)> {
             ffmpeg_output
         ))?;
 
-    Ok((
-        NaiveTime::parse_from_str(length_caps.name("time").unwrap().as_str(), "%H:%M:%S%.f")
+    let length =
+        NaiveTime::parse_from_str(length_caps.name("time").unwrap().as_str(), "%H:%M:%S%.f")?
+            .signed_duration_since(NaiveTime::MIN);
+    let fps_str = fps_caps.name("fps").unwrap().as_str();
+    let fps: f64 = fps_str
+        .parse()
+        .map_err(|_| anyhow::anyhow!("failed to parse video fps '{}'", fps_str))?;
+    if length <= TimeDelta::zero() || fps <= 0.0 {
+        bail!("Video has no duration, live content is not supported")
+    }
+
+    Ok((length, fps))
+}
+
+/// Get the length of a media file.
+pub fn get_length(path: &Path) -> Result<TimeDelta> {
+    let length = Regex::new(r"Duration:\s(?P<time>\d+:\d+:\d+\.\d+),")?;
+
+    let ffmpeg = Command::new(ffmpeg_path())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .arg("-hide_banner")
+        .args(["-i", path.to_str().unwrap()])
+        .output()?;
+    let ffmpeg_output = String::from_utf8(ffmpeg.stderr)?;
+    let caps = length
+        .captures(ffmpeg_output.as_str())
+        .ok_or(anyhow::anyhow!("failed to get length: {}", ffmpeg_output))?;
+
+    Ok(
+        NaiveTime::parse_from_str(caps.name("time").unwrap().as_str(), "%H:%M:%S%.f")
             .unwrap()
             .signed_duration_since(NaiveTime::MIN),
-        fps_caps.name("fps").unwrap().as_str().parse().unwrap(),
-    ))
+    )
+}
+
+/// Probe all streams of the given (muxed) file and check if the expected number of video, audio
+/// and subtitle streams are present and have a non-zero duration. Additionally, the length of the
+/// file must match `expected_len` within a tolerance of 2 seconds.
+fn verify_output(
+    path: &Path,
+    expected_len: TimeDelta,
+    videos: usize,
+    audios: usize,
+    subtitles: usize,
+) -> Result<()> {
+    let ffprobe = match Command::new(ffprobe_path())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "stream=index,codec_type,duration:stream_disposition=attached_pic:stream_tags=DURATION",
+            "-of",
+            "json",
+            path.to_str().unwrap(),
+        ])
+        .output()
+    {
+        Ok(ffprobe) => ffprobe,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            bail!("ffprobe is required to verify the output file but was not found")
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if !ffprobe.status.success() {
+        bail!(
+            "failed to probe output file: {}",
+            String::from_utf8_lossy(&ffprobe.stderr)
+        )
+    }
+
+    let probe: serde_json::Value = serde_json::from_slice(&ffprobe.stdout)?;
+    verify_streams(
+        path,
+        &probe["streams"].as_array().cloned().unwrap_or_default(),
+        videos,
+        audios,
+        subtitles,
+    )?;
+
+    let len = get_length(path)?;
+    if (len - expected_len).abs() > TimeDelta::seconds(2) {
+        bail!(
+            "output file '{}' is {} long, but {} were expected. The file is probably truncated",
+            path.to_string_lossy(),
+            format_time_delta(&len),
+            format_time_delta(&expected_len)
+        )
+    }
+
+    Ok(())
+}
+
+/// Check if the ffprobe `streams` of the output file contain the expected number of non-empty
+/// video, audio and subtitle streams. Cover art (`--thumbnail`) is stored as video stream with the
+/// `attached_pic` disposition in mp4 and mov, it has no duration and is not counted.
+fn verify_streams(
+    path: &Path,
+    streams: &[serde_json::Value],
+    videos: usize,
+    audios: usize,
+    subtitles: usize,
+) -> Result<()> {
+    for (codec_type, expected) in [
+        ("video", videos),
+        ("audio", audios),
+        ("subtitle", subtitles),
+    ] {
+        let found: Vec<&serde_json::Value> = streams
+            .iter()
+            .filter(|s| s["codec_type"].as_str() == Some(codec_type))
+            .filter(|s| s["disposition"]["attached_pic"].as_u64() != Some(1))
+            .collect();
+        if found.len() != expected {
+            bail!(
+                "output file '{}' has {} {} stream(s), but {} were expected",
+                path.to_string_lossy(),
+                found.len(),
+                codec_type,
+                expected
+            )
+        }
+
+        for stream in found {
+            // mp4 and mov store the duration in the stream itself, mkv stores it as tag
+            let duration = if let Some(duration) = stream["duration"].as_str() {
+                duration.parse::<f64>().unwrap_or_default()
+            } else if let Some(duration) = stream["tags"]["DURATION"].as_str() {
+                NaiveTime::parse_from_str(duration, "%H:%M:%S%.f")
+                    .map(|t| {
+                        t.signed_duration_since(NaiveTime::MIN).num_milliseconds() as f64 / 1000.0
+                    })
+                    .unwrap_or_default()
+            } else {
+                0.0
+            };
+            if duration <= 0.0 {
+                bail!(
+                    "{} stream #{} of output file '{}' is empty",
+                    codec_type,
+                    stream["index"].as_u64().unwrap_or_default(),
+                    path.to_string_lossy()
+                )
+            }
+        }
+    }
+
+    Ok(())
 }
 
 // all subtitle fonts (extracted from javascript)
@@ -1299,6 +2919,59 @@ const FONTS: [(&str, &str); 68] = [
     ("Vrinda Bold", "vrindab.woff2"),
     ("Webdings", "webdings.woff2"),
 ];
+const FONT_EXTENSIONS: [&str; 4] = ["woff2", "woff", "ttf", "otf"];
+
+enum FontOrigin {
+    Downloaded,
+    Cached,
+    Local,
+}
+
+/// Search the given directory for a font. The font either matches the filename from [`FONTS`] or
+/// is named like the font itself (case-insensitive) and has one of the [`FONT_EXTENSIONS`].
+fn find_local_font(
+    font_dir: &Path,
+    name: &str,
+    font_file: Option<&str>,
+) -> Result<Option<PathBuf>> {
+    if let Some(font_file) = font_file {
+        let file = font_dir.join(font_file);
+        if file.is_file() {
+            return Ok(Some(file));
+        }
+    }
+
+    for entry in fs::read_dir(font_dir)? {
+        let path = entry?.path();
+        let (Some(stem), Some(extension)) = (path.file_stem(), path.extension()) else {
+            continue;
+        };
+        if path.is_file()
+            && stem.to_string_lossy().eq_ignore_ascii_case(name)
+            && FONT_EXTENSIONS.contains(&extension.to_string_lossy().to_lowercase().as_str())
+        {
+            return Ok(Some(path));
+        }
+    }
+
+    Ok(None)
+}
+
+fn font_mimetype(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase()
+        .as_str()
+    {
+        "woff" => "font/woff",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        _ => "font/woff2",
+    }
+}
+
 lazy_static::lazy_static! {
     static ref FONT_REGEX: Regex = Regex::new(r"(?m)^(?:Style:\s.+?,(?P<font>.+?),|(?:Dialogue:\s(?:.+?,)+,\{(?:\\.*)?\\fn(?P<overrideFont>[\w\s]+)(?:\\.*)?)\})").unwrap();
 }
@@ -1325,18 +2998,100 @@ fn get_subtitle_stats(path: &Path) -> Result<Vec<String>> {
     Ok(fonts)
 }
 
-fn write_ffmpeg_chapters(
-    file: &mut fs::File,
+/// Shift all events of the subtitle by `offset`. Times which would be negative after shifting are
+/// set to zero.
+fn shift_subtitle_events(ass: &mut SSA, offset: TimeDelta) {
+    let shift = |t: Time| {
+        let millis = (t - Time::MIDNIGHT).whole_milliseconds() as i64 + offset.num_milliseconds();
+        Time::MIDNIGHT.add(Duration::from_millis(millis.max(0) as u64))
+    };
+    for event in ass.events.iter_mut() {
+        event.start = shift(event.start);
+        event.end = shift(event.end);
+    }
+}
+
+/// Replace the styles of the subtitle with the styles of the same name from `template`. Styles which
+/// aren't in the template are left untouched.
+fn apply_subtitle_style(ass: &mut SSA, template: &SSA) {
+    for style in ass.styles.iter_mut() {
+        if let Some(template_style) = template.styles.iter().find(|s| s.name == style.name) {
+            *style = template_style.clone()
+        }
+    }
+}
+
+/// Sort the events of the subtitle by their start time and strip them to be at most as long as
+/// `max_length`.
+fn sort_subtitle_events(ass: &mut SSA, max_length: TimeDelta) {
+    // subtitles aren't always correct sorted and video players may have issues with that. to
+    // prevent issues, the subtitles are sorted
+    // (https://github.com/crunchy-labs/crunchy-cli/issues/208).
+    // the sort must be stable (so no `sort_unstable_by`), events with identical start times
+    // would otherwise get reordered which may desynchronize overlapping captions
+    ass.events.sort_by(|a, b| a.start.cmp(&b.start));
+    // it might be the case that the start and/or end time are greater than the actual video
+    // length. this might also result in issues with video players, thus the times are stripped
+    // to be at most as long as `max_length`
+    // (https://github.com/crunchy-labs/crunchy-cli/issues/32)
+    for i in (0..ass.events.len()).rev() {
+        let max_len = Time::from_hms(0, 0, 0)
+            .unwrap()
+            .add(Duration::from_millis(max_length.num_milliseconds() as u64));
+
+        if ass.events[i].start > max_len {
+            if ass.events[i].end > max_len {
+                ass.events[i].start = max_len
+            }
+            ass.events[i].end = max_len
+        } else {
+            break;
+        }
+    }
+}
+
+/// Get all events of `skip_events` with their chapter name. Only events whose (lowercase) name is
+/// in `chapter_types` are included, an empty `chapter_types` includes all events.
+pub fn skip_events_chapters<'a>(
+    skip_events: &'a SkipEvents,
+    chapter_types: &[String],
+) -> Vec<(&'static str, &'a SkipEventsEvent)> {
+    [
+        skip_events.recap.as_ref().map(|e| ("Recap", e)),
+        skip_events.intro.as_ref().map(|e| ("Intro", e)),
+        skip_events.credits.as_ref().map(|e| ("Credits", e)),
+        skip_events.preview.as_ref().map(|e| ("Preview", e)),
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|(name, _)| chapter_types.is_empty() || chapter_types.contains(&name.to_lowercase()))
+    .collect()
+}
+
+/// Write the given events as ffmpeg chapters. `offset` is subtracted from every event, events which
+/// are not within `0..video_len` after this are dropped or cut. If `gap_chapters` is true, gaps of
+/// more than 10 seconds between the events are filled with 'Episode' chapters.
+pub fn write_ffmpeg_chapters<W: Write>(
+    file: &mut W,
     video_len: TimeDelta,
+    offset: TimeDelta,
     events: &mut Vec<(&str, &SkipEventsEvent)>,
+    gap_chapters: bool,
 ) -> Result<()> {
     let video_len = video_len.num_milliseconds() as f32 / 1000.0;
+    let offset = offset.num_milliseconds() as f32 / 1000.0;
     events.sort_by(|(_, event_a), (_, event_b)| event_a.start.total_cmp(&event_b.start));
 
     writeln!(file, ";FFMETADATA1")?;
 
     let mut last_end_time = 0.0;
     for (name, event) in events {
+        let start = (event.start - offset).max(0.0);
+        let end = (event.end - offset).min(video_len);
+        if end <= start {
+            continue;
+        }
+
         /*
             - Convert from seconds to milliseconds for the correct timescale
             - Include an extra 'Episode' chapter if the start of the current chapter is more than 10
@@ -1344,26 +3099,26 @@ fn write_ffmpeg_chapters(
               This is done before writing the actual chapter of this loop to keep the chapter
               chronologically in order
         */
-        if event.start - last_end_time > 10.0 {
+        if gap_chapters && start - last_end_time > 10.0 {
             writeln!(file, "[CHAPTER]")?;
             writeln!(file, "TIMEBASE=1/1000")?;
             writeln!(file, "START={}", (last_end_time * 1000.0) as u32)?;
-            writeln!(file, "END={}", (event.start * 1000.0) as u32)?;
+            writeln!(file, "END={}", (start * 1000.0) as u32)?;
             writeln!(file, "title=Episode")?;
         }
 
         writeln!(file, "[CHAPTER]")?;
         writeln!(file, "TIMEBASE=1/1000")?;
-        writeln!(file, "START={}", (event.start * 1000.0) as u32)?;
-        writeln!(file, "END={}", (event.end * 1000.0) as u32)?;
+        writeln!(file, "START={}", (start * 1000.0) as u32)?;
+        writeln!(file, "END={}", (end * 1000.0) as u32)?;
         writeln!(file, "title={}", name)?;
 
-        last_end_time = event.end;
+        last_end_time = end;
     }
 
     // only add a trailing chapter if the gap between the end of the last chapter and the total video
     // length is greater than 10 seconds
-    if video_len - last_end_time > 10.0 {
+    if gap_chapters && video_len - last_end_time > 10.0 {
         writeln!(file, "[CHAPTER]")?;
         writeln!(file, "TIMEBASE=1/1000")?;
         writeln!(file, "START={}", (last_end_time * 1000.0) as u32)?;
@@ -1374,62 +3129,131 @@ fn write_ffmpeg_chapters(
     Ok(())
 }
 
-async fn ffmpeg_progress<R: AsyncReadExt + Unpin>(
+/// Show the ffmpeg progress. It is read from `vstats` (the output of `-vstats_file`), if given,
+/// otherwise from `progress_stats` (the output of `-progress`). If no vstats are received within
+/// [`FFMPEG_PROGRESS_TIMEOUT`], `progress_stats` is used as fallback.
+async fn ffmpeg_progress(
     total_frames: u64,
-    stats: R,
+    vstats: Option<Box<dyn AsyncRead + Send + Unpin>>,
+    progress_stats: Box<dyn AsyncRead + Send + Unpin>,
     message: String,
+    json: bool,
+    multi_progress: Option<MultiProgress>,
     cancellation_token: CancellationToken,
 ) -> Result<()> {
-    let current_frame = Regex::new(r"frame=\s+(?P<frame>\d+)")?;
+    let current_frame = Regex::new(r"frame=\s*(?P<frame>\d+)")?;
+    let bar_style = ProgressStyle::with_template(":: {msg} [{wide_bar}] {percent:>3}%")
+        .unwrap()
+        .progress_chars("##-");
 
-    let progress = if log::max_level() == LevelFilter::Info {
+    let progress = if log::max_level() == LevelFilter::Info && (json || !progress_hidden()) {
         let progress = ProgressBar::new(total_frames)
-            .with_style(
-                ProgressStyle::with_template(":: {msg} [{wide_bar}] {percent:>3}%")
-                    .unwrap()
-                    .progress_chars("##-"),
-            )
+            .with_style(bar_style.clone())
             .with_message(message)
             .with_finish(ProgressFinish::Abandon);
-        progress.set_draw_target(ProgressDrawTarget::stdout());
-        progress.enable_steady_tick(Duration::from_millis(200));
-        Some(progress)
+        if json {
+            progress.set_draw_target(ProgressDrawTarget::hidden());
+            Some(progress)
+        } else {
+            let progress = if let Some(multi_progress) = multi_progress {
+                multi_progress.add(progress)
+            } else {
+                progress.set_draw_target(ProgressDrawTarget::stdout());
+                progress
+            };
+            progress.enable_steady_tick(Duration::from_millis(200));
+            Some(progress)
+        }
     } else {
         None
     };
 
-    let reader = BufReader::new(stats);
-    let mut lines = reader.lines();
+    let mut vstats_lines = vstats.map(|v| BufReader::new(v).lines());
+    let mut progress_lines = BufReader::new(progress_stats).lines();
     let mut frame = 0;
+    let mut last_json_print = Instant::now();
+    let mut received = false;
+    let mut timed_out = false;
     loop {
         select! {
             _ = cancellation_token.cancelled() => {
                 break
             }
-            line = lines.next_line() => {
+            _ = tokio::time::sleep(Duration::from_secs(FFMPEG_PROGRESS_TIMEOUT)), if !received && !timed_out => {
+                if let Some(vstats_lines) = vstats_lines.take() {
+                    debug!("No ffmpeg vstats received within {} seconds, reading the ffmpeg progress instead. If this happens every time, try `--no-progress-pipe`", FFMPEG_PROGRESS_TIMEOUT);
+                    // the vstats are still read, so that ffmpeg isn't blocked when writing them
+                    // to the named pipe
+                    tokio::spawn(async move {
+                        tokio::io::copy(&mut vstats_lines.into_inner(), &mut tokio::io::sink()).await
+                    });
+                    continue
+                }
+                debug!("No ffmpeg progress received within {} seconds", FFMPEG_PROGRESS_TIMEOUT);
+                timed_out = true;
+                if let Some(p) = progress.as_ref().filter(|_| !json) {
+                    p.set_style(
+                        ProgressStyle::with_template(":: {msg} {spinner}")
+                            .unwrap()
+                            .tick_strings(&["—", "\\", "|", "/", ""]),
+                    )
+                }
+            }
+            line = async {
+                match vstats_lines.as_mut() {
+                    Some(vstats_lines) => vstats_lines.next_line().await,
+                    None => progress_lines.next_line().await,
+                }
+            } => {
+                let vstats = vstats_lines.is_some();
                 let Some(line) = line? else {
+                    // if ffmpeg doesn't write any vstats, the progress is read instead
+                    if vstats && !received {
+                        vstats_lines = None;
+                        continue
+                    }
                     break
                 };
+                if !vstats && line == "progress=end" {
+                    break
+                }
 
                 // we're manually unpack the regex here as `.unwrap()` may fail in some cases, e.g.
                 // https://github.com/crunchy-labs/crunchy-cli/issues/337
                 let Some(frame_cap) = current_frame.captures(line.as_str()) else {
-                    break
+                    // the `-progress` output contains many other key-value lines besides the frame
+                    if vstats {
+                        break
+                    }
+                    continue
                 };
                 let Some(frame_str) = frame_cap.name("frame") else {
                     break
                 };
                 frame = frame_str.as_str().parse()?;
+                if !received && timed_out {
+                    if let Some(p) = progress.as_ref().filter(|_| !json) {
+                        p.set_style(bar_style.clone())
+                    }
+                }
+                received = true;
 
                 if let Some(p) = &progress {
-                    p.set_position(frame)
+                    p.set_position(frame);
+
+                    // ffmpeg reports every single frame, so the json output is throttled to not
+                    // flood stderr
+                    if json && last_json_print.elapsed() >= Duration::from_millis(500) {
+                        print_json_progress("ffmpeg", p, false);
+                        last_json_print = Instant::now()
+                    }
                 }
 
                 debug!(
                     "Processed frame [{}/{} {:.2}%]",
                     frame,
                     total_frames,
-                    (frame as f64 / total_frames as f64) * 100f64
+                    (frame as f64 / total_frames.max(1) as f64) * 100f64
                 )
             }
         }
@@ -1444,10 +3268,343 @@ async fn ffmpeg_progress<R: AsyncReadExt + Unpin>(
         }
         debug!("Processed frame [{}/{} 100%]", total_frames, total_frames);
     }
+    if json {
+        if let Some(p) = &progress {
+            print_json_progress("ffmpeg", p, false)
+        }
+    }
 
     Ok(())
 }
 
+/// Print the current state of a progress bar as newline-delimited json to stderr. Used instead of
+/// drawing the progress bar when `--progress-json` is set.
+fn print_json_progress(stage: &str, progress: &ProgressBar, bytes: bool) {
+    let mut record = serde_json::json!({
+        "stage": stage,
+        "message": progress.message().trim(),
+        "current": progress.position(),
+        "total": progress.length().unwrap_or_default(),
+    });
+    if bytes {
+        record["bytes_per_sec"] = serde_json::json!(progress.per_sec() as u64);
+    }
+    eprintln!("{}", record)
+}
+
 fn len_from_segments(segments: &[StreamSegment]) -> TimeDelta {
     TimeDelta::milliseconds(segments.iter().map(|s| s.length.as_millis()).sum::<u128>() as i64)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A skip event like crunchyroll returns it.
+    fn skip_event(start: f32, end: f32, event_type: &str) -> serde_json::Value {
+        json!({
+            "approverId": "",
+            "distributionNumber": "1",
+            "start": start,
+            "end": end,
+            "title": "",
+            "seriesId": "",
+            "new": false,
+            "type": event_type,
+        })
+    }
+
+    /// Skip events of a 1420 seconds long episode.
+    fn skip_events_fixture() -> SkipEvents {
+        serde_json::from_value(json!({
+            "intro": skip_event(60.0, 150.0, "intro"),
+            "credits": skip_event(1300.0, 1390.0, "credits"),
+            "preview": skip_event(1395.0, 1420.0, "preview"),
+        }))
+        .unwrap()
+    }
+
+    fn chapter(start: u32, end: u32, title: &str) -> String {
+        format!(
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            start, end, title
+        )
+    }
+
+    #[test]
+    fn write_ffmpeg_chapters_with_gap_chapters() {
+        let skip_events = skip_events_fixture();
+        let mut events = vec![
+            ("Credits", skip_events.credits.as_ref().unwrap()),
+            ("Intro", skip_events.intro.as_ref().unwrap()),
+            ("Preview", skip_events.preview.as_ref().unwrap()),
+        ];
+        let mut out = vec![];
+        write_ffmpeg_chapters(
+            &mut out,
+            TimeDelta::seconds(1420),
+            TimeDelta::zero(),
+            &mut events,
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            [
+                ";FFMETADATA1\n".to_string(),
+                chapter(0, 60000, "Episode"),
+                chapter(60000, 150000, "Intro"),
+                chapter(150000, 1300000, "Episode"),
+                chapter(1300000, 1390000, "Credits"),
+                // the gap between credits and preview is too short for an extra chapter
+                chapter(1395000, 1420000, "Preview"),
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn skip_events_chapters_selects_types() {
+        let skip_events = skip_events_fixture();
+        let names = |chapter_types: &[&str]| {
+            skip_events_chapters(
+                &skip_events,
+                &chapter_types
+                    .iter()
+                    .map(|t| t.to_string())
+                    .collect::<Vec<String>>(),
+            )
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<&str>>()
+        };
+
+        assert_eq!(names(&[]), ["Intro", "Credits", "Preview"]);
+        assert_eq!(names(&["credits", "intro"]), ["Intro", "Credits"]);
+        // the fixture has no recap
+        assert!(names(&["recap"]).is_empty());
+    }
+
+    #[test]
+    fn write_ffmpeg_chapters_without_gap_chapters() {
+        let skip_events = skip_events_fixture();
+        let mut events = skip_events_chapters(&skip_events, &["credits".to_string()]);
+        let mut out = vec![];
+        write_ffmpeg_chapters(
+            &mut out,
+            TimeDelta::seconds(1420),
+            TimeDelta::zero(),
+            &mut events,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            [
+                ";FFMETADATA1\n".to_string(),
+                chapter(1300000, 1390000, "Credits"),
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn write_ffmpeg_chapters_gap_threshold() {
+        let skip_events: SkipEvents = serde_json::from_value(json!({
+            "intro": skip_event(10.0, 20.0, "intro"),
+            "credits": skip_event(30.5, 40.0, "credits"),
+        }))
+        .unwrap();
+        let mut events = skip_events_chapters(&skip_events, &[]);
+        let mut out = vec![];
+        write_ffmpeg_chapters(
+            &mut out,
+            TimeDelta::seconds(50),
+            TimeDelta::zero(),
+            &mut events,
+            true,
+        )
+        .unwrap();
+
+        // gaps of exactly 10 seconds are not filled, only longer ones
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            [
+                ";FFMETADATA1\n".to_string(),
+                chapter(10000, 20000, "Intro"),
+                chapter(20000, 30500, "Episode"),
+                chapter(30500, 40000, "Credits"),
+            ]
+            .concat()
+        );
+    }
+
+    const SUBTITLE_FIXTURE: &str = "[Script Info]
+Title: crunchy-cli
+ScriptType: v4.00+
+PlayResX: 640
+PlayResY: 360
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Trebuchet MS,24,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,1,2,0020,0020,0020,1
+Style: Sign,Arial,18,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,1,8,0020,0020,0020,1
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:05.00,0:00:07.00,Default,,0000,0000,0000,,third
+Dialogue: 0,0:00:01.00,0:00:03.00,Default,,0000,0000,0000,,first
+Dialogue: 0,0:00:01.00,0:00:02.00,Sign,,0000,0000,0000,,second
+Dialogue: 0,0:00:09.00,0:00:12.00,Default,,0000,0000,0000,,fourth
+";
+
+    /// Start and end time in milliseconds and the text of all subtitle events.
+    fn subtitle_events(ass: &SSA) -> Vec<(i64, i64, String)> {
+        let to_millis = |t: Time| (t - Time::MIDNIGHT).whole_milliseconds() as i64;
+        ass.events
+            .iter()
+            .map(|e| (to_millis(e.start), to_millis(e.end), e.text.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn sort_subtitle_events_is_stable() {
+        let mut ass = SSA::parse(SUBTITLE_FIXTURE).unwrap();
+        sort_subtitle_events(&mut ass, TimeDelta::seconds(60));
+        assert_eq!(
+            subtitle_events(&ass),
+            [
+                (1000, 3000, "first".to_string()),
+                (1000, 2000, "second".to_string()),
+                (5000, 7000, "third".to_string()),
+                (9000, 12000, "fourth".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn sort_subtitle_events_strips_to_max_length() {
+        let mut ass = SSA::parse(SUBTITLE_FIXTURE).unwrap();
+        sort_subtitle_events(&mut ass, TimeDelta::seconds(8));
+        assert_eq!(
+            subtitle_events(&ass).last().unwrap(),
+            &(8000, 8000, "fourth".to_string())
+        );
+    }
+
+    #[test]
+    fn shift_subtitle_events_clamps_negative_times() {
+        let mut ass = SSA::parse(SUBTITLE_FIXTURE).unwrap();
+        shift_subtitle_events(&mut ass, TimeDelta::milliseconds(-2500));
+        assert_eq!(
+            subtitle_events(&ass),
+            [
+                (2500, 4500, "third".to_string()),
+                (0, 500, "first".to_string()),
+                (0, 0, "second".to_string()),
+                (6500, 9500, "fourth".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn shift_subtitle_events_past_video_end() {
+        let mut ass = SSA::parse(SUBTITLE_FIXTURE).unwrap();
+        // shifting happens before sorting and stripping, like when downloading a subtitle
+        shift_subtitle_events(&mut ass, TimeDelta::seconds(5));
+        sort_subtitle_events(&mut ass, TimeDelta::seconds(13));
+        assert_eq!(
+            subtitle_events(&ass),
+            [
+                (6000, 8000, "first".to_string()),
+                (6000, 7000, "second".to_string()),
+                (10000, 12000, "third".to_string()),
+                (13000, 13000, "fourth".to_string()),
+            ]
+        );
+    }
+
+    const STYLE_TEMPLATE_FIXTURE: &str = "[Script Info]
+ScriptType: v4.00+
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Noto Sans,32,&H00FFFFFF,&H000000FF,&H00202020,&H80000000,0,0,0,0,100,100,0,0,1,3,0,2,0030,0030,0040,1
+Style: Karaoke,Noto Sans,28,&H00FFFFFF,&H000000FF,&H00202020,&H80000000,0,0,0,0,100,100,0,0,1,3,0,8,0030,0030,0040,1
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:00.00,0:00:01.00,Default,,0000,0000,0000,,template
+";
+
+    #[test]
+    fn apply_subtitle_style_replaces_matching_styles() {
+        let mut ass = SSA::parse(SUBTITLE_FIXTURE).unwrap();
+        let original_sign = format!("{:?}", ass.styles[1]);
+        let template = SSA::parse(STYLE_TEMPLATE_FIXTURE).unwrap();
+
+        apply_subtitle_style(&mut ass, &template);
+        assert_eq!(ass.styles.len(), 2);
+        assert_eq!(
+            format!("{:?}", ass.styles[0]),
+            format!("{:?}", template.styles[0])
+        );
+        // styles which are only in the template are not added, unmatched ones are kept
+        assert_eq!(format!("{:?}", ass.styles[1]), original_sign);
+        assert!(ass.styles.iter().all(|s| s.name != "Karaoke"));
+    }
+
+    fn subtitle_meta(locale: Locale, cc: bool) -> FFmpegSubtitleMeta {
+        FFmpegSubtitleMeta {
+            path: InputPath::Local(PathBuf::from(format!("{}.ass", locale))),
+            locale,
+            cc,
+            start_time: None,
+            video_idx: 0,
+        }
+    }
+
+    #[test]
+    fn forced_subtitle_args_only_cc() {
+        let subtitles = [
+            subtitle_meta(Locale::en_US, false),
+            subtitle_meta(Locale::en_US, true),
+            subtitle_meta(Locale::de_DE, false),
+            subtitle_meta(Locale::ja_JP, true),
+        ];
+        assert_eq!(
+            forced_subtitle_args(&subtitles),
+            [
+                "-disposition:s:s:1",
+                "forced",
+                "-disposition:s:s:3",
+                "forced"
+            ]
+        );
+        assert!(forced_subtitle_args(&subtitles[..1]).is_empty());
+    }
+
+    #[test]
+    fn verify_streams_ignores_attached_pic() {
+        let streams = [
+            json!({"index": 0, "codec_type": "video", "duration": "1420.003000", "disposition": {"attached_pic": 0}}),
+            json!({"index": 1, "codec_type": "audio", "duration": "1420.032000", "disposition": {"attached_pic": 0}}),
+            json!({"index": 2, "codec_type": "video", "disposition": {"attached_pic": 1}}),
+        ];
+        assert!(verify_streams(Path::new("out.mp4"), &streams, 1, 1, 0).is_ok());
+        // the cover art must not hide a missing video stream
+        assert!(verify_streams(Path::new("out.mp4"), &streams[1..], 1, 1, 0).is_err());
+    }
+
+    #[test]
+    fn verify_streams_rejects_empty_streams() {
+        let streams = [
+            json!({"index": 0, "codec_type": "video", "tags": {"DURATION": "00:23:40.003000000"}}),
+            json!({"index": 1, "codec_type": "audio", "tags": {"DURATION": "00:00:00.000000000"}}),
+        ];
+        assert!(verify_streams(Path::new("out.mkv"), &streams, 1, 1, 0).is_err());
+    }
+}
@@ -4,7 +4,19 @@ use std::fmt;
 use std::fmt::Formatter;
 use std::str::FromStr;
 
-pub const SOFTSUB_CONTAINERS: [&str; 3] = ["mkv", "mov", "mp4"];
+pub const SOFTSUB_CONTAINERS: [&str; 4] = ["mkv", "mov", "mp4", "webm"];
+
+/// Error if ffmpeg failed to mux the downloaded streams into the output file.
+#[derive(Debug)]
+pub struct FFmpegError(pub String);
+
+impl fmt::Display for FFmpegError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for FFmpegError {}
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum FFmpegPreset {
@@ -64,7 +76,8 @@ ffmpeg_enum! {
     enum FFmpegCodec {
         H264,
         H265,
-        Av1
+        Av1,
+        Vp9
     }
 }
 
@@ -109,6 +122,7 @@ impl FFmpegPreset {
                 vec![FFmpegHwAccel::Amd],
                 FFmpegQuality::all(),
             ),
+            (FFmpegCodec::Vp9, vec![], FFmpegQuality::all()),
         ];
 
         let mut return_values = vec![];
@@ -363,6 +377,16 @@ impl FFmpegPreset {
                             output.extend(["-c:v", "libsvtav1", "-c:a", "copy"]);
                         }
                     }
+                    FFmpegCodec::Vp9 => {
+                        // vp9 is mostly used for webm which cannot contain aac audio, so the audio
+                        // is re-encoded to opus
+                        match quality {
+                            FFmpegQuality::Lossless => output.extend(["-crf", "15"]),
+                            FFmpegQuality::Normal => output.extend(["-crf", "31"]),
+                            FFmpegQuality::Low => output.extend(["-crf", "40"]),
+                        }
+                        output.extend(["-b:v", "0", "-c:v", "libvpx-vp9", "-c:a", "libopus"]);
+                    }
                 }
 
                 (
@@ -1,4 +1,4 @@
-use crate::utils::format::{SingleFormat, SingleFormatCollection};
+use crate::utils::format::{is_trailer_episode, SingleFormat, SingleFormatCollection};
 use crate::utils::interactive_select::{check_for_duplicated_seasons, get_duplicated_seasons};
 use crate::utils::parse::{fract, UrlFilter};
 use anyhow::Result;
@@ -22,6 +22,8 @@ pub(crate) struct Filter {
     url_filter: UrlFilter,
 
     skip_specials: bool,
+    include_extras: bool,
+    include_trailers: bool,
     interactive_input: bool,
 
     relative_episode_number: bool,
@@ -68,11 +70,25 @@ impl Filter {
             series_visited: false,
             season_episodes: HashMap::new(),
             skip_specials,
+            include_extras: false,
+            include_trailers: false,
             season_with_premium: is_premium.not().then_some(vec![]),
             season_sorting: vec![],
         }
     }
 
+    /// If set, the extras (featured music videos) of a series are visited too.
+    pub(crate) fn include_extras(mut self, include_extras: bool) -> Self {
+        self.include_extras = include_extras;
+        self
+    }
+
+    /// If set, trailers aren't skipped even if specials are.
+    pub(crate) fn include_trailers(mut self, include_trailers: bool) -> Self {
+        self.include_trailers = include_trailers;
+        self
+    }
+
     async fn visit_series(&mut self, series: Series) -> Result<Vec<Season>> {
         // the audio locales field isn't always populated
         if !series.audio_locales.is_empty() {
@@ -133,6 +149,23 @@ impl Filter {
         Ok(seasons)
     }
 
+    async fn visit_series_extras(&self, series: &Series) -> Vec<MediaCollection> {
+        match series.featured_music().await {
+            Ok(extras) => {
+                if extras.is_empty() {
+                    info!("Series {} has no extras", series.title)
+                }
+                extras.into_iter().map(|extra| extra.into()).collect()
+            }
+            // extras are only an addition, so failing to get them shouldn't abort the download of
+            // the actual series
+            Err(e) => {
+                warn!("Failed to get extras of series {}: {}", series.title, e);
+                vec![]
+            }
+        }
+    }
+
     async fn visit_season(&mut self, season: Season) -> Result<Vec<Episode>> {
         if !self.url_filter.is_season_valid(season.season_number) {
             return Ok(vec![]);
@@ -216,6 +249,7 @@ impl Filter {
         // skip the episode if it's a special
         if self.skip_specials
             && (episode.sequence_number == 0.0 || episode.sequence_number.fract() != 0.0)
+            && !(self.include_trailers && is_trailer_episode(&episode.episode, &episode.title))
         {
             return Ok(vec![]);
         }
@@ -410,13 +444,18 @@ impl Filter {
 
             for i in items {
                 match i {
-                    MediaCollection::Series(series) => new_items.extend(
-                        self.visit_series(series)
-                            .await?
-                            .into_iter()
-                            .map(|s| s.into())
-                            .collect::<Vec<MediaCollection>>(),
-                    ),
+                    MediaCollection::Series(series) => {
+                        if self.include_extras {
+                            new_items.extend(self.visit_series_extras(&series).await)
+                        }
+                        new_items.extend(
+                            self.visit_series(series)
+                                .await?
+                                .into_iter()
+                                .map(|s| s.into())
+                                .collect::<Vec<MediaCollection>>(),
+                        )
+                    }
                     MediaCollection::Season(season) => new_items.extend(
                         self.visit_season(season)
                             .await?
@@ -427,13 +466,21 @@ impl Filter {
                     MediaCollection::Episode(episode) => {
                         result.push(self.visit_episode(episode).await?)
                     }
-                    MediaCollection::MovieListing(movie_listing) => new_items.extend(
-                        self.visit_movie_listing(movie_listing)
-                            .await?
-                            .into_iter()
-                            .map(|m| m.into())
-                            .collect::<Vec<MediaCollection>>(),
-                    ),
+                    MediaCollection::MovieListing(movie_listing) => {
+                        let movies = self.visit_movie_listing(movie_listing).await?;
+                        // a movie listing with multiple movies is a movie which is split into
+                        // multiple parts
+                        let parts = movies.len() as u32;
+                        for (i, movie) in movies.into_iter().enumerate() {
+                            let mut single_formats = self.visit_movie(movie).await?;
+                            if parts > 1 {
+                                for single_format in single_formats.iter_mut() {
+                                    single_format.set_part(i as u32 + 1, parts)
+                                }
+                            }
+                            result.push(single_formats)
+                        }
+                    }
                     MediaCollection::Movie(movie) => result.push(self.visit_movie(movie).await?),
                     MediaCollection::MusicVideo(music_video) => {
                         result.push(self.visit_music_video(music_video).await?)
@@ -17,3 +17,17 @@ pub fn format_time_delta(time_delta: &TimeDelta) -> String {
         milliseconds
     )
 }
+
+pub fn format_size(bytes: u64) -> String {
+    let kb = bytes as f64 / 1024.0;
+    let mb = kb / 1024.0;
+    let gb = mb / 1024.0;
+
+    if gb >= 1.0 {
+        format!("{:.2}GB", gb)
+    } else if mb >= 1.0 {
+        format!("{:.0}MB", mb.ceil())
+    } else {
+        format!("{:.0}KB", kb.ceil())
+    }
+}
@@ -1,16 +1,17 @@
 use crate::utils::filter::real_dedup_vec;
-use crate::utils::locale::LanguageTagging;
+use crate::utils::locale::{region, LanguageTagging};
 use crate::utils::log::tab_info;
 use crate::utils::os::{is_special_file, sanitize};
 use anyhow::{bail, Result};
 use chrono::{Datelike, Duration};
 use crunchyroll_rs::media::{SkipEvents, Stream, StreamData, Subtitle};
 use crunchyroll_rs::{Concert, Episode, Locale, MediaCollection, Movie, MusicVideo};
-use log::{debug, info};
+use log::{debug, info, warn};
+use regex::Regex;
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
-use std::env;
 use std::path::{Path, PathBuf};
+use std::{env, fmt};
 
 #[allow(dead_code)]
 #[derive(Clone)]
@@ -42,6 +43,9 @@ pub struct SingleFormat {
 
     pub duration: Duration,
 
+    /// The part number and the total number of parts if the video is split into multiple parts.
+    pub part: Option<(u32, u32)>,
+
     source: MediaCollection,
 }
 
@@ -87,6 +91,7 @@ impl SingleFormat {
             relative_episode_number,
             relative_sequence_number,
             duration: episode.duration,
+            part: None,
             source: episode.into(),
         }
     }
@@ -112,6 +117,7 @@ impl SingleFormat {
             sequence_number: 1.0,
             relative_sequence_number: Some(1.0),
             duration: movie.duration,
+            part: None,
             source: movie.into(),
         }
     }
@@ -137,6 +143,7 @@ impl SingleFormat {
             sequence_number: 1.0,
             relative_sequence_number: Some(1.0),
             duration: music_video.duration,
+            part: None,
             source: music_video.into(),
         }
     }
@@ -162,10 +169,26 @@ impl SingleFormat {
             sequence_number: 1.0,
             relative_sequence_number: Some(1.0),
             duration: concert.duration,
+            part: None,
             source: concert.into(),
         }
     }
 
+    /// Url of the biggest thumbnail image of the episode / movie. Other media types have no
+    /// thumbnail which fits as cover art.
+    pub fn thumbnail(&self) -> Option<String> {
+        let images = match &self.source {
+            MediaCollection::Episode(e) => &e.images.thumbnail,
+            MediaCollection::Movie(m) => &m.images.thumbnail,
+            _ => return None,
+        };
+        images
+            .iter()
+            .flatten()
+            .max_by_key(|image| image.width)
+            .map(|image| image.source.clone())
+    }
+
     pub async fn stream(&self) -> Result<Stream> {
         let stream = match &self.source {
             MediaCollection::Episode(e) => e.stream_maybe_without_drm().await,
@@ -179,6 +202,27 @@ impl SingleFormat {
             if message.starts_with("TOO_MANY_ACTIVE_STREAMS") {
                 bail!("Too many active/parallel streams. Please close at least one stream you're watching and try again")
             }
+            let upper_message = message.to_uppercase();
+            if REGION_LOCKED_MESSAGES
+                .iter()
+                .any(|m| upper_message.contains(m))
+            {
+                bail!(UnavailableError(format!(
+                    "{} is not available in your region{}: {}",
+                    self.source_type(),
+                    region().map_or("".to_string(), |r| format!(" ({})", r)),
+                    message
+                )))
+            } else if UNAVAILABLE_MESSAGES
+                .iter()
+                .any(|m| upper_message.contains(m))
+            {
+                bail!(UnavailableError(format!(
+                    "{} is not available (probably region locked): {}",
+                    self.source_type(),
+                    message
+                )))
+            }
         };
         Ok(stream?)
     }
@@ -206,9 +250,80 @@ impl SingleFormat {
         matches!(self.source, MediaCollection::Episode(_))
     }
 
+    /// Mark this format as part `part` of a video which is split into `total` parts. The part
+    /// number is also used as episode number, so that all parts are kept apart.
+    pub fn set_part(&mut self, part: u32, total: u32) {
+        self.part = Some((part, total));
+        self.episode_number = part.to_string();
+        self.relative_episode_number = Some(part);
+        self.sequence_number = part as f32;
+        self.relative_sequence_number = Some(part as f32);
+    }
+
+    pub fn is_movie(&self) -> bool {
+        matches!(self.source, MediaCollection::Movie(_))
+    }
+
     pub fn is_special(&self) -> bool {
         self.sequence_number == 0.0 || self.sequence_number.fract() != 0.0
     }
+
+    /// Crunchyroll has no dedicated trailer endpoint, trailers and previews are listed as special
+    /// episodes of a season instead.
+    pub fn is_trailer(&self) -> bool {
+        self.is_episode()
+            && self.is_special()
+            && is_trailer_episode(&self.episode_number, &self.title)
+    }
+}
+
+/// Words which mark an episode as trailer or preview if they appear in its episode number or title.
+const TRAILER_KEYWORDS: [&str; 4] = ["trailer", "teaser", "preview", "pv"];
+
+/// Checks if the episode number (e.g. 'PV1') or title of an episode contains a trailer keyword. This
+/// only matches whole words, so an episode titled 'Pvp' isn't a trailer.
+pub fn is_trailer_episode(episode_number: &str, title: &str) -> bool {
+    [episode_number, title].iter().any(|s| {
+        s.split(|c: char| !c.is_alphanumeric()).any(|word| {
+            let word = word
+                .trim_end_matches(|c: char| c.is_ascii_digit())
+                .to_lowercase();
+            TRAILER_KEYWORDS.contains(&word.as_str())
+        })
+    })
+}
+
+/// Parts of Crunchyroll error messages which indicate that a video isn't available, e.g. because
+/// it's region locked.
+const UNAVAILABLE_MESSAGES: [&str; 3] = ["FORBIDDEN", "NOT_AVAILABLE", "UNAVAILABLE"];
+/// Parts of Crunchyroll error messages which explicitly state that a video is region locked.
+const REGION_LOCKED_MESSAGES: [&str; 2] = ["REGION", "GEO"];
+
+/// Error if a video isn't available, e.g. because it's region locked. Videos which fail with this
+/// error are skipped instead of aborting the whole download (unless `--fail-fast` is set).
+#[derive(Debug)]
+pub struct UnavailableError(pub String);
+
+impl fmt::Display for UnavailableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for UnavailableError {}
+
+/// Print all skipped videos with the reason why they were skipped and fail if any video was
+/// skipped.
+pub fn report_skipped(skipped: &[(String, String)]) -> Result<()> {
+    if skipped.is_empty() {
+        return Ok(());
+    }
+
+    warn!("{} video(s) were skipped:", skipped.len());
+    for (name, reason) in skipped {
+        warn!("  {}: {}", name, reason)
+    }
+    bail!("{} video(s) could not be downloaded", skipped.len())
 }
 
 struct SingleFormatCollectionEpisodeKey(f32);
@@ -292,6 +407,13 @@ impl SingleFormatCollection {
             );
     }
 
+    pub fn has_trailers(&self) -> bool {
+        self.0
+            .values()
+            .flat_map(|episodes| episodes.values())
+            .any(|formats| formats.first().is_some_and(|f| f.is_trailer()))
+    }
+
     pub fn full_visual_output(&self) {
         debug!("Series has {} seasons", self.0.len());
         for (season_key, episodes) in &self.0 {
@@ -348,6 +470,61 @@ impl Iterator for SingleFormatCollectionIterator {
     }
 }
 
+/// Output template which results in the folder and file structure Plex expects for series
+/// (`Show Name/Season 01/Show Name - S01E03 - Title.mkv`).
+pub const PLEX_OUTPUT_TEMPLATE: &str =
+    "{series_name}/Season {season_number}/{series_name} - S{season_number}E{episode_number} - {title}.mkv";
+
+/// All options which can be used in an output template.
+const PATH_TEMPLATE_OPTIONS: [&str; 21] = [
+    "title",
+    "episode_title",
+    "series_name",
+    "season_name",
+    "audio",
+    "width",
+    "height",
+    "resolution",
+    "fps",
+    "season_number",
+    "episode_number",
+    "episode_number_padded",
+    "relative_episode_number",
+    "sequence_number",
+    "relative_sequence_number",
+    "release_year",
+    "release_month",
+    "release_day",
+    "series_id",
+    "season_id",
+    "episode_id",
+];
+
+lazy_static::lazy_static! {
+    static ref PATH_TEMPLATE_OPTION_RE: Regex = Regex::new(r"\{(?P<option>\w+)}").unwrap();
+}
+
+/// Check if all options (`{...}`) in the given output template are valid, so an invalid option is
+/// detected before anything is downloaded.
+pub fn check_path_template(template: &str) -> Result<()> {
+    for capture in PATH_TEMPLATE_OPTION_RE.captures_iter(template) {
+        let option = capture.name("option").unwrap().as_str();
+        if !PATH_TEMPLATE_OPTIONS.contains(&option) {
+            bail!(
+                "Invalid template option `{{{}}}` in output '{}'. Valid options are: {}",
+                option,
+                template,
+                PATH_TEMPLATE_OPTIONS
+                    .iter()
+                    .map(|o| format!("`{{{}}}`", o))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )
+        }
+    }
+    Ok(())
+}
+
 #[allow(dead_code)]
 #[derive(Clone)]
 pub struct Format {
@@ -421,16 +598,29 @@ impl Format {
     }
 
     /// Formats the given string if it has specific pattern in it. It also sanitizes the filename.
+    /// If `raw_episode_number` is set, `{episode_number}` is inserted exactly as Crunchyroll
+    /// provides it instead of being zero padded.
     pub fn format_path(
         &self,
         path: PathBuf,
         universal: bool,
+        raw_episode_number: bool,
         language_tagging: Option<&LanguageTagging>,
     ) -> PathBuf {
+        let episode_number = sanitize(&self.episode_number, true, universal);
+        // only pad episode numbers which are actual integers, padding something like '5.5' or
+        // 'SP1' results in names which are neither sortable nor match the crunchyroll numbering
+        let episode_number_padded = if self.episode_number.parse::<u32>().is_ok() {
+            format!("{:0>2}", episode_number)
+        } else {
+            episode_number.clone()
+        };
+
         let path = path
             .to_string_lossy()
             .to_string()
             .replace("{title}", &sanitize(&self.title, true, universal))
+            .replace("{episode_title}", &sanitize(&self.title, true, universal))
             .replace(
                 "{audio}",
                 &sanitize(
@@ -454,6 +644,20 @@ impl Format {
                 "{height}",
                 &sanitize(self.height.to_string(), true, universal),
             )
+            .replace(
+                "{resolution}",
+                &sanitize(format!("{}p", self.height), true, universal),
+            )
+            .replace(
+                "{fps}",
+                &sanitize(
+                    format!("{:.3}", self.fps)
+                        .trim_end_matches('0')
+                        .trim_end_matches('.'),
+                    true,
+                    universal,
+                ),
+            )
             .replace("{series_id}", &sanitize(&self.series_id, true, universal))
             .replace(
                 "{series_name}",
@@ -472,9 +676,14 @@ impl Format {
                 ),
             )
             .replace("{episode_id}", &sanitize(&self.episode_id, true, universal))
+            .replace("{episode_number_padded}", &episode_number_padded)
             .replace(
                 "{episode_number}",
-                &format!("{:0>2}", sanitize(&self.episode_number, true, universal)),
+                &if raw_episode_number {
+                    episode_number
+                } else {
+                    format!("{:0>2}", episode_number)
+                },
             )
             .replace(
                 "{relative_episode_number}",
@@ -601,3 +810,74 @@ impl Format {
             || s.as_ref().contains("{relative_sequence_number}");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_with_episode_number(episode_number: &str) -> Format {
+        Format {
+            title: "Title".to_string(),
+            description: "".to_string(),
+            locales: vec![(Locale::ja_JP, vec![Locale::en_US])],
+            width: 1920,
+            height: 1080,
+            fps: 23.976,
+            release_year: 2018,
+            release_month: 1,
+            release_day: 13,
+            series_id: "GY8VEQ95Y".to_string(),
+            series_name: "Series".to_string(),
+            season_id: "GR49C7EPD".to_string(),
+            season_title: "Season".to_string(),
+            season_number: 1,
+            episode_id: "GR3VWXP96".to_string(),
+            episode_number: episode_number.to_string(),
+            relative_episode_number: None,
+            sequence_number: 0.0,
+            relative_sequence_number: None,
+        }
+    }
+
+    /// Format `E{episode_number} P{episode_number_padded}` with the given episode number.
+    fn format_episode_number(episode_number: &str, raw_episode_number: bool) -> String {
+        format_with_episode_number(episode_number)
+            .format_path(
+                PathBuf::from("E{episode_number} P{episode_number_padded}"),
+                false,
+                raw_episode_number,
+                None,
+            )
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn format_path_episode_number() {
+        // without `raw_episode_number`, every episode number is padded to two characters
+        assert_eq!(format_episode_number("5", false), "E05 P05");
+        assert_eq!(format_episode_number("12", false), "E12 P12");
+        assert_eq!(format_episode_number("5.5", false), "E5.5 P5.5");
+        assert_eq!(format_episode_number("SP1", false), "ESP1 PSP1");
+        assert_eq!(format_episode_number("A", false), "E0A PA");
+
+        // only integer episode numbers are padded by `{episode_number_padded}`
+        assert_eq!(format_episode_number("5", true), "E5 P05");
+        assert_eq!(format_episode_number("12", true), "E12 P12");
+        assert_eq!(format_episode_number("5.5", true), "E5.5 P5.5");
+        assert_eq!(format_episode_number("SP1", true), "ESP1 PSP1");
+        assert_eq!(format_episode_number("A", true), "EA PA");
+    }
+
+    #[test]
+    fn trailer_episode() {
+        assert!(is_trailer_episode("PV1", "Episode Title"));
+        assert!(is_trailer_episode("", "Official Trailer"));
+        assert!(is_trailer_episode("", "Teaser-PV"));
+        assert!(is_trailer_episode("", "Preview 2"));
+
+        assert!(!is_trailer_episode("5", "Episode Title"));
+        assert!(!is_trailer_episode("SP1", "Pvp Tournament"));
+        assert!(!is_trailer_episode("", "Trailers"));
+    }
+}
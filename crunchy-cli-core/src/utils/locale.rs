@@ -1,5 +1,23 @@
+use anyhow::{bail, Result};
 use crunchyroll_rs::Locale;
 use log::warn;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+static REGION: OnceLock<String> = OnceLock::new();
+
+/// Set the region of the account for this invocation. Only the first call has an effect.
+pub fn set_region(region: String) {
+    let _ = REGION.set(region);
+}
+
+/// Get the region of the account, if it was set via [`set_region`]. The region is only a hint for
+/// messages about region locked videos, it doesn't change which videos are available.
+pub fn region() -> Option<&'static str> {
+    REGION.get().map(|r| r.as_str())
+}
 
 #[derive(Clone, Debug)]
 #[allow(clippy::upper_case_acronyms)]
@@ -65,7 +83,76 @@ impl LanguageTagging {
     }
 }
 
-pub fn resolve_locales(locales: &[Locale]) -> Vec<Locale> {
+/// Language tags which are written into the container instead of the default ones, read from a
+/// file via [`LocaleMap::read`].
+#[derive(Clone, Debug, Default)]
+pub struct LocaleMap {
+    pub audio: HashMap<Locale, String>,
+    pub subtitle: HashMap<Locale, String>,
+}
+
+impl LocaleMap {
+    /// Read a locale map file. Every line has the format `<locale>=<tag>` (e.g. `es-419=spa`).
+    /// Lines before any section apply to audios and subtitles, lines after an `[audio]` or
+    /// `[subtitle]` line only to the respective tracks. Empty lines and lines starting with `#` are
+    /// ignored.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        let all_locales = Locale::all();
+
+        let mut locale_map = Self::default();
+        let mut section = None;
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line {
+                "[audio]" | "[subtitle]" => {
+                    section = Some(line.trim_matches(['[', ']']));
+                    continue;
+                }
+                _ => (),
+            }
+
+            let Some((locale, tag)) = line
+                .split_once('=')
+                .map(|(l, t)| (Locale::from(l.trim().to_string()), t.trim().to_string()))
+                .filter(|(_, t)| !t.is_empty())
+            else {
+                bail!(
+                    "Invalid line {} in locale map '{}': '{}' is not in format <locale>=<tag>",
+                    i + 1,
+                    path.to_string_lossy(),
+                    line
+                )
+            };
+            if !all_locales.contains(&locale) {
+                bail!(
+                    "Invalid line {} in locale map '{}': '{}' is not a valid locale",
+                    i + 1,
+                    path.to_string_lossy(),
+                    locale
+                )
+            }
+
+            if section != Some("subtitle") {
+                locale_map.audio.insert(locale.clone(), tag.clone());
+            }
+            if section != Some("audio") {
+                locale_map.subtitle.insert(locale, tag);
+            }
+        }
+
+        Ok(locale_map)
+    }
+}
+
+/// Resolve IETF language codes (e.g. `es`) to their matching Crunchyroll locales. If `strict` is
+/// set, no implicit substitution is made and every locale which isn't an exact Crunchyroll locale
+/// results in an error.
+pub fn resolve_locales(locales: &[Locale], strict: bool) -> Result<Vec<Locale>> {
     let ietf_language_codes = ietf_language_codes();
     let all_locales = Locale::all();
 
@@ -79,19 +166,69 @@ pub fn resolve_locales(locales: &[Locale]) -> Vec<Locale> {
         {
             let (first, alternatives) = resolved_locales.split_first().unwrap();
 
+            if strict {
+                bail!(
+                    "Locale '{}' is not an exact locale and is not resolved because of `--strict-locale-match`. Please use one of the following locales instead: {}",
+                    locale,
+                    resolved_locales
+                        .iter()
+                        .map(|l| format!("'{l}'"))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )
+            }
+
             resolved.push(first.clone());
             // ignoring `Locale::en_IN` because I think the majority of users which want english
             // audio / subs want the "actual" english version and not the hindi accent dub
             if !alternatives.is_empty() && resolved_locales.first().unwrap() != &Locale::en_IN {
                 warn!("Resolving locale '{}' to '{}', but there are some alternatives: {}. If you an alternative instead, please write it completely out instead of '{}'", locale, first, alternatives.iter().map(|l| format!("'{l}'")).collect::<Vec<String>>().join(", "), locale)
             }
+        } else if strict {
+            bail!("Unknown locale '{}'", locale)
         } else {
             resolved.push(locale.clone());
             warn!("Unknown locale '{}'", locale)
         }
     }
 
-    resolved
+    Ok(resolved)
+}
+
+/// Get the ISO 639-2 (bibliographic) language code of a locale. Returns the locale itself if it
+/// has no known code.
+pub fn iso639_2_code(locale: &Locale) -> String {
+    let Some((tag, _)) = ietf_language_codes()
+        .into_iter()
+        .find(|(_, l)| l.contains(locale))
+    else {
+        return locale.to_string();
+    };
+    match tag {
+        "ar" => "ara",
+        "ca" => "cat",
+        "de" => "ger",
+        "en" => "eng",
+        "es" => "spa",
+        "fr" => "fre",
+        "hi" => "hin",
+        "id" => "ind",
+        "it" => "ita",
+        "ja" => "jpn",
+        "ko" => "kor",
+        "ms" => "may",
+        "pl" => "pol",
+        "pt" => "por",
+        "ru" => "rus",
+        "ta" => "tam",
+        "te" => "tel",
+        "th" => "tha",
+        "tr" => "tur",
+        "vi" => "vie",
+        "zh" => "chi",
+        _ => tag,
+    }
+    .to_string()
 }
 
 fn ietf_language_codes<'a>() -> Vec<(&'a str, Vec<Locale>)> {
@@ -4,10 +4,18 @@ use log::{
     SetLoggerError,
 };
 use std::io::{stdout, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
 use std::thread;
 use std::time::Duration;
 
+static PROGRESS_HIDDEN: AtomicBool = AtomicBool::new(false);
+
+/// If all progress bars and spinners should be hidden, regardless of the log level.
+pub fn progress_hidden() -> bool {
+    PROGRESS_HIDDEN.load(Ordering::Relaxed)
+}
+
 pub struct ProgressHandler {
     pub(crate) stopped: bool,
 }
@@ -120,7 +128,8 @@ impl CliLogger {
         }
     }
 
-    pub fn init(level: LevelFilter) -> Result<(), SetLoggerError> {
+    pub fn init(level: LevelFilter, hide_progress: bool) -> Result<(), SetLoggerError> {
+        PROGRESS_HIDDEN.store(hide_progress, Ordering::Relaxed);
         set_max_level(level);
         set_boxed_logger(Box::new(CliLogger::new(level)))
     }
@@ -155,6 +164,14 @@ impl CliLogger {
         let mut progress = self.progress.lock().unwrap();
 
         let msg = format!("{}", record.args());
+        // without progress spinners the messages are just printed as normal log lines
+        if progress_hidden() {
+            if !msg.is_empty() {
+                println!(":: {}", msg)
+            }
+            return;
+        }
+
         if stop && progress.is_some() {
             if msg.is_empty() {
                 progress.take().unwrap().finish()
@@ -0,0 +1,66 @@
+use crate::utils::context::Context;
+use anyhow::Result;
+use crunchyroll_rs::media::{Stream, StreamData};
+use log::debug;
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+
+/// Write the raw manifest of a stream and the segment urls of the given stream data to `dir`. If
+/// `redact` is set, all url query values (which contain the signed access tokens) are replaced.
+pub async fn dump_manifest(
+    ctx: &Context,
+    stream: &Stream,
+    stream_data: &[&StreamData],
+    name: &str,
+    dir: &Path,
+    redact: bool,
+) -> Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let manifest = ctx
+        .client
+        .get(&stream.url)
+        .bearer_auth(ctx.crunchy.access_token().await)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    let mut segments = vec![];
+    for (i, data) in stream_data.iter().enumerate() {
+        segments.push(format!(
+            "# stream #{} (bandwidth: {})",
+            i + 1,
+            data.bandwidth
+        ));
+        segments.extend(data.segments().into_iter().map(|s| s.url));
+    }
+
+    let manifest_path = dir.join(format!("{}.mpd", name));
+    let segments_path = dir.join(format!("{}.segments.txt", name));
+    if redact {
+        fs::write(&manifest_path, redact_urls(&manifest))?;
+        fs::write(&segments_path, redact_urls(segments.join("\n")) + "\n")?;
+    } else {
+        fs::write(&manifest_path, manifest)?;
+        fs::write(&segments_path, segments.join("\n") + "\n")?;
+    }
+    debug!(
+        "Dumped manifest to '{}' and segments to '{}'",
+        manifest_path.to_string_lossy(),
+        segments_path.to_string_lossy()
+    );
+
+    Ok(())
+}
+
+/// Replace the values of all url query parameters. The manifest is xml which escapes `&` as
+/// `&amp;`, so a `;` is also accepted as parameter separator.
+fn redact_urls<S: AsRef<str>>(s: S) -> String {
+    let query_param = Regex::new(r#"(?P<key>[?&;][\w\-]+=)[^&"'\s<>]+"#).unwrap();
+    query_param
+        .replace_all(s.as_ref(), "${key}REDACTED")
+        .to_string()
+}
@@ -1,4 +1,6 @@
+pub mod cache;
 pub mod clap;
+pub mod concat;
 pub mod context;
 pub mod download;
 pub mod ffmpeg;
@@ -8,8 +10,10 @@ pub mod format;
 pub mod interactive_select;
 pub mod locale;
 pub mod log;
+pub mod manifest;
 pub mod os;
 pub mod parse;
 pub mod rate_limit;
+pub mod sidecar;
 pub mod sync;
 pub mod video;
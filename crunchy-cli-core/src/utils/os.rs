@@ -1,35 +1,87 @@
+use dialoguer::console::Term;
+use dialoguer::Confirm;
 use log::debug;
 use regex::{Regex, RegexBuilder};
 use std::borrow::Cow;
-use std::io::ErrorKind;
+use std::io::{ErrorKind, Read};
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::process::{Command, Stdio};
+use std::sync::OnceLock;
 use std::task::{Context, Poll};
 use std::{env, fs, io};
 use tempfile::{Builder, NamedTempFile, TempPath};
 use tokio::io::{AsyncRead, ReadBuf};
 
+static FFMPEG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Overwrite the ffmpeg binary for this invocation. Only the first call has an effect.
+pub fn set_ffmpeg_path(path: PathBuf) {
+    let _ = FFMPEG_PATH.set(path);
+}
+
+/// Get the ffmpeg binary either by the path set via [`set_ffmpeg_path`], the specified
+/// `CRUNCHY_CLI_FFMPEG` env variable or `ffmpeg`, which is looked up in `PATH`.
+pub fn ffmpeg_path() -> PathBuf {
+    if let Some(ffmpeg_path) = FFMPEG_PATH.get() {
+        return ffmpeg_path.clone();
+    }
+    env::var("CRUNCHY_CLI_FFMPEG").map_or(PathBuf::from("ffmpeg"), PathBuf::from)
+}
+
+/// Get the ffprobe binary. If ffmpeg is set to a path (instead of only a binary name which is
+/// looked up in `PATH`), ffprobe is expected to lie next to it.
+pub fn ffprobe_path() -> PathBuf {
+    match ffmpeg_path().parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => {
+            parent.join(format!("ffprobe{}", env::consts::EXE_SUFFIX))
+        }
+        _ => PathBuf::from("ffprobe"),
+    }
+}
+
 pub fn has_ffmpeg() -> bool {
-    if let Err(e) = Command::new("ffmpeg").stderr(Stdio::null()).spawn() {
-        if ErrorKind::NotFound != e.kind() {
-            debug!(
-                "unknown error occurred while checking if ffmpeg exists: {}",
-                e.kind()
-            )
+    match Command::new(ffmpeg_path())
+        .arg("-version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+    {
+        Ok(status) => status.success(),
+        Err(e) => {
+            if ErrorKind::NotFound != e.kind() {
+                debug!(
+                    "unknown error occurred while checking if ffmpeg exists: {}",
+                    e.kind()
+                )
+            }
+            false
         }
-        false
-    } else {
-        true
     }
 }
 
-/// Get the temp directory either by the specified `CRUNCHY_CLI_TEMP_DIR` env variable or the dir
-/// provided by the os.
+static TEMP_DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+
+/// Overwrite the temp directory for this invocation. Only the first call has an effect.
+pub fn set_temp_directory(path: PathBuf) {
+    let _ = TEMP_DIRECTORY.set(path);
+}
+
+/// Get the temp directory either by the directory set via [`set_temp_directory`], the specified
+/// `CRUNCHY_CLI_TEMP_DIR` env variable or the dir provided by the os.
 pub fn temp_directory() -> PathBuf {
+    if let Some(temp_directory) = TEMP_DIRECTORY.get() {
+        return temp_directory.clone();
+    }
     env::var("CRUNCHY_CLI_TEMP_DIR").map_or(env::temp_dir(), PathBuf::from)
 }
 
+/// Get the directory in which local fonts are looked up, if specified by the `CRUNCHY_CLI_FONT_DIR`
+/// env variable.
+pub fn font_directory() -> Option<PathBuf> {
+    env::var("CRUNCHY_CLI_FONT_DIR").ok().map(PathBuf::from)
+}
+
 /// Any tempfile should be created with this function. The prefix and directory of every file
 /// created with this function stays the same which is helpful to query all existing tempfiles and
 /// e.g. remove them in a case of ctrl-c. Having one function also good to prevent mistakes like
@@ -138,6 +190,47 @@ pub fn temp_named_pipe() -> io::Result<TempNamedPipe> {
     }
 }
 
+/// A regular temporary file which is read like `tail -f`: if the end of the file is reached, the
+/// reader waits until more data is written to it instead of returning eof.
+pub struct TempTailFile {
+    path: TempPath,
+    file: fs::File,
+}
+
+impl TempTailFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsyncRead for TempTailFile {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let read = self.file.read(buf.initialize_unfilled())?;
+        if read > 0 {
+            buf.advance(read);
+            return Poll::Ready(Ok(()));
+        }
+
+        // poll again after a short time to not loop insanely fast and consume unnecessary system
+        // resources
+        let waker = cx.waker().clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            waker.wake()
+        });
+        Poll::Pending
+    }
+}
+
+pub fn temp_tail_file() -> io::Result<TempTailFile> {
+    let (file, path) = tempfile("")?.into_parts();
+    Ok(TempTailFile { path, file })
+}
+
 /// Check if the given path exists and rename it until the new (renamed) file does not exist.
 pub fn free_file(mut path: PathBuf) -> (PathBuf, bool) {
     // do not rename it if it exists but is a special file
@@ -170,6 +263,41 @@ pub fn free_file(mut path: PathBuf) -> (PathBuf, bool) {
     (path, i != 0)
 }
 
+/// Check if the already existing file at `path` should be overwritten, according to the given
+/// `--overwrite` policy ('always', 'never' or 'prompt'). 'prompt' asks interactively if stdout is a
+/// terminal and falls back to 'never' otherwise.
+pub fn should_overwrite<P: AsRef<Path>>(path: P, policy: &str) -> bool {
+    match policy {
+        "always" => true,
+        "prompt" if Term::stdout().is_term() => Confirm::new()
+            .with_prompt(format!(
+                "File '{}' already exists. Overwrite it?",
+                path.as_ref().to_string_lossy()
+            ))
+            .default(false)
+            .interact_on(&Term::stdout())
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Check if the given path is an already existing, non-empty regular file. If `min_size` is set,
+/// the file must additionally be at least `min_size` bytes big. Special files and stdout (`-`) are
+/// never considered as existing.
+pub fn is_existing_file<P: AsRef<Path>>(path: P, min_size: Option<u64>) -> bool {
+    let path = path.as_ref();
+    if is_special_file(path) || path.to_string_lossy() == "-" {
+        return false;
+    }
+
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => {
+            metadata.len() > 0 && metadata.len() >= min_size.unwrap_or_default()
+        }
+        _ => false,
+    }
+}
+
 /// Check if the given path is a special file. On Linux this is probably a pipe and on Windows
 /// ¯\_(ツ)_/¯
 pub fn is_special_file<P: AsRef<Path>>(path: P) -> bool {
@@ -1,3 +1,5 @@
+use crate::utils::format::UnavailableError;
+use crate::utils::locale::region;
 use anyhow::{anyhow, bail, Result};
 use crunchyroll_rs::media::Resolution;
 use crunchyroll_rs::{Crunchyroll, MediaCollection, UrlType};
@@ -154,7 +156,20 @@ pub async fn parse_url(
         | UrlType::MovieListing(id)
         | UrlType::EpisodeOrMovie(id)
         | UrlType::MusicVideo(id)
-        | UrlType::Concert(id) => crunchy.media_collection_from_id(id).await?,
+        | UrlType::Concert(id) => match crunchy.media_collection_from_id(&id).await {
+            Ok(media_collection) => media_collection,
+            // the crunchyroll api responds to ids which aren't available in the region of the
+            // account the same as to ids which don't exist at all
+            Err(crunchyroll_rs::error::Error::Request {
+                status: Some(status),
+                ..
+            }) if status.as_u16() == 404 => bail!(UnavailableError(format!(
+                "{} was not found, it may not be available in your region{}",
+                id,
+                region().map_or("".to_string(), |r| format!(" ({})", r))
+            ))),
+            Err(e) => bail!(e),
+        },
     };
 
     Ok((media_collection, url_filter))
@@ -205,3 +220,79 @@ pub fn fract(input: f32) -> f32 {
         .parse::<f32>()
         .unwrap()
 }
+
+/// Split an input in the format of `<url>=<output>` into the url and the output. Crunchyroll urls
+/// don't contain a `=` (except in query parameters), so everything after the first `=` which is
+/// not part of a query is the output.
+pub fn split_url_output<S: AsRef<str>>(input: S) -> (String, Option<String>) {
+    let input = input.as_ref();
+    match input.split_once('=') {
+        Some((url, output)) if !url.contains('?') && !output.is_empty() => {
+            (url.to_string(), Some(output.to_string()))
+        }
+        _ => (input.to_string(), None),
+    }
+}
+
+/// Convert an episode selection like `5-12,14,S1` into the filter syntax of [`parse_url`]. Plain
+/// numbers are episode numbers, everything else must already be in the filter syntax (e.g. `S1` or
+/// `S2E3-S2E5`).
+pub fn episodes_to_url_filter<S: AsRef<str>>(episodes: S) -> Result<String> {
+    let episode_range_regex = Regex::new(r"^(?P<from>\d+)?(?P<dash>-)?(?P<to>\d+)?$").unwrap();
+    let filter_regex = Regex::new(r"^(S\d+)?(E\d+)?(-(S\d+)?(E\d+)?)?$").unwrap();
+
+    let mut filters = vec![];
+    for item in episodes
+        .as_ref()
+        .split(',')
+        .map(|i| i.trim().to_uppercase())
+    {
+        // a range needs at least one bound, a bare '-' is invalid
+        if let Some(capture) = episode_range_regex
+            .captures(&item)
+            .filter(|c| c.name("from").is_some() || c.name("to").is_some())
+        {
+            let parse_episode = |number: &str| match number.parse::<u32>() {
+                Ok(number) => Ok(number),
+                Err(_) => bail!(
+                    "Invalid episode selection '{}': episode number {} is too large",
+                    item,
+                    number
+                ),
+            };
+            let from = capture
+                .name("from")
+                .map(|f| parse_episode(f.as_str()))
+                .transpose()?;
+            let to = capture
+                .name("to")
+                .map(|t| parse_episode(t.as_str()))
+                .transpose()?;
+            if let (Some(from), Some(to)) = (from, to) {
+                if from > to {
+                    bail!(
+                        "Invalid episode range '{}': the first episode ({}) is after the last episode ({})",
+                        item,
+                        from,
+                        to
+                    )
+                }
+            }
+            filters.push(format!(
+                "{}{}{}",
+                from.map_or("".to_string(), |f| format!("E{}", f)),
+                capture.name("dash").map_or("", |d| d.as_str()),
+                to.map_or("".to_string(), |t| format!("E{}", t))
+            ))
+        } else if filter_regex.is_match(&item) && item != "-" && !item.is_empty() {
+            filters.push(item)
+        } else {
+            bail!(
+                "Invalid episode selection '{}'. Valid are episode numbers (e.g. '5'), episode ranges (e.g. '5-12', '5-' or '-12') and seasons / season episodes (e.g. 'S1' or 'S2E3-S2E5'), separated by ','",
+                item
+            )
+        }
+    }
+
+    Ok(filters.join(","))
+}
@@ -1,7 +1,9 @@
+use crate::utils::fmt::format_size;
 use async_speed_limit::Limiter;
 use crunchyroll_rs::error::Error;
 use futures_util::TryStreamExt;
-use reqwest::{Client, Request, Response, ResponseBuilderExt};
+use log::debug;
+use reqwest::{Client, Request, Response, ResponseBuilderExt, StatusCode};
 use std::future::Future;
 use std::io;
 use std::pin::Pin;
@@ -9,10 +11,20 @@ use std::sync::Arc;
 use std::task::{Context, Poll};
 use tower_service::Service;
 
+/// The speed limit is never lowered below this (in bytes per second) by the adaptive rate.
+const ADAPTIVE_MIN_SPEED_LIMIT: f64 = 64.0 * 1024.0;
+/// Factor by which the speed limit recovers with every successful response if the adaptive rate is
+/// enabled.
+const ADAPTIVE_RECOVERY_FACTOR: f64 = 1.05;
+
 #[derive(Clone)]
 pub struct RateLimiterService {
     client: Arc<Client>,
     rate_limiter: Limiter,
+    /// The initial speed limit if the adaptive rate is enabled. The current speed limit is halved
+    /// on every rate limited (429) response and recovers gradually up to this value with every
+    /// successful response.
+    adaptive_max: Option<f64>,
 }
 
 impl RateLimiterService {
@@ -20,8 +32,15 @@ impl RateLimiterService {
         Self {
             client: Arc::new(client),
             rate_limiter: Limiter::new(bytes as f64),
+            adaptive_max: None,
         }
     }
+
+    /// Adapt the speed limit to rate limited responses.
+    pub fn adaptive(mut self, adaptive: bool) -> Self {
+        self.adaptive_max = adaptive.then(|| self.rate_limiter.speed_limit());
+        self
+    }
 }
 
 impl Service<Request> for RateLimiterService {
@@ -36,10 +55,26 @@ impl Service<Request> for RateLimiterService {
     fn call(&mut self, req: Request) -> Self::Future {
         let client = self.client.clone();
         let rate_limiter = self.rate_limiter.clone();
+        let adaptive_max = self.adaptive_max;
 
         Box::pin(async move {
             let mut body = vec![];
             let res = client.execute(req).await?;
+
+            if let Some(adaptive_max) = adaptive_max {
+                let speed_limit = rate_limiter.speed_limit();
+                if res.status() == StatusCode::TOO_MANY_REQUESTS {
+                    let lowered = (speed_limit / 2.0).max(ADAPTIVE_MIN_SPEED_LIMIT);
+                    rate_limiter.set_speed_limit(lowered);
+                    debug!(
+                        "Received rate limit response, lowering speed limit to {}/s",
+                        format_size(lowered as u64)
+                    )
+                } else if res.status().is_success() && speed_limit < adaptive_max {
+                    rate_limiter
+                        .set_speed_limit((speed_limit * ADAPTIVE_RECOVERY_FACTOR).min(adaptive_max))
+                }
+            }
             let _url = res.url().clone().to_string();
             let url = _url.as_str();
 
@@ -0,0 +1,328 @@
+use crate::utils::download::{get_video_stats, DownloadFormat};
+use crate::utils::format::Format;
+use anyhow::Result;
+use crunchyroll_rs::Series;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::{env, fs};
+
+/// Write a Kodi / Jellyfin compatible `.nfo` file next to `video_path`. The file has the same name
+/// as the video, so media server scrapers pick it up automatically.
+pub fn write_nfo(format: &Format, is_movie: bool, video_path: &Path) -> Result<PathBuf> {
+    let release_date = format!(
+        "{}-{:0>2}-{:0>2}",
+        format.release_year, format.release_month, format.release_day
+    );
+
+    let mut nfo = vec![r#"<?xml version="1.0" encoding="UTF-8" standalone="yes" ?>"#.to_string()];
+    if is_movie {
+        nfo.extend([
+            "<movie>".to_string(),
+            format!("  <title>{}</title>", xml_escape(&format.title)),
+            format!("  <plot>{}</plot>", xml_escape(&format.description)),
+            format!("  <premiered>{}</premiered>", release_date),
+            format!("  <year>{}</year>", format.release_year),
+            format!(
+                r#"  <uniqueid type="crunchyroll" default="true">{}</uniqueid>"#,
+                xml_escape(&format.episode_id)
+            ),
+            "</movie>".to_string(),
+        ])
+    } else {
+        nfo.extend([
+            "<episodedetails>".to_string(),
+            format!("  <title>{}</title>", xml_escape(&format.title)),
+            format!(
+                "  <showtitle>{}</showtitle>",
+                xml_escape(&format.series_name)
+            ),
+            format!("  <season>{}</season>", format.season_number),
+            format!(
+                "  <episode>{}</episode>",
+                xml_escape(&format.episode_number)
+            ),
+            format!("  <aired>{}</aired>", release_date),
+            format!("  <plot>{}</plot>", xml_escape(&format.description)),
+            format!(
+                r#"  <uniqueid type="crunchyroll" default="true">{}</uniqueid>"#,
+                xml_escape(&format.episode_id)
+            ),
+            "</episodedetails>".to_string(),
+        ])
+    }
+
+    let path = video_path.with_extension("nfo");
+    fs::write(&path, nfo.join("\n") + "\n")?;
+    Ok(path)
+}
+
+/// Write a plain text `.txt` file next to `video_path` which contains the title, series name, air
+/// date and description of the video.
+pub fn write_description(format: &Format, is_movie: bool, video_path: &Path) -> Result<PathBuf> {
+    let mut lines = vec![format.title.clone()];
+    if !is_movie {
+        lines.extend([
+            format!("Series: {}", format.series_name),
+            format!(
+                "Season {}, Episode {}",
+                format.season_number, format.episode_number
+            ),
+        ])
+    }
+    lines.extend([
+        format!(
+            "Aired: {}-{:0>2}-{:0>2}",
+            format.release_year, format.release_month, format.release_day
+        ),
+        "".to_string(),
+        format.description.clone(),
+    ]);
+
+    let path = video_path.with_extension("txt");
+    fs::write(&path, lines.join("\n") + "\n")?;
+    Ok(path)
+}
+
+/// Write a `series.txt` file into `dir` which contains the title, launch year and description of
+/// the series.
+pub fn write_series_description(series: &Series, dir: &Path) -> Result<PathBuf> {
+    let mut lines = vec![series.title.clone()];
+    if let Some(launch_year) = series.series_launch_year {
+        lines.push(format!("Launched: {}", launch_year))
+    }
+    lines.extend(["".to_string(), series.description.clone()]);
+
+    let path = dir.join("series.txt");
+    fs::write(&path, lines.join("\n") + "\n")?;
+    Ok(path)
+}
+
+/// Information about the streams of a video which is not part of [`Format`]. Must be created before
+/// the [`DownloadFormat`]s are passed to the downloader.
+#[derive(Clone, Serialize)]
+pub struct StreamMetadata {
+    duration: f64,
+    skip_events: Vec<SkipEventMetadata>,
+}
+
+#[derive(Clone, Serialize)]
+struct SkipEventMetadata {
+    #[serde(rename = "type")]
+    event_type: String,
+    start: f64,
+    end: f64,
+}
+
+impl StreamMetadata {
+    pub fn new(download_formats: &[DownloadFormat]) -> Self {
+        let duration = download_formats
+            .iter()
+            .map(|f| {
+                f.video
+                    .0
+                    .segments()
+                    .iter()
+                    .map(|s| s.length.as_secs_f64())
+                    .sum::<f64>()
+            })
+            .fold(0.0, f64::max);
+        let skip_events = download_formats
+            .iter()
+            .find_map(|f| f.metadata.skip_events.as_ref())
+            .map_or(vec![], |skip_events| {
+                [
+                    ("recap", &skip_events.recap),
+                    ("intro", &skip_events.intro),
+                    ("credits", &skip_events.credits),
+                    ("preview", &skip_events.preview),
+                ]
+                .into_iter()
+                .filter_map(|(event_type, event)| {
+                    event.as_ref().map(|e| SkipEventMetadata {
+                        event_type: event_type.to_string(),
+                        start: e.start,
+                        end: e.end,
+                    })
+                })
+                .collect()
+            });
+
+        Self {
+            duration,
+            skip_events,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct MetadataJson<'a> {
+    id: &'a str,
+    #[serde(rename = "type")]
+    source_type: &'a str,
+    title: &'a str,
+    description: &'a str,
+    series_id: &'a str,
+    series_name: &'a str,
+    season_id: &'a str,
+    season_title: &'a str,
+    season_number: u32,
+    episode_number: &'a str,
+    sequence_number: f32,
+    release_date: String,
+    audio_locales: Vec<String>,
+    subtitle_locales: Vec<String>,
+    width: u64,
+    height: u64,
+    fps: f64,
+    #[serde(flatten)]
+    stream_metadata: &'a StreamMetadata,
+}
+
+/// Write a `.json` file with all resolved metadata next to `video_path`. The file has the same name
+/// as the video.
+pub fn write_metadata_json(
+    format: &Format,
+    is_movie: bool,
+    stream_metadata: &StreamMetadata,
+    video_path: &Path,
+) -> Result<PathBuf> {
+    let mut subtitle_locales = vec![];
+    for (_, subtitles) in &format.locales {
+        for subtitle in subtitles {
+            if !subtitle_locales.contains(&subtitle.to_string()) {
+                subtitle_locales.push(subtitle.to_string())
+            }
+        }
+    }
+
+    let metadata = MetadataJson {
+        id: &format.episode_id,
+        source_type: if is_movie { "movie" } else { "episode" },
+        title: &format.title,
+        description: &format.description,
+        series_id: &format.series_id,
+        series_name: &format.series_name,
+        season_id: &format.season_id,
+        season_title: &format.season_title,
+        season_number: format.season_number,
+        episode_number: &format.episode_number,
+        sequence_number: format.sequence_number,
+        release_date: format!(
+            "{}-{:0>2}-{:0>2}",
+            format.release_year, format.release_month, format.release_day
+        ),
+        audio_locales: format.locales.iter().map(|(a, _)| a.to_string()).collect(),
+        subtitle_locales,
+        width: format.width,
+        height: format.height,
+        fps: format.fps,
+        stream_metadata,
+    };
+
+    let path = video_path.with_extension("json");
+    fs::write(&path, serde_json::to_string_pretty(&metadata)? + "\n")?;
+    Ok(path)
+}
+
+/// A `.m3u` playlist to which videos are appended as soon as they're downloaded.
+#[derive(Clone)]
+pub struct Playlist {
+    path: PathBuf,
+    relative: bool,
+}
+
+impl Playlist {
+    /// Open the playlist at `path`. If it doesn't exist yet, it is created with the `#EXTM3U`
+    /// header, otherwise new entries are appended to the existing ones. If `relative` is set, the
+    /// video paths are written relative to the directory of the playlist.
+    pub fn open(path: PathBuf, relative: bool) -> Result<Self> {
+        let path = absolute_path(&path)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        if file.metadata()?.len() == 0 {
+            file.write_all(b"#EXTM3U\n")?;
+            file.flush()?
+        }
+        Ok(Self { path, relative })
+    }
+
+    /// Append `video_path` with its duration and `title` to the playlist. The playlist file is
+    /// flushed after every entry, so an interrupted archive still leaves a valid playlist with all
+    /// videos which were completed until then.
+    pub fn append(&self, title: &str, video_path: &Path) -> Result<()> {
+        let (length, _) = get_video_stats(video_path)?;
+
+        let video_path = absolute_path(video_path)?;
+        let entry_path = if self.relative {
+            relative_path(self.path.parent().unwrap_or(Path::new("")), &video_path)
+        } else {
+            video_path
+        };
+
+        let mut file = OpenOptions::new().append(true).open(&self.path)?;
+        // the entry is written at once, so that parallel downloads cannot interleave their entries
+        file.write_all(
+            format!(
+                "#EXTINF:{},{}\n{}\n",
+                length.num_seconds(),
+                title.replace(['\r', '\n'], " "),
+                entry_path.to_string_lossy()
+            )
+            .as_bytes(),
+        )?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+pub fn absolute_path(path: &Path) -> Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(path.to_path_buf())
+    } else {
+        Ok(env::current_dir()?.join(path))
+    }
+}
+
+/// Get `path` relative to `base`. Both paths must be absolute. If they don't share any common
+/// prefix (e.g. different drives on Windows), `path` is returned unchanged.
+fn relative_path(base: &Path, path: &Path) -> PathBuf {
+    let base: Vec<Component> = base
+        .components()
+        .filter(|c| c != &Component::CurDir)
+        .collect();
+    let path_components: Vec<Component> = path
+        .components()
+        .filter(|c| c != &Component::CurDir)
+        .collect();
+
+    let common = base
+        .iter()
+        .zip(&path_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        return path.to_path_buf();
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in common..base.len() {
+        relative.push("..")
+    }
+    for component in &path_components[common..] {
+        relative.push(component)
+    }
+    relative
+}
+
+fn xml_escape<S: AsRef<str>>(s: S) -> String {
+    s.as_ref()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -1,10 +1,19 @@
 use anyhow::{bail, Result};
 use crunchyroll_rs::media::{Resolution, Stream, StreamData};
 use crunchyroll_rs::Locale;
+use log::warn;
+
+/// Resolution which always selects the highest available resolution, equivalent to
+/// `--resolution best`.
+pub const BEST_RESOLUTION: Resolution = Resolution {
+    width: u64::MAX,
+    height: u64::MAX,
+};
 
 pub async fn stream_data_from_stream(
     stream: &Stream,
     resolution: &Resolution,
+    min_bitrate: Option<u64>,
     hardsub_subtitle: Option<Locale>,
 ) -> Result<Option<(StreamData, StreamData, bool)>> {
     let (hardsub_locale, mut contains_hardsub) = if hardsub_subtitle.is_some() {
@@ -31,16 +40,122 @@ pub async fn stream_data_from_stream(
     if videos.iter().any(|v| v.drm.is_some()) || audios.iter().any(|v| v.drm.is_some()) {
         bail!("Stream is DRM protected")
     }
+    // live streams have no (or only zero-length) segments as their end isn't known yet
+    if videos.is_empty()
+        || audios.is_empty()
+        || videos[0].segments().iter().all(|s| s.length.is_zero())
+    {
+        bail!("Stream has no duration, live content is not supported")
+    }
 
-    videos.sort_by(|a, b| a.bandwidth.cmp(&b.bandwidth).reverse());
-    audios.sort_by(|a, b| a.bandwidth.cmp(&b.bandwidth).reverse());
+    sort_stream_data(&mut videos, &mut audios);
+
+    if let Some(min_bitrate) = min_bitrate {
+        videos.retain(|v| v.bandwidth >= min_bitrate);
+        if videos.is_empty() {
+            bail!(
+                "No video stream with a bitrate of at least {} kbps is available",
+                min_bitrate / 1000
+            )
+        }
+    }
 
     let video_variant = match resolution.height {
         u64::MAX => Some(videos.into_iter().next().unwrap()),
         u64::MIN => Some(videos.into_iter().last().unwrap()),
-        _ => videos
-            .into_iter()
-            .find(|v| resolution.height == v.resolution().unwrap().height),
+        _ => {
+            if let Some(video) = videos
+                .iter()
+                .find(|v| resolution.height == v.resolution().unwrap().height)
+            {
+                Some(video.clone())
+            } else {
+                // the videos are sorted by bandwidth, so the first one which is lower than the
+                // requested resolution is the nearest lower one
+                let fallback = videos
+                    .into_iter()
+                    .find(|v| v.resolution().unwrap().height < resolution.height);
+                if let Some(fallback) = &fallback {
+                    warn!(
+                        "Resolution {} is not available, falling back to {}",
+                        resolution,
+                        fallback.resolution().unwrap()
+                    )
+                }
+                fallback
+            }
+        }
     };
     Ok(video_variant.map(|v| (v, audios.first().unwrap().clone(), contains_hardsub)))
 }
+
+/// Sorts videos and audios by their bandwidth, the best stream comes first.
+fn sort_stream_data(videos: &mut [StreamData], audios: &mut [StreamData]) {
+    videos.sort_by(|a, b| a.bandwidth.cmp(&b.bandwidth).reverse());
+    audios.sort_by(|a, b| a.bandwidth.cmp(&b.bandwidth).reverse());
+}
+
+/// Prints all video and audio variants of a stream as table. The variants are ordered the same way
+/// as they are when choosing a variant to download.
+pub async fn print_stream_variants(stream: &Stream, audio_locale: &Locale) -> Result<()> {
+    let Some((mut videos, mut audios)) = stream.stream_data(None).await? else {
+        bail!("No stream data available")
+    };
+    sort_stream_data(&mut videos, &mut audios);
+
+    let mut rows = vec![[
+        "ID".to_string(),
+        "TYPE".to_string(),
+        "RESOLUTION".to_string(),
+        "FPS".to_string(),
+        "BANDWIDTH".to_string(),
+        "CODEC".to_string(),
+        "AUDIO".to_string(),
+    ]];
+    for (i, video) in videos.iter().enumerate() {
+        rows.push([
+            format!("v{}", i + 1),
+            "video".to_string(),
+            video
+                .resolution()
+                .map_or("-".to_string(), |r| r.to_string()),
+            video
+                .fps()
+                .map_or("-".to_string(), |fps| format!("{:.3}", fps)),
+            format!("{} kbps", video.bandwidth / 1000),
+            video.codecs.clone(),
+            "-".to_string(),
+        ])
+    }
+    for (i, audio) in audios.iter().enumerate() {
+        rows.push([
+            format!("a{}", i + 1),
+            "audio".to_string(),
+            "-".to_string(),
+            "-".to_string(),
+            format!("{} kbps", audio.bandwidth / 1000),
+            audio.codecs.clone(),
+            audio_locale.to_string(),
+        ])
+    }
+
+    let mut widths = [0; 7];
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.len())
+        }
+    }
+    for row in rows {
+        println!(
+            "  {}",
+            row.iter()
+                .zip(widths)
+                .map(|(column, width)| format!("{:<1$}", column, width))
+                .collect::<Vec<String>>()
+                .join("  ")
+                .trim_end()
+        )
+    }
+
+    Ok(())
+}